@@ -7,4 +7,13 @@ class Note {
     author: string;
     source: string;
     pub_date: number;
+    html: string;
+    outline: Heading[];
+}
+
+class Heading {
+    level: number;
+    title: string;
+    slug: string;
+    children: Heading[];
 }
\ No newline at end of file
@@ -13,7 +13,7 @@ export const load = (async ({ fetch }) : Promise<Values<Note>> => {
             }
         ),
         await fetch (
-            PUBLIC_BACKEND_URL + "/data/notes/get", 
+            PUBLIC_BACKEND_URL + "/data/notes/get?fields=title,author,pub_date&order_by=title", 
             {
                 method: "GET",
                 mode: "cors",
@@ -22,7 +22,7 @@ export const load = (async ({ fetch }) : Promise<Values<Note>> => {
         )
     ])
 
-    let [role, notes] = await Promise.all([
+    let [role, page] = await Promise.all([
         res.json(),
         data.json(),
     ]);
@@ -31,6 +31,6 @@ export const load = (async ({ fetch }) : Promise<Values<Note>> => {
         throw redirect(307, "/account/login")
     }
 
-    return { "values": notes };
+    return { "values": page.notes };
 }) satisfies PageLoad;
 
@@ -4,7 +4,7 @@ import type { PageLoad } from './$types';
 
 export const load = (async ({ fetch, params}) : Promise<Note> => {
     const res = await fetch(
-        PUBLIC_BACKEND_URL + "/data/notes/get/" + params.title, 
+        PUBLIC_BACKEND_URL + "/data/notes/source/" + params.title, 
         {
             method: "GET",
             mode: "cors",
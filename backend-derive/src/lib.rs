@@ -10,7 +10,13 @@ extern crate quote;
 use syn::Ident; 
 use syn::Ty;
 
-#[proc_macro_derive(Table)]
+/// # Usage
+/// Implements the `Table` trait for a struct whose fields
+/// are the columns of `<Struct>Table`.
+///
+/// Columns computed from the values being set can be given with
+/// `#[table(derived = "path::to::function")]`, see `Table::derived`.
+#[proc_macro_derive(Table, attributes(table))]
 pub fn table(input: TokenStream) -> TokenStream {
     let s = input.to_string();
     let ast = syn::parse_derive_input(&s).unwrap();
@@ -22,6 +28,17 @@ fn impl_table(ast: &syn::DeriveInput) -> quote::Tokens {
     let struct_name = &ast.ident;
     let global_name = Ident::new(format!("_{}_FIELDS", struct_name.to_string().to_ascii_uppercase()));
     let table_name = format!("{}Table", struct_name);
+    let derived = derived_columns(&ast.attrs).map(|path| {
+        let path = Ident::new(path);
+        quote! {
+            fn derived(
+                set: &std::collections::HashMap<String, serde_json::Value>,
+                library: &crate::md::Library,
+            ) -> anyhow::Result<Vec<(&'static str, String)>> {
+                #path(set, library)
+            }
+        }
+    });
 
     if let syn::Body::Struct(body) = &ast.body {
         let fields = body.fields();
@@ -79,9 +96,35 @@ fn impl_table(ast: &syn::DeriveInput) -> quote::Tokens {
                     fn name() -> &'static str {
                         return #table_name;
                     }
+
+                    #derived
                 }
             }
     } else {
        panic!("#[derive(Table)] is only defined for structs, not for enums!");
     }
 }
+
+/// # Usage
+/// Finds the function given by `#[table(derived = "...")]`.
+fn derived_columns(attrs: &[syn::Attribute]) -> Option<String> {
+    for attr in attrs {
+        if let syn::MetaItem::List(name, items) = &attr.value {
+            if name != "table" {
+                continue;
+            }
+
+            for item in items {
+                match item {
+                    syn::NestedMetaItem::MetaItem(syn::MetaItem::NameValue(
+                        key,
+                        syn::Lit::Str(path, _),
+                    )) if key == "derived" => return Some(path.clone()),
+                    _ => panic!("Expected #[table(derived = \"path::to::function\")]"),
+                }
+            }
+        }
+    }
+
+    return None;
+}
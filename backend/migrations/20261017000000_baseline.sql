@@ -0,0 +1,17 @@
+-- The tables which existed before migrations were kept, so that
+-- a fresh database can be set up. Databases which already have
+-- them are left as they are.
+CREATE TABLE IF NOT EXISTS NoteTable (
+    title TEXT PRIMARY KEY NOT NULL,
+    author TEXT NOT NULL,
+    source TEXT NOT NULL,
+    pub_date INTEGER NOT NULL
+);
+
+-- Roles are 0 for users, 1 for admins and 2 for owners.
+CREATE TABLE IF NOT EXISTS UserTable (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    role INTEGER NOT NULL
+);
@@ -0,0 +1,4 @@
+-- The compiled HTML of each note, along with a hash of the source it
+-- was compiled from. Notes with an empty hash are compiled when read.
+ALTER TABLE NoteTable ADD COLUMN html TEXT NOT NULL DEFAULT '';
+ALTER TABLE NoteTable ADD COLUMN source_hash TEXT NOT NULL DEFAULT '';
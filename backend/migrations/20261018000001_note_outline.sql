@@ -0,0 +1,3 @@
+-- The outline of each note's headings, as JSON. It is cached along
+-- with the HTML and so shares its source_hash.
+ALTER TABLE NoteTable ADD COLUMN outline TEXT NOT NULL DEFAULT '[]';
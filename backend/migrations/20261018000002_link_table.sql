@@ -0,0 +1,8 @@
+-- Links between notes, by title, as found in their sources. Targets
+-- are written as in the link, so may not match a note exactly.
+CREATE TABLE IF NOT EXISTS LinkTable (
+    source TEXT NOT NULL,
+    target TEXT NOT NULL COLLATE NOCASE,
+    PRIMARY KEY (source, target)
+);
+CREATE INDEX IF NOT EXISTS LinkTargetIndex ON LinkTable (target);
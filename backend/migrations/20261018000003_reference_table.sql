@@ -0,0 +1,10 @@
+-- The works notes can cite, as in `[@stewart, p. 92]`. Keys are
+-- matched ignoring case, as the compiler does.
+CREATE TABLE IF NOT EXISTS ReferenceTable (
+    key TEXT PRIMARY KEY NOT NULL COLLATE NOCASE,
+    author TEXT NOT NULL DEFAULT '',
+    title TEXT NOT NULL DEFAULT '',
+    year INTEGER NOT NULL DEFAULT 0,
+    publisher TEXT NOT NULL DEFAULT '',
+    url TEXT NOT NULL DEFAULT ''
+);
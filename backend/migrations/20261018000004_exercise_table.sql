@@ -0,0 +1,10 @@
+-- The hidden answers of the exercises in each note, written
+-- whenever the note is compiled. Ids are only unique within
+-- a note, and notes are matched ignoring case.
+CREATE TABLE IF NOT EXISTS ExerciseTable (
+    note TEXT NOT NULL COLLATE NOCASE,
+    id TEXT NOT NULL,
+    expected TEXT NOT NULL,
+    tolerance REAL NOT NULL,
+    PRIMARY KEY (note, id)
+);
@@ -0,0 +1,14 @@
+-- How far each user has read each note. Times are in seconds since
+-- the epoch, with 0 for never; scroll is the furthest fraction of
+-- the note scrolled through, and section the last heading reached.
+CREATE TABLE IF NOT EXISTS ProgressTable (
+    user_id INTEGER NOT NULL,
+    note TEXT NOT NULL COLLATE NOCASE,
+    section TEXT NOT NULL DEFAULT '',
+    scroll REAL NOT NULL DEFAULT 0,
+    completed INTEGER NOT NULL DEFAULT 0,
+    opened_at INTEGER NOT NULL DEFAULT 0,
+    updated_at INTEGER NOT NULL DEFAULT 0,
+    completed_at INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, note)
+);
@@ -0,0 +1,20 @@
+-- The section each exercise is under, so that answering it
+-- counts as reviewing that section.
+ALTER TABLE ExerciseTable ADD COLUMN section TEXT NOT NULL DEFAULT '';
+
+-- Sections of notes each user reviews, scheduled by SM-2. Times
+-- are in seconds since the epoch and intervals in days. Cards of
+-- notes without headings have an empty section.
+CREATE TABLE IF NOT EXISTS CardTable (
+    user_id INTEGER NOT NULL,
+    note TEXT NOT NULL COLLATE NOCASE,
+    section TEXT NOT NULL,
+    title TEXT NOT NULL,
+    ease REAL NOT NULL DEFAULT 2.5,
+    interval INTEGER NOT NULL DEFAULT 0,
+    repetitions INTEGER NOT NULL DEFAULT 0,
+    due INTEGER NOT NULL DEFAULT 0,
+    reviewed_at INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, note, section)
+);
+CREATE INDEX IF NOT EXISTS CardDueIndex ON CardTable (user_id, due);
@@ -0,0 +1,11 @@
+-- The definitions and theorems of each note as flashcards,
+-- written whenever the note is compiled. Fronts and backs
+-- are HTML, and labels are empty for untitled elements.
+CREATE TABLE IF NOT EXISTS FlashcardTable (
+    note TEXT NOT NULL COLLATE NOCASE,
+    position INTEGER NOT NULL,
+    label TEXT NOT NULL,
+    front TEXT NOT NULL,
+    back TEXT NOT NULL,
+    PRIMARY KEY (note, position)
+);
@@ -67,7 +67,7 @@ impl User {
         ",
         )
         .bind(&name)
-        .bind(&parsed_hash.to_string())
+        .bind(parsed_hash.to_string())
         .bind(role.as_u32())
         .execute(&mut conn)
         .await
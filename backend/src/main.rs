@@ -18,6 +18,9 @@ use tower_http::cors::CorsLayer;
 pub mod model;
 pub mod web;
 pub mod auth;
+pub mod md;
+pub mod math;
+pub mod review;
 
 use crate::auth::{Role, User};
 use crate::model::ModelController;
@@ -0,0 +1,111 @@
+//! # Usage
+//! Symbolic differentiation, by the sum, product, quotient
+//! and chain rules along with the derivatives of each [Func].
+
+use super::{Expr, Func};
+
+fn b(x: Expr) -> Box<Expr> {
+    return Box::new(x);
+}
+
+impl Expr {
+    /// # Usage
+    /// Whether the expression depends on `var`.
+    pub fn depends(&self, var: char) -> bool {
+        return self.variables().contains(&var);
+    }
+
+    /// # Usage
+    /// Returns the derivative with respect to `var`, simplified.
+    pub fn derivative(&self, var: char) -> Expr {
+        return self.derive(var).simplify();
+    }
+
+    fn derive(&self, var: char) -> Expr {
+        use Expr::*;
+
+        if !self.depends(var) {
+            return Num(0.0);
+        }
+
+        return match self {
+            Num(_) | Const(_) => Num(0.0),
+            Var(_) => Num(1.0),
+            Neg(u) => Neg(b(u.derive(var))),
+            Add(u, v) => Add(b(u.derive(var)), b(v.derive(var))),
+            Sub(u, v) => Sub(b(u.derive(var)), b(v.derive(var))),
+            Mul(u, v) => Add(
+                b(Mul(b(u.derive(var)), v.clone())),
+                b(Mul(u.clone(), b(v.derive(var)))),
+            ),
+            Div(u, v) => Div(
+                b(Sub(
+                    b(Mul(b(u.derive(var)), v.clone())),
+                    b(Mul(u.clone(), b(v.derive(var)))),
+                )),
+                b(Pow(v.clone(), b(Num(2.0)))),
+            ),
+            // x^n
+            Pow(u, n) if !n.depends(var) => Mul(
+                b(Mul(
+                    n.clone(),
+                    b(Pow(u.clone(), b(Sub(n.clone(), b(Num(1.0)))))),
+                )),
+                b(u.derive(var)),
+            ),
+            // e^v
+            Pow(u, v) if matches!(**u, Const(super::Const::E)) => {
+                Mul(b(self.clone()), b(v.derive(var)))
+            }
+            // a^v
+            Pow(u, v) if !u.depends(var) => Mul(
+                b(Mul(b(self.clone()), b(Call(Func::Ln, u.clone())))),
+                b(v.derive(var)),
+            ),
+            // u^v, as e^(v ln(u))
+            Pow(u, v) => Mul(
+                b(self.clone()),
+                b(Add(
+                    b(Mul(b(v.derive(var)), b(Call(Func::Ln, u.clone())))),
+                    b(Div(b(Mul(v.clone(), b(u.derive(var)))), u.clone())),
+                )),
+            ),
+            Call(func, u) => Mul(b(outer(*func, u)), b(u.derive(var))),
+        };
+    }
+}
+
+/// # Usage
+/// The derivative of `func` at `u`, to be multiplied
+/// by the derivative of `u` by the chain rule.
+fn outer(func: Func, u: &Expr) -> Expr {
+    use Expr::*;
+
+    let u = || b(u.clone());
+    let call = |func: Func| b(Call(func, u()));
+    let square = |x: Box<Expr>| b(Pow(x, b(Num(2.0))));
+    let one = || b(Num(1.0));
+
+    return match func {
+        Func::Sin => Call(Func::Cos, u()),
+        Func::Cos => Neg(call(Func::Sin)),
+        Func::Tan => Pow(call(Func::Sec), b(Num(2.0))),
+        Func::Sec => Mul(call(Func::Sec), call(Func::Tan)),
+        Func::Csc => Neg(b(Mul(call(Func::Csc), call(Func::Cot)))),
+        Func::Cot => Neg(square(call(Func::Csc))),
+        Func::Asin => Div(one(), b(Call(Func::Sqrt, b(Sub(one(), square(u())))))),
+        Func::Acos => Neg(b(Div(
+            one(),
+            b(Call(Func::Sqrt, b(Sub(one(), square(u()))))),
+        ))),
+        Func::Atan => Div(one(), b(Add(one(), square(u())))),
+        Func::Sinh => Call(Func::Cosh, u()),
+        Func::Cosh => Call(Func::Sinh, u()),
+        Func::Tanh => Div(one(), square(call(Func::Cosh))),
+        Func::Exp => Call(Func::Exp, u()),
+        Func::Ln => Div(one(), u()),
+        Func::Log => Div(one(), b(Mul(u(), b(Call(Func::Ln, b(Num(10.0))))))),
+        Func::Sqrt => Div(one(), b(Mul(b(Num(2.0)), call(Func::Sqrt)))),
+        Func::Abs => Div(u(), call(Func::Abs)),
+    };
+}
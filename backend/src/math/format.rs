@@ -0,0 +1,105 @@
+//! # Usage
+//! Writes expressions back out, either as text in the
+//! syntax accepted by the [parser](super::parser) or as
+//! LaTeX for the markdown compiler.
+
+use super::{Const, Expr, Func};
+use std::fmt;
+
+impl Expr {
+    /// # Usage
+    /// How tightly the expression binds, used to decide
+    /// where parentheses are needed.
+    fn precedence(&self) -> u8 {
+        return match self {
+            Expr::Add(..) | Expr::Sub(..) => 1,
+            Expr::Mul(..) | Expr::Div(..) => 2,
+            Expr::Neg(_) => 3,
+            Expr::Num(x) if *x < 0.0 => 3,
+            Expr::Pow(..) => 4,
+            _ => 5,
+        };
+    }
+
+    /// # Usage
+    /// Whether a product with this as its right factor can be
+    /// written without a sign, as in `2x` or `x sin(x)`.
+    fn juxtaposes(&self) -> bool {
+        return match self {
+            Expr::Var(_) | Expr::Const(_) | Expr::Call(..) => true,
+            Expr::Pow(base, _) => base.juxtaposes(),
+            Expr::Mul(a, _) => a.juxtaposes(),
+            _ => false,
+        };
+    }
+
+    /// # Usage
+    /// Writes the expression as LaTeX.
+    pub fn to_tex(&self) -> String {
+        let wrap = |x: &Expr, min: u8| match x.precedence() < min {
+            true => format!("\\left({}\\right)", x.to_tex()),
+            false => x.to_tex(),
+        };
+
+        return match self {
+            Expr::Num(x) => x.to_string(),
+            Expr::Const(Const::Pi) => "\\pi".to_string(),
+            Expr::Const(Const::E) => "e".to_string(),
+            Expr::Var(x) => x.to_string(),
+            Expr::Neg(a) => format!("-{}", wrap(a, 2)),
+            Expr::Add(a, b) => format!("{} + {}", a.to_tex(), wrap(b, 2)),
+            Expr::Sub(a, b) => format!("{} - {}", a.to_tex(), wrap(b, 2)),
+            Expr::Mul(a, b) if b.juxtaposes() && !matches!(**a, Expr::Neg(_)) => {
+                format!("{} {}", wrap(a, 2), wrap(b, 2))
+            }
+            Expr::Mul(a, b) => format!("{} \\cdot {}", wrap(a, 2), wrap(b, 3)),
+            Expr::Div(a, b) => format!("\\frac{{{}}}{{{}}}", a.to_tex(), b.to_tex()),
+            // `e^{x}^{2}` would be a double superscript
+            Expr::Pow(a, b) if matches!(**a, Expr::Call(Func::Exp, _)) => {
+                format!("\\left({}\\right)^{{{}}}", a.to_tex(), b.to_tex())
+            }
+            Expr::Pow(a, b) => format!("{}^{{{}}}", wrap(a, 5), b.to_tex()),
+            Expr::Call(Func::Sqrt, a) => format!("\\sqrt{{{}}}", a.to_tex()),
+            Expr::Call(Func::Abs, a) => format!("\\left|{}\\right|", a.to_tex()),
+            Expr::Call(Func::Exp, a) => format!("e^{{{}}}", a.to_tex()),
+            Expr::Call(func, a) => {
+                let name = match func {
+                    Func::Asin => "arcsin",
+                    Func::Acos => "arccos",
+                    Func::Atan => "arctan",
+                    func => func.name(),
+                };
+                format!("\\{}\\left({}\\right)", name, a.to_tex())
+            }
+        };
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let wrap = |x: &Expr, min: u8| match x.precedence() < min {
+            true => format!("({})", x),
+            false => x.to_string(),
+        };
+
+        return match self {
+            Expr::Num(x) => write!(f, "{}", x),
+            Expr::Const(Const::Pi) => write!(f, "pi"),
+            Expr::Const(Const::E) => write!(f, "e"),
+            Expr::Var(x) => write!(f, "{}", x),
+            Expr::Neg(a) => write!(f, "-{}", wrap(a, 2)),
+            Expr::Add(a, b) => write!(f, "{} + {}", a, wrap(b, 2)),
+            Expr::Sub(a, b) => write!(f, "{} - {}", a, wrap(b, 2)),
+            Expr::Mul(a, b) if matches!(**a, Expr::Num(_)) && b.juxtaposes() => {
+                write!(f, "{}{}", wrap(a, 2), wrap(b, 2))
+            }
+            Expr::Mul(a, b) if b.juxtaposes() && !matches!(**a, Expr::Neg(_)) => {
+                write!(f, "{} {}", wrap(a, 2), wrap(b, 2))
+            }
+            Expr::Mul(a, b) => write!(f, "{} * {}", wrap(a, 2), wrap(b, 3)),
+            Expr::Div(a, b) => write!(f, "{} / {}", wrap(a, 2), wrap(b, 3)),
+            Expr::Pow(a, b) => write!(f, "{}^{}", wrap(a, 5), wrap(b, 3)),
+            Expr::Call(func, a) => write!(f, "{}({})", func.name(), a),
+        };
+    }
+}
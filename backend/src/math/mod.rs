@@ -0,0 +1,349 @@
+//! # Usage
+//! Mathematical expressions in one or more variables, as
+//! written by authors in macros such as `@plot(f="x^2 - 1")`.
+//!
+//! The [parser] accepts the usual infix notation: `+ - * / ^`,
+//! implied multiplication (`2x`, `3(x+1)`, `x sin(x)`), absolute
+//! values `|x|`, the constants `pi` and `e`, and the functions in
+//! [Func]. Variables are single letters.
+//!
+//! Expressions can be [simplified](Expr::simplify),
+//! [differentiated](Expr::derivative), compared by
+//! [value](Expr::equivalent) and written back out as text or
+//! [LaTeX](Expr::to_tex).
+
+mod deriv;
+mod format;
+mod parser;
+mod simplify;
+
+pub use parser::parse;
+use std::{collections::HashMap, f64::consts};
+
+/// # Usage
+/// A position in the parsed text and a message.
+pub type Error = (usize, String);
+
+/// Points at which [Expr::equivalent] compares expressions. They
+/// avoid small integers, where different functions often agree.
+const SAMPLES: [f64; 12] = [
+    0.37, -1.21, 2.63, -0.58, 1.49, -2.17, 0.83, 3.11, -2.94, 1.97, -0.29, 2.29,
+];
+
+/// # Usage
+/// A named constant.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Const {
+    Pi,
+    E,
+}
+
+impl Const {
+    pub fn value(&self) -> f64 {
+        return match self {
+            Const::Pi => consts::PI,
+            Const::E => consts::E,
+        };
+    }
+}
+
+/// # Usage
+/// A function of one argument.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Func {
+    Sin,
+    Cos,
+    Tan,
+    Sec,
+    Csc,
+    Cot,
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    /// The natural logarithm.
+    Ln,
+    /// The base 10 logarithm.
+    Log,
+    Sqrt,
+    Abs,
+}
+
+/// Every function along with the name it is written as.
+const FUNCS: [(&str, Func); 17] = [
+    ("sin", Func::Sin),
+    ("cos", Func::Cos),
+    ("tan", Func::Tan),
+    ("sec", Func::Sec),
+    ("csc", Func::Csc),
+    ("cot", Func::Cot),
+    ("asin", Func::Asin),
+    ("acos", Func::Acos),
+    ("atan", Func::Atan),
+    ("sinh", Func::Sinh),
+    ("cosh", Func::Cosh),
+    ("tanh", Func::Tanh),
+    ("exp", Func::Exp),
+    ("ln", Func::Ln),
+    ("log", Func::Log),
+    ("sqrt", Func::Sqrt),
+    ("abs", Func::Abs),
+];
+
+impl Func {
+    pub fn parse(name: &str) -> Option<Self> {
+        return FUNCS.iter().find(|x| x.0 == name).map(|x| x.1);
+    }
+
+    pub fn name(&self) -> &'static str {
+        return FUNCS.iter().find(|x| x.1 == *self).unwrap().0;
+    }
+
+    pub fn apply(&self, x: f64) -> f64 {
+        return match self {
+            Func::Sin => x.sin(),
+            Func::Cos => x.cos(),
+            Func::Tan => x.tan(),
+            Func::Sec => 1.0 / x.cos(),
+            Func::Csc => 1.0 / x.sin(),
+            Func::Cot => 1.0 / x.tan(),
+            Func::Asin => x.asin(),
+            Func::Acos => x.acos(),
+            Func::Atan => x.atan(),
+            Func::Sinh => x.sinh(),
+            Func::Cosh => x.cosh(),
+            Func::Tanh => x.tanh(),
+            Func::Exp => x.exp(),
+            Func::Ln => x.ln(),
+            Func::Log => x.log10(),
+            Func::Sqrt => x.sqrt(),
+            Func::Abs => x.abs(),
+        };
+    }
+}
+
+/// # Usage
+/// The syntax tree of an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    Const(Const),
+    Var(char),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Pow(Box<Expr>, Box<Expr>),
+    Call(Func, Box<Expr>),
+}
+
+impl Expr {
+    /// # Usage
+    /// Evaluates the expression with the given values for its
+    /// variables. Fails if a variable has no value; results
+    /// outside of a function's domain are `NaN`.
+    pub fn eval(&self, vars: &HashMap<char, f64>) -> Result<f64, String> {
+        return Ok(match self {
+            Expr::Num(x) => *x,
+            Expr::Const(c) => c.value(),
+            Expr::Var(name) => *vars.get(name).ok_or(format!("`{}` has no value", name))?,
+            Expr::Neg(a) => -a.eval(vars)?,
+            Expr::Add(a, b) => a.eval(vars)? + b.eval(vars)?,
+            Expr::Sub(a, b) => a.eval(vars)? - b.eval(vars)?,
+            Expr::Mul(a, b) => a.eval(vars)? * b.eval(vars)?,
+            Expr::Div(a, b) => a.eval(vars)? / b.eval(vars)?,
+            Expr::Pow(a, b) => a.eval(vars)?.powf(b.eval(vars)?),
+            Expr::Call(func, a) => func.apply(a.eval(vars)?),
+        });
+    }
+
+    /// # Usage
+    /// Evaluates an expression in the single variable `x`.
+    pub fn at(&self, x: f64) -> Result<f64, String> {
+        return self.eval(&HashMap::from([('x', x)]));
+    }
+
+    /// # Usage
+    /// The variables appearing in the expression, sorted.
+    pub fn variables(&self) -> Vec<char> {
+        let mut out = Vec::new();
+        self.collect(&mut out);
+        out.sort();
+        out.dedup();
+        return out;
+    }
+
+    /// # Usage
+    /// Whether two expressions have the same value, judged by
+    /// comparing them at a number of points with a relative
+    /// tolerance. Points where either is undefined are skipped,
+    /// but most must be defined for the two to be equivalent.
+    pub fn equivalent(&self, other: &Expr) -> bool {
+        return self.within(other, 1e-6);
+    }
+
+    /// # Usage
+    /// Whether two expressions agree to within `tolerance` as in
+    /// [Expr::equivalent]. The tolerance is relative for values
+    /// larger than one and absolute for smaller ones, so `0.01`
+    /// accepts `3.14` for `pi`.
+    pub fn within(&self, other: &Expr, tolerance: f64) -> bool {
+        let mut vars = self.variables();
+        vars.extend(other.variables());
+        vars.sort();
+        vars.dedup();
+
+        let mut compared = 0;
+        for i in 0..SAMPLES.len() {
+            // Give each variable a different point
+            let values = vars
+                .iter()
+                .enumerate()
+                .map(|(j, var)| (*var, SAMPLES[(i + 5 * j) % SAMPLES.len()]))
+                .collect::<HashMap<char, f64>>();
+            let (a, b) = match (self.eval(&values), other.eval(&values)) {
+                (Ok(a), Ok(b)) if a.is_finite() && b.is_finite() => (a, b),
+                _ => continue,
+            };
+
+            if (a - b).abs() > tolerance * a.abs().max(b.abs()).max(1.0) {
+                return false;
+            }
+            compared += 1;
+        }
+
+        return compared >= SAMPLES.len() / 2;
+    }
+
+    fn collect(&self, out: &mut Vec<char>) {
+        match self {
+            Expr::Num(_) | Expr::Const(_) => {}
+            Expr::Var(name) => out.push(*name),
+            Expr::Neg(a) | Expr::Call(_, a) => a.collect(out),
+            Expr::Add(a, b)
+            | Expr::Sub(a, b)
+            | Expr::Mul(a, b)
+            | Expr::Div(a, b)
+            | Expr::Pow(a, b) => {
+                a.collect(out);
+                b.collect(out);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(source: &str, x: f64) -> f64 {
+        return parse(source).unwrap().at(x).unwrap();
+    }
+
+    #[test]
+    fn evaluate() {
+        assert_eq!(at("x^2 - 1", 3.0), 8.0);
+        assert_eq!(at("-x^2", 3.0), -9.0);
+        assert_eq!(at("2^3^2", 0.0), 512.0);
+        assert_eq!(at("2x(x+1)", 2.0), 12.0);
+        assert_eq!(at("6/2/3", 0.0), 1.0);
+        assert_eq!(at("|x - 5| + 2^-1", 1.0), 4.5);
+        assert_eq!(at("x sin(pi/2)", 4.0), 4.0);
+        assert_eq!(at("ln(e^2)", 0.0), 2.0);
+        assert_eq!(at("1.5e2", 0.0), 150.0);
+        assert!(at("sqrt(x)", -1.0).is_nan());
+    }
+
+    #[test]
+    fn simplify() {
+        let simplified = |x: &str| parse(x).unwrap().simplify().to_string();
+        assert_eq!(simplified("0 + 1x^1 * 1"), "x");
+        assert_eq!(simplified("x * 3 * 2 + x"), "7x");
+        assert_eq!(simplified("x x^2 - 0"), "x^3");
+        assert_eq!(simplified("sin(x) * 2 * x"), "2x sin(x)");
+        assert_eq!(simplified("x + -2"), "x - 2");
+        assert_eq!(simplified("(x^2)^0.5"), "(x^2)^0.5");
+        assert_eq!(simplified("1/3 + 6/2"), "1 / 3 + 3");
+    }
+
+    #[test]
+    fn derivative() {
+        let derived = |x: &str| parse(x).unwrap().derivative('x');
+        assert_eq!(derived("x^3 - 2x + 7").to_string(), "3x^2 - 2");
+        assert_eq!(derived("sin(x)*x^2").to_string(), "x^2 cos(x) + 2x sin(x)");
+        assert_eq!(derived("e^(2x)").to_string(), "2e^(2x)");
+        assert_eq!(derived("y^2").to_string(), "0");
+
+        for (f, expected) in [
+            ("ln(x^2 + 1)", "2x / (x^2 + 1)"),
+            ("tan(x)", "1 / cos(x)^2"),
+            ("x^x", "x^x (ln(x) + 1)"),
+            ("atan(x) - acos(x/4)", "1/(1 + x^2) + 1/(4sqrt(1 - x^2/16))"),
+            ("sqrt(x) / x", "-1 / (2x^1.5)"),
+            ("2^x log(x)", "2^x ln(2) log(x) + 2^x / (x ln(10))"),
+        ] {
+            let derivative = derived(f);
+            assert!(
+                derivative.equivalent(&parse(expected).unwrap()),
+                "d/dx {} = {}",
+                f,
+                derivative
+            );
+        }
+    }
+
+    #[test]
+    fn equivalent() {
+        let same = |a: &str, b: &str| parse(a).unwrap().equivalent(&parse(b).unwrap());
+        assert!(same("(x + 1)^2", "x^2 + 2x + 1"));
+        assert!(same("sin(x)^2 + cos(x)^2", "1"));
+        assert!(same("x y", "y x"));
+        assert!(!same("x^2", "x^3"));
+        assert!(!same("sqrt(x)", "sqrt(-x)"));
+        assert!(!same("x", "y"));
+
+        let pi = parse("pi").unwrap();
+        assert!(parse("3.14").unwrap().within(&pi, 0.01));
+        assert!(!parse("3.14").unwrap().within(&pi, 0.0001));
+    }
+
+    #[test]
+    fn tex() {
+        let tex = |x: &str| parse(x).unwrap().to_tex();
+        assert_eq!(tex("2x^2 sin(x)"), "2 x^{2} \\sin\\left(x\\right)");
+        assert_eq!(tex("(x+1)/sqrt(x)"), "\\frac{x + 1}{\\sqrt{x}}");
+        assert_eq!(tex("-(x - 1) * 3"), "-\\left(x - 1\\right) \\cdot 3");
+        assert_eq!(tex("exp(x) + pi"), "e^{x} + \\pi");
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(parse("t^2 + x y").unwrap().variables(), vec!['t', 'x', 'y']);
+        assert!(parse("y").unwrap().at(1.0).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse("x +").unwrap_err(),
+            (3, "Expected a value".to_string())
+        );
+        assert_eq!(parse("(x").unwrap_err().1, "Expected `)`");
+        assert_eq!(parse("sin x").unwrap_err().1, "Expected `(` after `sin`");
+        assert_eq!(
+            parse("foo(x)").unwrap_err(),
+            (0, "Unknown function `foo`".to_string())
+        );
+        assert_eq!(parse("x )").unwrap_err(), (2, "Unexpected `)`".to_string()));
+
+        let nested = format!("{}x{}", "(".repeat(10000), ")".repeat(10000));
+        assert_eq!(parse(&nested).unwrap_err().1, "Expression is nested too deeply");
+        assert!(parse(&"-".repeat(10000)).is_err());
+        let shallow = format!("{}x{}", "(".repeat(100), ")".repeat(100));
+        assert!(parse(&shallow).is_ok());
+    }
+}
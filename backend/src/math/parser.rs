@@ -0,0 +1,227 @@
+//! # Usage
+//! A recursive descent parser for expressions, from
+//! lowest to highest precedence:
+//! ```text
+//! sum     = product (("+" | "-") product)*
+//! product = unary (("*" | "/") unary | unary)*
+//! unary   = "-" unary | power
+//! power   = primary ("^" unary)?
+//! primary = number | name | func "(" sum ")" | "(" sum ")" | "|" sum "|"
+//! ```
+//! A `unary` directly following another in a product is an implied
+//! multiplication. Powers are right associative and bind tighter than
+//! negation, so `-x^2` is `-(x^2)` and `2^-1` is `1/2`.
+//!
+//! Nesting deeper than [MAX_DEPTH] is an error rather than
+//! recursing without bound, since answers come from anyone.
+
+use super::{Const, Error, Expr, Func};
+
+/// How deeply `unary`s may nest, through parentheses, signs,
+/// exponents and function calls.
+const MAX_DEPTH: usize = 200;
+
+struct Parser<'a> {
+    s: &'a str,
+    i: usize,
+    /// How many `|` are open, where a `|` closes instead of opening.
+    abs: usize,
+    /// How many `unary`s are being parsed.
+    depth: usize,
+}
+
+/// # Usage
+/// Parses an expression, failing on any trailing text.
+pub fn parse(s: &str) -> Result<Expr, Error> {
+    let mut parser = Parser {
+        s,
+        i: 0,
+        abs: 0,
+        depth: 0,
+    };
+    let expr = parser.sum()?;
+    parser.skip();
+    if let Some(c) = parser.peek() {
+        return Err((parser.i, format!("Unexpected `{}`", c)));
+    }
+    return Ok(expr);
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        return self.s[self.i..].chars().next();
+    }
+
+    fn skip(&mut self) {
+        let rest = &self.s[self.i..];
+        self.i += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip();
+        if self.peek() == Some(c) {
+            self.i += c.len_utf8();
+            return true;
+        }
+        return false;
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        if self.eat(c) {
+            return Ok(());
+        }
+        return Err((self.i, format!("Expected `{}`", c)));
+    }
+
+    fn sum(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.product()?;
+        loop {
+            if self.eat('+') {
+                expr = Expr::Add(Box::new(expr), Box::new(self.product()?));
+            } else if self.eat('-') {
+                expr = Expr::Sub(Box::new(expr), Box::new(self.product()?));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.unary()?;
+        loop {
+            if self.eat('*') {
+                expr = Expr::Mul(Box::new(expr), Box::new(self.unary()?));
+            } else if self.eat('/') {
+                expr = Expr::Div(Box::new(expr), Box::new(self.unary()?));
+            } else if self.implied() {
+                expr = Expr::Mul(Box::new(expr), Box::new(self.unary()?));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// # Usage
+    /// Whether the next token starts a factor of an implied
+    /// multiplication. A `|` only does if it can't close one.
+    fn implied(&mut self) -> bool {
+        self.skip();
+        return match self.peek() {
+            Some('(') => true,
+            Some('|') => self.abs == 0,
+            Some(c) => c.is_ascii_alphanumeric() || c == '.',
+            None => false,
+        };
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        if self.depth == MAX_DEPTH {
+            self.skip();
+            return Err((self.i, "Expression is nested too deeply".to_string()));
+        }
+        self.depth += 1;
+        let expr = self.signed();
+        self.depth -= 1;
+        return expr;
+    }
+
+    fn signed(&mut self) -> Result<Expr, Error> {
+        if self.eat('-') {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+        return self.power();
+    }
+
+    fn power(&mut self) -> Result<Expr, Error> {
+        let base = self.primary()?;
+        if self.eat('^') {
+            return Ok(Expr::Pow(Box::new(base), Box::new(self.unary()?)));
+        }
+        return Ok(base);
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        self.skip();
+        let start = self.i;
+        let rest = &self.s[self.i..];
+
+        match self.peek() {
+            Some('(') => {
+                self.i += 1;
+                let outer = std::mem::replace(&mut self.abs, 0);
+                let expr = self.sum()?;
+                self.abs = outer;
+                self.expect(')')?;
+                return Ok(expr);
+            }
+            Some('|') => {
+                self.i += 1;
+                self.abs += 1;
+                let expr = self.sum()?;
+                self.abs -= 1;
+                self.expect('|')?;
+                return Ok(Expr::Call(Func::Abs, Box::new(expr)));
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let mut len = rest
+                    .find(|c: char| !c.is_ascii_digit() && c != '.')
+                    .unwrap_or(rest.len());
+                // An exponent, as in `1.5e-3`
+                let exponent = rest[len..].strip_prefix(['e', 'E']).map(|x| {
+                    let sign = x.starts_with(['+', '-']) as usize;
+                    let digits = x[sign..]
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap_or(x.len() - sign);
+                    (sign, digits)
+                });
+                if let Some((sign, digits)) = exponent {
+                    if digits > 0 {
+                        len += 1 + sign + digits;
+                    }
+                }
+
+                self.i += len;
+                return rest[..len]
+                    .parse::<f64>()
+                    .map(Expr::Num)
+                    .map_err(|_| (start, format!("Invalid number `{}`", &rest[..len])));
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(rest.len());
+                let name = &rest[..len];
+                self.i += len;
+
+                if let Some(func) = Func::parse(name) {
+                    if !self.eat('(') {
+                        return Err((self.i, format!("Expected `(` after `{}`", name)));
+                    }
+                    let outer = std::mem::replace(&mut self.abs, 0);
+                    let arg = self.sum()?;
+                    self.abs = outer;
+                    self.expect(')')?;
+                    return Ok(Expr::Call(func, Box::new(arg)));
+                }
+
+                return match name {
+                    "pi" => Ok(Expr::Const(Const::Pi)),
+                    "e" => Ok(Expr::Const(Const::E)),
+                    _ if self.s[self.i..].trim_start().starts_with('(') && len > 1 => {
+                        Err((start, format!("Unknown function `{}`", name)))
+                    }
+                    _ if len == 1 => Ok(Expr::Var(name.chars().next().unwrap())),
+                    _ => Err((
+                        start,
+                        format!("Unknown name `{}`, variables are single letters", name),
+                    )),
+                };
+            }
+            Some(c) => return Err((start, format!("Unexpected `{}`", c))),
+            None => return Err((start, "Expected a value".to_string())),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! # Usage
+//! Rewrites expressions into a simpler, equal form: folding
+//! constants, dropping identities such as `x + 0` and `x^1`,
+//! ordering the factors of products (numbers, then constants,
+//! then powers of variables, then everything else) and
+//! collecting repeated terms and factors.
+//!
+//! This is not a canonical form; two equal expressions may
+//! still simplify differently. Use [Expr::equivalent] to compare.
+
+use super::Expr;
+
+/// How many passes are made before giving up on a fixed point.
+const MAX_PASSES: usize = 32;
+
+fn num(x: f64) -> Box<Expr> {
+    return Box::new(Expr::Num(x));
+}
+
+/// # Usage
+/// Whether `x` is a whole number, which folded results
+/// must be so that `1/3` isn't turned into `0.333...`.
+fn whole(x: f64) -> bool {
+    return x.is_finite() && x == x.round();
+}
+
+/// # Usage
+/// Where a factor goes when ordering a product.
+fn rank(x: &Expr) -> u8 {
+    return match x {
+        Expr::Num(_) => 0,
+        Expr::Const(_) => 1,
+        Expr::Var(_) => 2,
+        Expr::Pow(base, _) if matches!(**base, Expr::Var(_)) => 2,
+        _ => 3,
+    };
+}
+
+/// # Usage
+/// Splits a term into its numeric coefficient and the rest,
+/// so that `3x` and `x` can be collected.
+fn coefficient(x: &Expr) -> (f64, &Expr) {
+    return match x {
+        Expr::Mul(a, b) => match **a {
+            Expr::Num(n) => (n, b),
+            _ => (1.0, x),
+        },
+        _ => (1.0, x),
+    };
+}
+
+/// # Usage
+/// Splits a factor into its base and exponent, so
+/// that `x`, `x^2` and `x^n` can be collected.
+fn power(x: &Expr) -> (&Expr, Expr) {
+    return match x {
+        Expr::Pow(a, b) => (a, (**b).clone()),
+        _ => (x, Expr::Num(1.0)),
+    };
+}
+
+impl Expr {
+    /// # Usage
+    /// Returns a simpler expression with the same value.
+    pub fn simplify(&self) -> Expr {
+        let mut expr = self.clone();
+        for _ in 0..MAX_PASSES {
+            let next = expr.pass();
+            if next == expr {
+                break;
+            }
+            expr = next;
+        }
+        return expr;
+    }
+
+    /// # Usage
+    /// Simplifies the children, then applies the first
+    /// rule matching the expression itself.
+    fn pass(&self) -> Expr {
+        use Expr::*;
+
+        return match self {
+            Num(_) | Const(_) | Var(_) => self.clone(),
+            Neg(a) => match a.pass() {
+                Num(x) => Num(-x),
+                Neg(a) => *a,
+                a => Neg(Box::new(a)),
+            },
+            Add(a, b) => match (a.pass(), b.pass()) {
+                (Num(x), Num(y)) => Num(x + y),
+                (Num(0.0), b) => b,
+                (a, Num(0.0)) => a,
+                (a, Num(x)) if x < 0.0 => Sub(Box::new(a), num(-x)),
+                (Num(x), b) => Add(Box::new(b), num(x)),
+                (a, Neg(b)) => Sub(Box::new(a), b),
+                (Neg(a), b) => Sub(Box::new(b), a),
+                (a, b) => {
+                    let ((n, x), (m, y)) = (coefficient(&a), coefficient(&b));
+                    match x == y {
+                        true => Mul(num(n + m), Box::new(x.clone())),
+                        false => Add(Box::new(a), Box::new(b)),
+                    }
+                }
+            },
+            Sub(a, b) => match (a.pass(), b.pass()) {
+                (Num(x), Num(y)) => Num(x - y),
+                (a, Num(0.0)) => a,
+                (Num(0.0), b) => Neg(Box::new(b)),
+                (a, Num(x)) if x < 0.0 => Add(Box::new(a), num(-x)),
+                (a, Neg(b)) => Add(Box::new(a), b),
+                (a, b) => {
+                    let ((n, x), (m, y)) = (coefficient(&a), coefficient(&b));
+                    match x == y {
+                        true => Mul(num(n - m), Box::new(x.clone())),
+                        false => Sub(Box::new(a), Box::new(b)),
+                    }
+                }
+            },
+            Mul(a, b) => match (a.pass(), b.pass()) {
+                (Num(x), Num(y)) => Num(x * y),
+                (Num(0.0), _) | (_, Num(0.0)) => Num(0.0),
+                (Num(1.0), b) => b,
+                (a, Num(1.0)) => a,
+                (Num(-1.0), b) => Neg(Box::new(b)),
+                (Num(x), b) if x < 0.0 => Neg(Box::new(Mul(num(-x), Box::new(b)))),
+                (Neg(a), b) => Neg(Box::new(Mul(a, Box::new(b)))),
+                (a, Neg(b)) => Neg(Box::new(Mul(Box::new(a), b))),
+                (Num(x), Mul(b, c)) if matches!(*b, Num(_)) => match *b {
+                    Num(y) => Mul(num(x * y), c),
+                    _ => unreachable!(),
+                },
+                (Mul(a, b), c) => Mul(a, Box::new(Mul(b, Box::new(c)))),
+                (a, Mul(b, c)) if rank(&a) > rank(&b) => Mul(b, Box::new(Mul(Box::new(a), c))),
+                (a, Mul(b, c)) if power(&a).0 == power(&b).0 => {
+                    let (base, m) = power(&a);
+                    let n = power(&b).1;
+                    Mul(
+                        Box::new(Pow(
+                            Box::new(base.clone()),
+                            Box::new(Add(Box::new(m), Box::new(n))),
+                        )),
+                        c,
+                    )
+                }
+                (a, b) if rank(&a) > rank(&b) => Mul(Box::new(b), Box::new(a)),
+                (a, b) if power(&a).0 == power(&b).0 => {
+                    let ((base, m), n) = (power(&a), power(&b).1);
+                    Pow(
+                        Box::new(base.clone()),
+                        Box::new(Add(Box::new(m), Box::new(n))),
+                    )
+                }
+                (a, b) => Mul(Box::new(a), Box::new(b)),
+            },
+            Div(a, b) => match (a.pass(), b.pass()) {
+                (Num(x), Num(y)) if y != 0.0 && whole(x / y) => Num(x / y),
+                (Num(0.0), _) => Num(0.0),
+                (a, Num(1.0)) => a,
+                (a, b) if a == b => Num(1.0),
+                (Neg(a), b) => Neg(Box::new(Div(a, Box::new(b)))),
+                (a, Neg(b)) => Neg(Box::new(Div(Box::new(a), b))),
+                (a, b) => Div(Box::new(a), Box::new(b)),
+            },
+            Pow(a, b) => match (a.pass(), b.pass()) {
+                (Num(x), Num(y)) if whole(x.powf(y)) && y >= 0.0 => Num(x.powf(y)),
+                (_, Num(0.0)) => Num(1.0),
+                (a, Num(1.0)) => a,
+                (Num(1.0), _) => Num(1.0),
+                // Only for whole `n`, as `(x^2)^0.5` is `|x|`
+                (Pow(a, m), n) if matches!(*m, Num(_)) && matches!(n, Num(y) if whole(y)) => {
+                    Pow(a, Box::new(Mul(m, Box::new(n))))
+                }
+                (a, b) => Pow(Box::new(a), Box::new(b)),
+            },
+            Call(func, a) => match a.pass() {
+                Num(x) if whole(func.apply(x)) => Num(func.apply(x)),
+                a => Call(*func, Box::new(a)),
+            },
+        };
+    }
+}
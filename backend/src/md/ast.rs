@@ -0,0 +1,144 @@
+//! # Usage
+//! The syntax tree produced by the [parser](super::parser)
+//! and consumed by the [renderer](super::html).
+
+use super::{
+    cite::{Cite, Work},
+    environment::Kind,
+    exercise::Answer,
+    Span,
+};
+
+/// # Usage
+/// A block level element. Blocks are either leaves
+/// (headings, paragraphs, code) or containers holding
+/// further blocks (quotes, lists).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Heading {
+        level: u8,
+        content: Vec<Inline>,
+        /// The anchor of the heading, given by the [outline](super::outline).
+        id: Option<String>,
+    },
+    Paragraph(Vec<Inline>),
+    Quote(Vec<Block>),
+    List {
+        /// `Some(start)` for ordered lists.
+        start: Option<u64>,
+        /// Tight lists render their paragraphs without `<p>` tags.
+        tight: bool,
+        items: Vec<Vec<Block>>,
+    },
+    Code {
+        lang: Option<String>,
+        text: String,
+    },
+    /// Raw HTML passed through untouched.
+    Html(String),
+    /// Display math, `$$...$$`, already compiled to MathML.
+    Math {
+        tex: String,
+        mathml: String,
+    },
+    /// A theorem, definition, proof, etc.
+    Environment {
+        kind: Kind,
+        title: Vec<Inline>,
+        label: Option<String>,
+        /// Given by [numbering](super::environment), if the kind is numbered.
+        number: Option<String>,
+        /// Where problems with the label are reported.
+        span: Span,
+        content: Vec<Block>,
+    },
+    /// A practice problem, see [exercise](super::exercise).
+    Exercise {
+        id: String,
+        prompt: Vec<Block>,
+        hints: Vec<Vec<Block>>,
+        solution: Vec<Block>,
+        /// Never rendered. `None` for exercises included from
+        /// another note, whose answers are that note's.
+        answer: Option<Answer>,
+        /// The title of the note an included exercise is from.
+        note: Option<String>,
+    },
+    /// The text of each footnote, in order, added at the end of a note.
+    Footnotes(Vec<Vec<Inline>>),
+    /// The works cited, in order, added at the end of a note.
+    Bibliography(Vec<Work>),
+    Rule,
+}
+
+/// # Usage
+/// An element inside of a paragraph or heading.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Emphasis(Vec<Inline>),
+    Strong(Vec<Inline>),
+    Code(String),
+    Link {
+        href: String,
+        title: Option<String>,
+        content: Vec<Inline>,
+    },
+    Image {
+        src: String,
+        title: Option<String>,
+        alt: String,
+    },
+    /// Raw HTML passed through untouched.
+    Html(String),
+    /// Inline math, `$...$`, already compiled to MathML.
+    Math {
+        tex: String,
+        mathml: String,
+    },
+    /// `@ref(label)`, a reference to a labelled environment.
+    Ref {
+        label: String,
+        span: Span,
+        /// What the reference reads as once resolved, e.g. `Theorem 2.1`.
+        text: Option<String>,
+    },
+    /// `[^label]`, a reference to a footnote.
+    Footnote {
+        label: String,
+        span: Span,
+        /// The text of the footnote, once resolved.
+        content: Option<Vec<Inline>>,
+        number: Option<usize>,
+    },
+    /// `[@key, locator; @key]`, a citation of one or more references.
+    Citation {
+        cites: Vec<Cite>,
+        span: Span,
+    },
+    SoftBreak,
+    LineBreak,
+}
+
+impl Inline {
+    /// # Usage
+    /// Returns the text content of the inline, without
+    /// any markup. Used for image alt text.
+    pub fn plain(inlines: &[Inline]) -> String {
+        let mut out = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) | Inline::Code(text) => out.push_str(text),
+                Inline::Math { tex, .. } => out.push_str(tex),
+                Inline::Emphasis(content)
+                | Inline::Strong(content)
+                | Inline::Link { content, .. } => out.push_str(&Inline::plain(content)),
+                Inline::Image { alt, .. } => out.push_str(alt),
+                Inline::Ref { label, text, .. } => out.push_str(text.as_deref().unwrap_or(label)),
+                Inline::SoftBreak | Inline::LineBreak => out.push(' '),
+                Inline::Html(_) | Inline::Footnote { .. } | Inline::Citation { .. } => {}
+            }
+        }
+        return out;
+    }
+}
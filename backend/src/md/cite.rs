@@ -0,0 +1,243 @@
+//! # Usage
+//! Footnotes and citations.
+//! ```markdown
+//! Limits were made rigorous by Weierstrass.[^history]
+//! See [@stewart, p. 92] or [@spivak; @stewart].
+//!
+//! [^history]: Cauchy's definition came first, but used infinitesimals.
+//! ```
+//! Footnotes are defined on a single line anywhere in the note,
+//! and are numbered in the order they are first referenced.
+//!
+//! Citations refer to rows of `ReferenceTable` by key, ignoring
+//! case, optionally followed by a locator such as a page. They
+//! are numbered in the order the references are first cited.
+//!
+//! Both are listed at the end of the note: the footnotes and
+//! then a bibliography of every reference cited. Undefined
+//! footnotes and unknown keys are reported as errors.
+
+use super::{
+    ast::{Block, Inline},
+    environment::inlines,
+    lexer::Line,
+    parser::Parser,
+    Severity, Span,
+};
+use std::collections::HashMap;
+
+/// # Usage
+/// An entry of the bibliography. Empty fields, and a
+/// year of `0`, are left out when it is rendered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Work {
+    pub author: String,
+    pub title: String,
+    pub year: i64,
+    pub publisher: String,
+    pub url: String,
+}
+
+/// # Usage
+/// A single reference of a citation, e.g. `@stewart, p. 92`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cite {
+    pub key: String,
+    pub locator: Option<String>,
+    /// Given once the key is resolved.
+    pub number: Option<usize>,
+}
+
+fn is_key(c: char) -> bool {
+    return c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.');
+}
+
+/// # Usage
+/// Parses a footnote reference, `[^label]`, at the start of
+/// `s`, returning the label and the length of the reference.
+pub fn footnote(s: &str) -> Option<(String, usize)> {
+    let rest = s.strip_prefix("[^")?;
+    let len = rest.chars().take_while(|c| is_key(*c)).count();
+    if len == 0 || !rest[len..].starts_with(']') {
+        return None;
+    }
+    return Some((rest[..len].to_string(), len + 3));
+}
+
+/// # Usage
+/// Parses a footnote definition, `[^label]: text`, returning the
+/// label and the byte offset at which the text starts.
+pub fn definition(text: &str) -> Option<(String, usize)> {
+    let (label, len) = footnote(text)?;
+    let rest = text[len..].strip_prefix(':')?;
+    return Some((label, text.len() - rest.trim_start().len()));
+}
+
+/// # Usage
+/// Parses a citation, `[@key, locator; @key]`, at the start of
+/// `s`, returning its references and its length.
+pub fn citation(s: &str) -> Option<(Vec<Cite>, usize)> {
+    let rest = s.strip_prefix('[')?;
+    let close = rest.find(']')?;
+    let inner = &rest[..close];
+    if inner.contains(['[', '\n']) {
+        return None;
+    }
+
+    let mut cites = Vec::new();
+    for part in inner.split(';') {
+        let part = part.trim().strip_prefix('@')?;
+        let len = part.chars().take_while(|c| is_key(*c)).count();
+        let locator = part[len..].trim();
+        if len == 0 || !(locator.is_empty() || locator.starts_with(',')) {
+            return None;
+        }
+
+        let locator = locator.trim_start_matches(',').trim();
+        cites.push(Cite {
+            key: part[..len].to_string(),
+            locator: (!locator.is_empty()).then(|| locator.to_string()),
+            number: None,
+        });
+    }
+
+    return Some((cites, close + 2));
+}
+
+/// # Usage
+/// Prepares the blocks of a note being included into another:
+/// its footnotes are prefixed by `prefix` so they can't clash
+/// with those of the including note, which lists them instead.
+pub fn adopt(blocks: &mut Vec<Block>, prefix: &str) {
+    blocks.retain(|x| !matches!(x, Block::Footnotes(_) | Block::Bibliography(_)));
+    inlines(blocks, &mut |inline| {
+        if let Inline::Footnote { label, .. } = inline {
+            *label = format!("{}/{}", prefix, label);
+        }
+    });
+}
+
+impl<'s> Parser<'s> {
+    /// # Usage
+    /// Resolves and numbers the footnotes and citations of a note,
+    /// appending the footnotes and bibliography to `blocks`.
+    pub fn cite(&mut self, blocks: &mut Vec<Block>) {
+        // Parse the text of every footnote which is referenced
+        let mut wanted: Vec<(String, Span)> = Vec::new();
+        inlines(blocks, &mut |inline| {
+            if let Inline::Footnote {
+                label,
+                span,
+                content: None,
+                ..
+            } = inline
+            {
+                wanted.push((label.clone(), *span));
+            }
+        });
+
+        let mut bodies: HashMap<String, Vec<Inline>> = HashMap::new();
+        for (label, span) in wanted {
+            if bodies.contains_key(&label) {
+                continue;
+            }
+            let Some(line) = self.footnotes.get(&label).copied() else {
+                self.report(
+                    span,
+                    Severity::Error,
+                    format!("Footnote `{}` is never defined", label),
+                );
+                continue;
+            };
+            let body = self.inline(&[line]);
+            bodies.insert(label, body);
+        }
+
+        for (label, line) in &self.footnotes.clone() {
+            if !bodies.contains_key(label) {
+                self.report(
+                    Span::at(line.offset),
+                    Severity::Warning,
+                    format!("Footnote `{}` is never referenced", label),
+                );
+            }
+        }
+
+        // Number footnotes in the order they are first referenced
+        let mut footnotes: Vec<(String, Vec<Inline>)> = Vec::new();
+        inlines(blocks, &mut |inline| {
+            if let Inline::Footnote {
+                label,
+                content,
+                number,
+                ..
+            } = inline
+            {
+                if content.is_none() {
+                    *content = bodies.get(label).cloned();
+                }
+                let Some(content) = content else {
+                    return;
+                };
+                *number = match footnotes.iter().position(|x| x.0 == *label) {
+                    Some(i) => Some(i + 1),
+                    None => {
+                        footnotes.push((label.clone(), content.clone()));
+                        Some(footnotes.len())
+                    }
+                };
+            }
+        });
+        if !footnotes.is_empty() {
+            blocks.push(Block::Footnotes(
+                footnotes.into_iter().map(|x| x.1).collect(),
+            ));
+        }
+
+        // Then citations, including those in footnotes
+        let mut works: Vec<(String, Work)> = Vec::new();
+        let mut unresolved: Vec<(Span, String)> = Vec::new();
+        let library = self.library;
+        inlines(blocks, &mut |inline| {
+            let Inline::Citation { cites, span } = inline else {
+                return;
+            };
+            for cite in cites {
+                let key = cite.key.to_ascii_lowercase();
+                cite.number = match works.iter().position(|x| x.0 == key) {
+                    Some(i) => Some(i + 1),
+                    None => match library.reference(&key) {
+                        Some(work) => {
+                            works.push((key, work.clone()));
+                            Some(works.len())
+                        }
+                        None => {
+                            unresolved.push((*span, cite.key.clone()));
+                            None
+                        }
+                    },
+                };
+            }
+        });
+
+        for (span, key) in unresolved {
+            self.report(
+                span,
+                Severity::Error,
+                format!("No reference has the key `{}`", key),
+            );
+        }
+        if !works.is_empty() {
+            blocks.push(Block::Bibliography(
+                works.into_iter().map(|x| x.1).collect(),
+            ));
+        }
+    }
+}
+
+/// # Usage
+/// Whether a line of a note defines a footnote, in which
+/// case it is skipped when parsing blocks.
+pub fn defines(line: Line) -> bool {
+    return definition(line.text.trim_start()).is_some();
+}
@@ -0,0 +1,41 @@
+//! # Usage
+//! The built in `@deriv` macro, which differentiates a
+//! function and writes the result as math.
+//! ```markdown
+//! @deriv(f="sin(x)*x^2")
+//! ```
+//! expands to `$\frac{d}{dx}\left(x^{2} \sin\left(x\right)\right) = ...$`.
+//! `x` names the variable to differentiate by, and `display`
+//! writes display math rather than inline math.
+
+use super::macros::Value;
+use crate::math;
+use std::collections::HashMap;
+
+/// # Usage
+/// Expands `@deriv`, failing if the function doesn't parse.
+pub fn deriv(values: &HashMap<String, Value>) -> Result<String, String> {
+    let (Value::String(f), Value::String(x), Value::Boolean(display)) =
+        (&values["f"], &values["x"], &values["display"])
+    else {
+        unreachable!("Arguments are checked by Macro::bind");
+    };
+
+    let var = match x.chars().collect::<Vec<char>>()[..] {
+        [var] if var.is_ascii_alphabetic() && var != 'e' => var,
+        _ => return Err(format!("`x` should be a single letter, found `{}`", x)),
+    };
+    let expr = math::parse(f)
+        .map_err(|(offset, message)| format!("{} in `{}` at column {}", message, f, offset + 1))?;
+
+    let tex = format!(
+        "\\frac{{d}}{{d{}}}\\left({}\\right) = {}",
+        var,
+        expr.to_tex(),
+        expr.derivative(var).to_tex()
+    );
+    return Ok(match display {
+        true => format!("$$\n{}\n$$", tex),
+        false => format!("${}$", tex),
+    });
+}
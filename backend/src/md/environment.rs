@@ -0,0 +1,383 @@
+//! # Usage
+//! Theorem-like environments, written as blocks fenced by `:::`
+//! with an optional title and label:
+//! ```markdown
+//! :::theorem Mean value theorem {#mvt}
+//! If $f$ is continuous on $[a, b]$ and differentiable on $(a, b)$...
+//! :::
+//!
+//! :::proof
+//! Apply @ref(rolle) to $f(x) - \frac{f(b) - f(a)}{b - a} x$.
+//! :::
+//! ```
+//! Every kind except proofs and remarks is numbered, sharing one
+//! counter per section, so the second numbered environment under
+//! the third section of a note is `3.2`. Sections are the top
+//! level headings of the highest level used; notes without
+//! headings are numbered `1`, `2`, ... instead.
+//!
+//! `@ref(label)` reads as `Theorem 3.2` and links to the
+//! environment. Unknown labels are reported as errors.
+
+use super::{
+    ast::{Block, Inline},
+    lexer::{self, Line, LineKind},
+    parser::Parser,
+    Severity, Span,
+};
+use std::collections::HashMap;
+
+/// # Usage
+/// The kind of an environment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Definition,
+    Theorem,
+    Lemma,
+    Proposition,
+    Corollary,
+    Example,
+    Remark,
+    Proof,
+}
+
+/// Every kind along with the name it is written as.
+const KINDS: [(&str, Kind); 8] = [
+    ("definition", Kind::Definition),
+    ("theorem", Kind::Theorem),
+    ("lemma", Kind::Lemma),
+    ("proposition", Kind::Proposition),
+    ("corollary", Kind::Corollary),
+    ("example", Kind::Example),
+    ("remark", Kind::Remark),
+    ("proof", Kind::Proof),
+];
+
+impl Kind {
+    pub fn parse(name: &str) -> Option<Self> {
+        return KINDS.iter().find(|x| x.0 == name).map(|x| x.1);
+    }
+
+    /// # Usage
+    /// The name as written, also used as the class of the HTML.
+    pub fn name(&self) -> &'static str {
+        return KINDS.iter().find(|x| x.1 == *self).unwrap().0;
+    }
+
+    /// # Usage
+    /// The name as it reads in the note, e.g. `Theorem`.
+    pub fn heading(&self) -> String {
+        let name = self.name();
+        return name[..1].to_uppercase() + &name[1..];
+    }
+
+    pub fn numbered(&self) -> bool {
+        return !matches!(self, Kind::Remark | Kind::Proof);
+    }
+}
+
+fn is_label(c: char) -> bool {
+    return c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.');
+}
+
+/// # Usage
+/// Parses `@ref(label)` at the start of `s`, returning
+/// the label and the length of the reference.
+pub fn reference(s: &str) -> Option<(String, usize)> {
+    let rest = s.strip_prefix("@ref(")?;
+    let len = rest.chars().take_while(|c| is_label(*c)).count();
+    if len == 0 || !rest[len..].starts_with(')') {
+        return None;
+    }
+    return Some((rest[..len].to_string(), "@ref(".len() + len + 1));
+}
+
+/// # Usage
+/// Calls `f` on every block, outer ones first.
+pub fn blocks(blocks: &mut [Block], f: &mut impl FnMut(&mut Block)) {
+    fn each(blocks: &mut [Block], f: &mut impl FnMut(&mut Block)) {
+        for block in blocks {
+            f(block);
+            match block {
+                Block::Quote(blocks)
+                | Block::Environment {
+                    content: blocks, ..
+                } => each(blocks, f),
+                Block::List { items, .. } => {
+                    for item in items {
+                        each(item, f);
+                    }
+                }
+                Block::Exercise {
+                    prompt,
+                    hints,
+                    solution,
+                    ..
+                } => {
+                    each(prompt, f);
+                    for hint in hints {
+                        each(hint, f);
+                    }
+                    each(solution, f);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    each(blocks, f);
+}
+
+/// # Usage
+/// Calls `f` on every inline, including those nested in others.
+pub fn inlines(blocks: &mut [Block], f: &mut impl FnMut(&mut Inline)) {
+    fn each(inlines: &mut [Inline], f: &mut impl FnMut(&mut Inline)) {
+        for inline in inlines {
+            f(inline);
+            if let Inline::Emphasis(content)
+            | Inline::Strong(content)
+            | Inline::Link { content, .. } = inline
+            {
+                each(content, f);
+            }
+        }
+    }
+
+    for block in blocks {
+        match block {
+            Block::Heading { content, .. } | Block::Paragraph(content) => each(content, f),
+            Block::Quote(blocks) => inlines(blocks, f),
+            Block::List { items, .. } => {
+                for item in items {
+                    inlines(item, f);
+                }
+            }
+            Block::Environment { title, content, .. } => {
+                each(title, f);
+                inlines(content, f);
+            }
+            Block::Exercise {
+                prompt,
+                hints,
+                solution,
+                ..
+            } => {
+                inlines(prompt, f);
+                for hint in hints {
+                    inlines(hint, f);
+                }
+                inlines(solution, f);
+            }
+            Block::Footnotes(footnotes) => {
+                for footnote in footnotes {
+                    each(footnote, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// # Usage
+/// Reports every problem with the environments, references and
+/// citations of `blocks` at `span`, for blocks included from
+/// another note.
+pub fn relocate(blocks: &mut [Block], span: Span) {
+    self::blocks(blocks, &mut |block| {
+        if let Block::Environment { span: at, .. } = block {
+            *at = span;
+        }
+    });
+    inlines(blocks, &mut |inline| match inline {
+        Inline::Ref { span: at, .. }
+        | Inline::Footnote { span: at, .. }
+        | Inline::Citation { span: at, .. } => *at = span,
+        _ => {}
+    });
+}
+
+impl<'s> Parser<'s> {
+    /// # Usage
+    /// Parses an environment, from its `:::name` line to the
+    /// matching `:::`. Environments may be nested, as a proof
+    /// inside of an example.
+    pub fn environment(
+        &mut self,
+        lines: &[Line],
+        i: &mut usize,
+        name: &str,
+        rest: Line,
+    ) -> Vec<Block> {
+        let open = lines[*i].trim_start();
+        *i += 1;
+
+        let start = *i;
+        let mut depth = 0;
+        let mut fence: Option<char> = None;
+        let mut closed = false;
+        while *i < lines.len() {
+            match (lexer::classify(lines[*i]), fence) {
+                (LineKind::Fence { marker, .. }, None) => fence = Some(marker),
+                (LineKind::Fence { marker, .. }, Some(open)) if marker == open => fence = None,
+                (LineKind::Environment { .. }, None) => depth += 1,
+                (LineKind::EnvironmentEnd, None) if depth == 0 => {
+                    closed = true;
+                    break;
+                }
+                (LineKind::EnvironmentEnd, None) => depth -= 1,
+                _ => {}
+            }
+            *i += 1;
+        }
+
+        let content = self.blocks(&lines[start..*i]);
+        if closed {
+            *i += 1;
+        } else {
+            self.report(
+                Span::at(open.offset),
+                Severity::Warning,
+                "Environment is never closed with `:::`",
+            );
+        }
+
+        let kind = match Kind::parse(name) {
+            Some(kind) => kind,
+            None => {
+                self.report(
+                    Span::at(open.offset + 3),
+                    Severity::Error,
+                    format!("Unknown environment `{}`", name),
+                );
+                return content;
+            }
+        };
+
+        // The label is written last, as in `Title {#label}`
+        let text = rest.text.trim_end();
+        let mut title = Line {
+            text,
+            offset: rest.offset,
+        };
+        let mut label = None;
+        if let Some(at) = text.rfind("{#").filter(|_| text.ends_with('}')) {
+            let name = &text[at + 2..text.len() - 1];
+            if name.is_empty() || !name.chars().all(is_label) {
+                self.report(
+                    Span::at(rest.offset + at),
+                    Severity::Error,
+                    format!("Invalid label `{}`, use letters, digits and `-_:.`", name),
+                );
+            } else if !kind.numbered() {
+                self.report(
+                    Span::at(rest.offset + at),
+                    Severity::Error,
+                    format!("A {} is not numbered, so cannot be labelled", kind.name()),
+                );
+            } else {
+                label = Some(name.to_string());
+            }
+            title.text = text[..at].trim_end();
+        }
+
+        let title = match title.text.is_empty() {
+            true => Vec::new(),
+            false => self.inline(&[title]),
+        };
+
+        return vec![Block::Environment {
+            kind,
+            title,
+            label,
+            number: None,
+            span: self.expansion.unwrap_or(Span::at(open.offset)),
+            content,
+        }];
+    }
+
+    /// # Usage
+    /// Numbers the environments of a note and resolves the
+    /// references to them, reporting duplicate labels and
+    /// references to labels which don't exist.
+    pub fn number(&mut self, blocks: &mut [Block]) {
+        let sections = blocks
+            .iter()
+            .filter_map(|x| match x {
+                Block::Heading { level, .. } => Some(*level),
+                _ => None,
+            })
+            .min();
+
+        let mut section = 0;
+        let mut count = 0;
+        let mut labels: HashMap<String, String> = HashMap::new();
+        let mut duplicates = Vec::new();
+        for block in blocks.iter_mut() {
+            if let Block::Heading { level, .. } = block {
+                if Some(*level) == sections {
+                    section += 1;
+                    count = 0;
+                }
+            }
+
+            self::blocks(std::slice::from_mut(block), &mut |block| {
+                let Block::Environment {
+                    kind,
+                    label,
+                    number,
+                    span,
+                    ..
+                } = block
+                else {
+                    return;
+                };
+                if !kind.numbered() {
+                    return;
+                }
+
+                count += 1;
+                let n = match sections {
+                    Some(_) => format!("{}.{}", section, count),
+                    None => count.to_string(),
+                };
+                if let Some(label) = label {
+                    if labels.contains_key(label) {
+                        duplicates.push((*span, label.clone()));
+                    } else {
+                        labels.insert(label.clone(), format!("{} {}", kind.heading(), n));
+                    }
+                }
+                *number = Some(n);
+            });
+        }
+
+        for (span, label) in duplicates {
+            self.report(
+                span,
+                Severity::Error,
+                format!("Label `{}` is used twice", label),
+            );
+        }
+
+        let mut unresolved = Vec::new();
+        inlines(blocks, &mut |inline| {
+            if let Inline::Ref { label, span, text } = inline {
+                // References included from another note keep what they
+                // resolved to there, if the label isn't included with them
+                match labels.get(label) {
+                    Some(found) => *text = Some(found.clone()),
+                    None if text.is_none() => unresolved.push((*span, label.clone())),
+                    None => {}
+                }
+            }
+        });
+
+        for (span, label) in unresolved {
+            self.report(
+                span,
+                Severity::Error,
+                format!("No environment is labelled `{}`", label),
+            );
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! # Usage
+//! Practice problems, whose answers are checked by the server:
+//! ```markdown
+//! @exercise(
+//!     id="sinc-limit",
+//!     prompt="Find $\lim_{x \to 0} \frac{\sin x}{x}$.",
+//!     answer="1",
+//!     hints="Compare areas around the unit circle.\nOr use L'Hôpital's rule.",
+//!     solution="By the squeeze theorem, ..."
+//! )
+//! ```
+//! The prompt, hints and solution are markdown, with one hint
+//! per line of `hints`. The answer is an expression, as accepted
+//! by [math](crate::math), which is either a number or in terms
+//! of variables. It is left out of the HTML and instead returned
+//! along with it, so the server can grade answers by comparing
+//! them with it at sample points, to within `tolerance`.
+//!
+//! Ids must be unique within a note, and are the exercise's anchor.
+
+use super::{
+    ast::Block,
+    inline::Text,
+    lexer,
+    macros::{Call, Value},
+    parser::Parser,
+    Severity, Span,
+};
+use crate::math;
+
+/// # Usage
+/// The hidden answer of an exercise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Answer {
+    /// The expected expression, as written.
+    pub expected: String,
+    /// See [math::Expr::within].
+    pub tolerance: f64,
+}
+
+/// # Usage
+/// An exercise of a compiled note.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exercise {
+    pub id: String,
+    /// The slug of the heading the exercise is under,
+    /// or empty if it comes before any heading.
+    pub section: String,
+    pub answer: Answer,
+}
+
+/// # Usage
+/// Returns the exercises in `blocks`, skipping those included
+/// from other notes. Headings must already have their slugs.
+pub fn exercises(blocks: &mut [Block]) -> Vec<Exercise> {
+    let mut out = Vec::new();
+    let mut section = String::new();
+    super::environment::blocks(blocks, &mut |block| match block {
+        Block::Heading { id: Some(id), .. } => section = id.clone(),
+        Block::Exercise {
+            id,
+            answer: Some(answer),
+            ..
+        } => out.push(Exercise {
+            id: id.clone(),
+            section: section.clone(),
+            answer: answer.clone(),
+        }),
+        _ => {}
+    });
+    return out;
+}
+
+/// # Usage
+/// Prepares the blocks of the note titled `title` being included
+/// into another, whose exercises remain graded as the included
+/// note's. Exercises it included itself keep their own note.
+pub fn adopt(blocks: &mut [Block], title: &str) {
+    super::environment::blocks(blocks, &mut |block| {
+        if let Block::Exercise { answer, note, .. } = block {
+            *answer = None;
+            note.get_or_insert_with(|| title.to_string());
+        }
+    });
+}
+
+impl<'s> Parser<'s> {
+    /// # Usage
+    /// Expands `@exercise`, found at offset `at` of `text`.
+    pub fn exercise(&mut self, call: &Call, text: &Text, at: usize) -> Vec<Block> {
+        let span = Span {
+            start: text.source(at),
+            end: text.source(at + call.len),
+        };
+        let values = match self.macros["exercise"].bind(call) {
+            Ok(values) => values,
+            Err(errors) => {
+                for (offset, message) in errors {
+                    self.report(Span::at(text.source(at + offset)), Severity::Error, message);
+                }
+                return Vec::new();
+            }
+        };
+        let (
+            Value::String(id),
+            Value::String(prompt),
+            Value::String(expected),
+            Value::Real(tolerance),
+            Value::String(hints),
+            Value::String(solution),
+        ) = (
+            &values["id"],
+            &values["prompt"],
+            &values["answer"],
+            &values["tolerance"],
+            &values["hints"],
+            &values["solution"],
+        )
+        else {
+            unreachable!("Arguments are checked by Macro::bind");
+        };
+
+        let at = self.expansion.unwrap_or(span);
+        if id.is_empty()
+            || !id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            self.report(
+                at,
+                Severity::Error,
+                format!("Invalid exercise id `{}`, use letters, digits and `-_`", id),
+            );
+        } else if !self.exercises.insert(id.clone()) {
+            self.report(
+                at,
+                Severity::Error,
+                format!("Exercise `{}` is defined twice", id),
+            );
+        }
+        if let Err((offset, message)) = math::parse(expected) {
+            self.report(
+                at,
+                Severity::Error,
+                format!("{} in `{}` at column {}", message, expected, offset + 1),
+            );
+        }
+        if *tolerance <= 0.0 || !tolerance.is_finite() {
+            self.report(at, Severity::Error, "`tolerance` should be positive");
+        }
+
+        let mut markdown = |s: &str| {
+            let lines = lexer::lines(s, 0);
+            return self.expanding(span, |parser| parser.blocks(&lines));
+        };
+        return vec![Block::Exercise {
+            id: id.clone(),
+            prompt: markdown(prompt),
+            hints: hints
+                .lines()
+                .filter(|x| !x.trim().is_empty())
+                .map(&mut markdown)
+                .collect(),
+            solution: match solution.trim().is_empty() {
+                true => Vec::new(),
+                false => markdown(solution),
+            },
+            answer: Some(Answer {
+                expected: expected.clone(),
+                tolerance: *tolerance,
+            }),
+            note: None,
+        }];
+    }
+}
@@ -0,0 +1,190 @@
+//! # Usage
+//! Flashcards, taken from the definitions and theorems of a note.
+//! Every element of the compiled HTML whose class is one of
+//! `definition`, `theorem`, `lemma`, `proposition` or `corollary`
+//! is a card, whether it was written as an [environment](super::environment)
+//! or as raw HTML:
+//! ```markdown
+//! <div class="definition" id="limit">
+//!
+//! **Limit** (of a sequence). The value its terms approach.
+//!
+//! </div>
+//! ```
+//! The bold lead in of the element, along with a title in parentheses
+//! after it, is the front of the card and the rest is the back, so
+//! the card above reads `Limit (of a sequence)` on the front.
+//! Elements without a lead in show their kind on the front instead.
+//! Cards are labelled by the id of the element, if it has one.
+
+/// The classes of elements which are cards.
+const KINDS: [&str; 5] = ["definition", "theorem", "lemma", "proposition", "corollary"];
+
+/// # Usage
+/// A card of a compiled note. The front and back are HTML.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flashcard {
+    pub front: String,
+    pub back: String,
+    /// The id of the element, or empty if it has none.
+    pub label: String,
+}
+
+/// # Usage
+/// An opening tag at the start of a string.
+struct Tag<'h> {
+    name: &'h str,
+    class: &'h str,
+    id: &'h str,
+    /// The length of the tag, up to and including `>`.
+    len: usize,
+}
+
+/// # Usage
+/// Parses the opening tag at the start of `s`. The HTML is sanitized,
+/// so attributes are always quoted with `"` and separated by a space.
+/// Their values may hold `>`, but any `"` in them is escaped.
+fn open(s: &str) -> Option<Tag<'_>> {
+    let rest = s.strip_prefix('<')?;
+    let name = &rest[..rest
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(rest.len())];
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut quoted = false;
+    let end = s.char_indices().find(|(_, c)| {
+        if *c == '"' {
+            quoted = !quoted;
+        }
+        return !quoted && *c == '>';
+    });
+    let len = end?.0 + 1;
+    let attributes = &s[1 + name.len()..len - 1];
+    let attribute = |key: &str| {
+        let pattern = format!(" {}=\"", key);
+        return attributes.find(&pattern).map_or("", |x| {
+            let value = &attributes[x + pattern.len()..];
+            &value[..value.find('"').unwrap_or(value.len())]
+        });
+    };
+    return Some(Tag {
+        name,
+        class: attribute("class"),
+        id: attribute("id"),
+        len,
+    });
+}
+
+/// # Usage
+/// Returns the offset of the tag closing an element named `name`,
+/// whose content starts at the start of `s`.
+fn close(s: &str, name: &str) -> Option<usize> {
+    let closing = format!("</{}>", name);
+    let mut depth = 1;
+    let mut i = 0;
+    while let Some(offset) = s[i..].find('<') {
+        i += offset;
+        if s[i..].starts_with(&closing) {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        } else if open(&s[i..]).is_some_and(|x| x.name == name) {
+            depth += 1;
+        }
+        i += 1;
+    }
+    return None;
+}
+
+/// # Usage
+/// Returns the length of the parenthesized title at the start
+/// of `s`, including the parentheses.
+fn parenthesized(s: &str) -> Option<usize> {
+    if !s.starts_with('(') {
+        return None;
+    }
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    return None;
+}
+
+/// # Usage
+/// Splits the content of an element into a card.
+fn card(kind: &str, label: &str, content: &str) -> Flashcard {
+    let content = content.trim();
+    let (paragraph, body) = match open(content).filter(|x| x.name == "p") {
+        Some(tag) => (&content[..tag.len], content[tag.len..].trim_start()),
+        None => ("", content),
+    };
+
+    let lead = body
+        .strip_prefix("<strong>")
+        .and_then(|x| Some((x, x.find("</strong>")?)));
+    let Some((lead, end)) = lead else {
+        return Flashcard {
+            front: kind[..1].to_uppercase() + &kind[1..],
+            back: content.to_string(),
+            label: label.to_string(),
+        };
+    };
+
+    let mut front = lead[..end].trim().trim_end_matches('.').to_string();
+    let mut rest = lead[end + "</strong>".len()..].trim_start();
+    if let Some(len) = parenthesized(rest) {
+        front.push(' ');
+        front.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+    let rest = rest.strip_prefix('.').unwrap_or(rest).trim_start();
+
+    // Drop the paragraph if the lead in was all of it
+    let back = match rest.strip_prefix("</p>") {
+        Some(rest) => rest.trim_start().to_string(),
+        None => format!("{}{}", paragraph, rest),
+    };
+    return Flashcard {
+        front,
+        back,
+        label: label.to_string(),
+    };
+}
+
+/// # Usage
+/// Returns the cards in the compiled HTML of a note, in order.
+/// Cards nested inside of others are part of the outer card.
+pub fn flashcards(html: &str) -> Vec<Flashcard> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while let Some(offset) = html[i..].find('<') {
+        i += offset;
+        let Some(tag) = open(&html[i..]) else {
+            i += 1;
+            continue;
+        };
+
+        let start = i + tag.len;
+        let kind = tag.class.split_whitespace().find(|x| KINDS.contains(x));
+        match kind.zip(close(&html[start..], tag.name)) {
+            Some((kind, len)) => {
+                out.push(card(kind, tag.id, &html[start..start + len]));
+                i = start + len;
+            }
+            None => i = start,
+        }
+    }
+    return out;
+}
@@ -0,0 +1,307 @@
+//! # Usage
+//! Highlights fenced code by language:
+//! ````markdown
+//! ```python
+//! def newton(f, df, x):
+//!     return x - f(x) / df(x)  # One step
+//! ```
+//! ````
+//! Code is split into tokens, each wrapped in a span with a class
+//! such as `hl-keyword`, which the site's themes color. Rust, Python,
+//! JavaScript and LaTeX are supported; code in any other language,
+//! or without one, is only escaped.
+
+use super::html::escape;
+
+/// # Usage
+/// How the tokens of a language are recognized.
+struct Language {
+    names: &'static [&'static str],
+    keywords: &'static [&'static str],
+    /// Built in types and constants.
+    builtins: &'static [&'static str],
+    line_comment: &'static str,
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// Whether `"""` and `'''` start strings spanning lines.
+    triple_quotes: bool,
+    /// Whether identifiers starting in uppercase are types.
+    types: bool,
+    /// Whether `name!` is a macro, as in Rust.
+    macros: bool,
+}
+
+const RUST: Language = Language {
+    names: &["rust", "rs"],
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "type",
+        "unsafe", "use", "where", "while",
+    ],
+    builtins: &[
+        "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "str", "u8",
+        "u16", "u32", "u64", "u128", "usize", "true", "false",
+    ],
+    line_comment: "//",
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"'],
+    triple_quotes: false,
+    types: true,
+    macros: true,
+};
+
+const PYTHON: Language = Language {
+    names: &["python", "py"],
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is",
+        "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with",
+        "yield",
+    ],
+    builtins: &[
+        "True", "False", "None", "int", "float", "str", "list", "dict", "tuple", "set", "range",
+        "len", "print", "abs", "min", "max", "sum",
+    ],
+    line_comment: "#",
+    block_comment: None,
+    quotes: &['"', '\''],
+    triple_quotes: true,
+    types: true,
+    macros: false,
+};
+
+const JAVASCRIPT: Language = Language {
+    names: &["javascript", "js", "typescript", "ts"],
+    keywords: &[
+        "async",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "export",
+        "extends",
+        "finally",
+        "for",
+        "function",
+        "if",
+        "import",
+        "in",
+        "instanceof",
+        "let",
+        "new",
+        "of",
+        "return",
+        "switch",
+        "this",
+        "throw",
+        "try",
+        "typeof",
+        "var",
+        "void",
+        "while",
+        "yield",
+    ],
+    builtins: &[
+        "true",
+        "false",
+        "null",
+        "undefined",
+        "NaN",
+        "Infinity",
+        "Math",
+        "console",
+        "number",
+        "string",
+        "boolean",
+    ],
+    line_comment: "//",
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    triple_quotes: false,
+    types: true,
+    macros: false,
+};
+
+const LANGUAGES: [&Language; 3] = [&RUST, &PYTHON, &JAVASCRIPT];
+
+/// # Usage
+/// Highlights `code` written in `lang`, returning HTML to be
+/// placed inside of `<code>`, or `None` for unknown languages.
+pub fn highlight(lang: &str, code: &str) -> Option<String> {
+    let lang = lang.to_ascii_lowercase();
+    if matches!(lang.as_str(), "latex" | "tex") {
+        return Some(latex(code));
+    }
+
+    let language = LANGUAGES
+        .iter()
+        .find(|x| x.names.contains(&lang.as_str()))?;
+    return Some(tokens(language, code));
+}
+
+/// # Usage
+/// Wraps `text` in a span of the given class.
+fn span(out: &mut String, class: &str, text: &str) {
+    out.push_str(&format!(
+        "<span class=\"hl-{}\">{}</span>",
+        class,
+        escape(text)
+    ));
+}
+
+fn is_ident(c: char) -> bool {
+    return c.is_alphanumeric() || c == '_';
+}
+
+/// # Usage
+/// Returns the length of the string starting at the start of `s`,
+/// which is unclosed if the code ends first. Backslashes escape.
+fn string(s: &str, close: &str) -> usize {
+    let mut chars = s.char_indices().skip(close.chars().count());
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if s[i..].starts_with(close) {
+            return i + close.len();
+        } else if c == '\n' && close.len() == 1 && !close.starts_with('`') {
+            return i;
+        }
+    }
+    return s.len();
+}
+
+fn tokens(language: &Language, code: &str) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < code.len() {
+        let rest = &code[i..];
+        let c = rest.chars().next().unwrap();
+
+        if rest.starts_with(language.line_comment) {
+            let len = rest.find('\n').unwrap_or(rest.len());
+            span(&mut out, "comment", &rest[..len]);
+            i += len;
+        } else if let Some((open, close)) = language.block_comment.filter(|x| rest.starts_with(x.0))
+        {
+            let len = rest[open.len()..]
+                .find(close)
+                .map_or(rest.len(), |x| open.len() + x + close.len());
+            span(&mut out, "comment", &rest[..len]);
+            i += len;
+        } else if language.triple_quotes && (rest.starts_with("\"\"\"") || rest.starts_with("'''"))
+        {
+            let len = string(rest, &rest[..3]);
+            span(&mut out, "string", &rest[..len]);
+            i += len;
+        } else if language.quotes.contains(&c) {
+            let len = string(rest, &rest[..1]);
+            span(&mut out, "string", &rest[..len]);
+            i += len;
+        } else if c == '\'' && language.macros {
+            // A character in Rust, rather than a lifetime as in `'a`
+            let mut chars = rest[1..].chars();
+            let len = match (chars.next(), chars.next()) {
+                (Some('\\'), _) => string(rest, "'"),
+                (Some(x), Some('\'')) => x.len_utf8() + 2,
+                _ => 0,
+            };
+            match len {
+                0 => {
+                    out.push('\'');
+                    i += 1;
+                }
+                _ => {
+                    span(&mut out, "string", &rest[..len]);
+                    i += len;
+                }
+            }
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|x: char| !(is_ident(x) || x == '.'))
+                .unwrap_or(rest.len());
+            // Leave ranges such as `0..n` to be split
+            let len = rest[..len].find("..").unwrap_or(len);
+            span(&mut out, "number", &rest[..len]);
+            i += len;
+        } else if is_ident(c) {
+            let len = rest.find(|x: char| !is_ident(x)).unwrap_or(rest.len());
+            let word = &rest[..len];
+            let after = &rest[len..];
+            if language.keywords.contains(&word) {
+                span(&mut out, "keyword", word);
+            } else if language.builtins.contains(&word) {
+                span(&mut out, "builtin", word);
+            } else if language.macros && after.starts_with('!') && !after.starts_with("!=") {
+                span(&mut out, "macro", &rest[..len + 1]);
+                i += 1;
+            } else if after.trim_start().starts_with('(') {
+                span(&mut out, "function", word);
+            } else if language.types && c.is_uppercase() {
+                span(&mut out, "type", word);
+            } else {
+                out.push_str(word);
+            }
+            i += len;
+        } else {
+            out.push_str(&escape(&rest[..c.len_utf8()]));
+            i += c.len_utf8();
+        }
+    }
+
+    return out;
+}
+
+/// # Usage
+/// Highlights LaTeX: commands, comments, math and numbers.
+fn latex(code: &str) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < code.len() {
+        let rest = &code[i..];
+        let c = rest.chars().next().unwrap();
+        match c {
+            '%' => {
+                let len = rest.find('\n').unwrap_or(rest.len());
+                span(&mut out, "comment", &rest[..len]);
+                i += len;
+            }
+            '\\' => {
+                let letters = rest[1..]
+                    .find(|x: char| !x.is_ascii_alphabetic())
+                    .unwrap_or(rest.len() - 1);
+                // Commands are letters, or else a single symbol as in `\{`
+                let len = match letters {
+                    0 => 1 + rest[1..].chars().next().map_or(0, |x| x.len_utf8()),
+                    _ => 1 + letters,
+                };
+                span(&mut out, "command", &rest[..len]);
+                i += len;
+            }
+            '$' | '{' | '}' | '&' | '^' | '_' => {
+                span(&mut out, "keyword", &rest[..1]);
+                i += 1;
+            }
+            '0'..='9' => {
+                let len = rest
+                    .find(|x: char| !(x.is_ascii_digit() || x == '.'))
+                    .unwrap_or(rest.len());
+                span(&mut out, "number", &rest[..len]);
+                i += len;
+            }
+            _ => {
+                out.push_str(&escape(&rest[..c.len_utf8()]));
+                i += c.len_utf8();
+            }
+        }
+    }
+
+    return out;
+}
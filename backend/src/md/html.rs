@@ -0,0 +1,322 @@
+//! # Usage
+//! Renders the [syntax tree](super::ast) to HTML.
+
+use super::{
+    ast::{Block, Inline},
+    cite::Work,
+    highlight::highlight,
+};
+
+/// # Usage
+/// Escapes text for use in HTML content and attribute values.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    return out;
+}
+
+/// # Usage
+/// Renders a list of blocks.
+pub fn render(blocks: &[Block]) -> String {
+    let mut out = String::new();
+    for block in blocks {
+        block_html(&mut out, block, false);
+    }
+    return out;
+}
+
+fn block_html(out: &mut String, block: &Block, tight: bool) {
+    match block {
+        Block::Heading { level, content, id } => {
+            match id {
+                Some(id) => out.push_str(&format!("<h{} id=\"{}\">", level, escape(id))),
+                None => out.push_str(&format!("<h{}>", level)),
+            }
+            inlines_html(out, content);
+            out.push_str(&format!("</h{}>\n", level));
+        }
+        Block::Paragraph(content) => {
+            if tight {
+                inlines_html(out, content);
+                out.push('\n');
+            } else {
+                out.push_str("<p>");
+                inlines_html(out, content);
+                out.push_str("</p>\n");
+            }
+        }
+        Block::Quote(blocks) => {
+            out.push_str("<blockquote>\n");
+            for block in blocks {
+                block_html(out, block, false);
+            }
+            out.push_str("</blockquote>\n");
+        }
+        Block::List {
+            start,
+            tight: is_tight,
+            items,
+        } => {
+            let tag = match start {
+                Some(1) => {
+                    out.push_str("<ol>\n");
+                    "ol"
+                }
+                Some(n) => {
+                    out.push_str(&format!("<ol start=\"{}\">\n", n));
+                    "ol"
+                }
+                None => {
+                    out.push_str("<ul>\n");
+                    "ul"
+                }
+            };
+            for item in items {
+                out.push_str("<li>");
+                if !*is_tight {
+                    out.push('\n');
+                }
+                for block in item {
+                    block_html(out, block, *is_tight);
+                }
+                // Tight items end with a newline from their last paragraph
+                if out.ends_with('\n') && *is_tight {
+                    out.pop();
+                }
+                out.push_str("</li>\n");
+            }
+            out.push_str(&format!("</{}>\n", tag));
+        }
+        Block::Code { lang, text } => {
+            match lang {
+                Some(lang) => {
+                    out.push_str(&format!("<pre><code class=\"language-{}\">", escape(lang)))
+                }
+                None => out.push_str("<pre><code>"),
+            }
+            match lang.as_deref().and_then(|x| highlight(x, text)) {
+                Some(html) => out.push_str(&html),
+                None => out.push_str(&escape(text)),
+            }
+            out.push_str("</code></pre>\n");
+        }
+        Block::Html(html) | Block::Math { mathml: html, .. } => {
+            out.push_str(html);
+            out.push('\n');
+        }
+        Block::Environment {
+            kind,
+            title,
+            label,
+            number,
+            content,
+            ..
+        } => {
+            match label {
+                Some(label) => out.push_str(&format!(
+                    "<section class=\"{}\" id=\"{}\">\n",
+                    kind.name(),
+                    escape(label)
+                )),
+                None => out.push_str(&format!("<section class=\"{}\">\n", kind.name())),
+            }
+            out.push_str("<p class=\"environment-head\"><strong>");
+            out.push_str(&kind.heading());
+            if let Some(number) = number {
+                out.push_str(&format!(" {}", number));
+            }
+            out.push_str("</strong>");
+            if !title.is_empty() {
+                out.push_str(" (");
+                inlines_html(out, title);
+                out.push(')');
+            }
+            out.push_str(".</p>\n");
+            for block in content {
+                block_html(out, block, false);
+            }
+            out.push_str("</section>\n");
+        }
+        Block::Exercise {
+            id,
+            prompt,
+            hints,
+            solution,
+            note,
+            ..
+        } => {
+            out.push_str(&format!(
+                "<section class=\"exercise\" id=\"exercise-{}\"{}>\n",
+                escape(id),
+                note.as_ref()
+                    .map_or(String::new(), |x| format!(" data-note=\"{}\"", escape(x)))
+            ));
+            out.push_str("<p class=\"environment-head\"><strong>Exercise</strong>.</p>\n");
+            out.push_str(&render(prompt));
+            for (i, hint) in hints.iter().enumerate() {
+                let summary = match hints.len() {
+                    1 => "Hint".to_string(),
+                    _ => format!("Hint {}", i + 1),
+                };
+                details(out, "hint", &summary, hint);
+            }
+            if !solution.is_empty() {
+                details(out, "solution", "Solution", solution);
+            }
+            out.push_str("</section>\n");
+        }
+        Block::Footnotes(footnotes) => {
+            out.push_str("<section class=\"footnotes\">\n<ol>\n");
+            for (i, footnote) in footnotes.iter().enumerate() {
+                out.push_str(&format!("<li id=\"fn-{}\">", i + 1));
+                inlines_html(out, footnote);
+                out.push_str("</li>\n");
+            }
+            out.push_str("</ol>\n</section>\n");
+        }
+        Block::Bibliography(works) => {
+            out.push_str("<section class=\"bibliography\">\n<h2>References</h2>\n<ol>\n");
+            for (i, work) in works.iter().enumerate() {
+                out.push_str(&format!("<li id=\"ref-{}\">", i + 1));
+                work_html(out, work);
+                out.push_str("</li>\n");
+            }
+            out.push_str("</ol>\n</section>\n");
+        }
+        Block::Rule => out.push_str("<hr>\n"),
+    }
+}
+
+/// # Usage
+/// Renders blocks which are hidden until the summary is clicked.
+fn details(out: &mut String, class: &str, summary: &str, blocks: &[Block]) {
+    out.push_str(&format!(
+        "<details class=\"{}\">\n<summary>{}</summary>\n",
+        class, summary
+    ));
+    out.push_str(&render(blocks));
+    out.push_str("</details>\n");
+}
+
+/// # Usage
+/// Renders an entry of the bibliography, as in
+/// `Stewart (2015). <em>Calculus</em>. Cengage.`
+fn work_html(out: &mut String, work: &Work) {
+    let mut parts = Vec::new();
+    match (work.author.is_empty(), work.year) {
+        (false, 0) => parts.push(escape(&work.author)),
+        (false, year) => parts.push(format!("{} ({})", escape(&work.author), year)),
+        (true, 0) => {}
+        (true, year) => parts.push(year.to_string()),
+    }
+    if !work.title.is_empty() {
+        parts.push(format!("<em>{}</em>", escape(&work.title)));
+    }
+    if !work.publisher.is_empty() {
+        parts.push(escape(&work.publisher));
+    }
+    out.push_str(&parts.join(". "));
+    if !parts.is_empty() {
+        out.push('.');
+    }
+    if !work.url.is_empty() {
+        out.push_str(&format!(
+            " <a href=\"{}\">{}</a>",
+            escape(&work.url),
+            escape(&work.url)
+        ));
+    }
+}
+
+fn inlines_html(out: &mut String, inlines: &[Inline]) {
+    for inline in inlines {
+        match inline {
+            Inline::Text(text) => out.push_str(&escape(text)),
+            Inline::Emphasis(content) => {
+                out.push_str("<em>");
+                inlines_html(out, content);
+                out.push_str("</em>");
+            }
+            Inline::Strong(content) => {
+                out.push_str("<strong>");
+                inlines_html(out, content);
+                out.push_str("</strong>");
+            }
+            Inline::Code(code) => {
+                out.push_str("<code>");
+                out.push_str(&escape(code));
+                out.push_str("</code>");
+            }
+            Inline::Link {
+                href,
+                title,
+                content,
+            } => {
+                out.push_str(&format!("<a href=\"{}\"", escape(href)));
+                if let Some(title) = title {
+                    out.push_str(&format!(" title=\"{}\"", escape(title)));
+                }
+                out.push('>');
+                inlines_html(out, content);
+                out.push_str("</a>");
+            }
+            Inline::Image { src, title, alt } => {
+                out.push_str(&format!(
+                    "<img src=\"{}\" alt=\"{}\"",
+                    escape(src),
+                    escape(alt)
+                ));
+                if let Some(title) = title {
+                    out.push_str(&format!(" title=\"{}\"", escape(title)));
+                }
+                out.push('>');
+            }
+            Inline::Html(html) | Inline::Math { mathml: html, .. } => out.push_str(html),
+            Inline::Ref { label, text, .. } => match text {
+                Some(text) => out.push_str(&format!(
+                    "<a class=\"ref\" href=\"#{}\">{}</a>",
+                    escape(label),
+                    escape(text)
+                )),
+                None => out.push_str(&format!("<a class=\"ref\">{}</a>", escape(label))),
+            },
+            Inline::Footnote { label, number, .. } => match number {
+                Some(n) => out.push_str(&format!(
+                    "<sup class=\"footnote-ref\"><a href=\"#fn-{}\">{}</a></sup>",
+                    n, n
+                )),
+                None => out.push_str(&format!(
+                    "<sup class=\"footnote-ref\">{}</sup>",
+                    escape(label)
+                )),
+            },
+            Inline::Citation { cites, .. } => {
+                out.push_str("<span class=\"citation\">[");
+                for (i, cite) in cites.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str("; ");
+                    }
+                    match cite.number {
+                        Some(n) => out.push_str(&format!("<a href=\"#ref-{}\">{}</a>", n, n)),
+                        None => out.push_str(&escape(&cite.key)),
+                    }
+                    if let Some(locator) = &cite.locator {
+                        out.push_str(", ");
+                        out.push_str(&escape(locator));
+                    }
+                }
+                out.push_str("]</span>");
+            }
+            Inline::SoftBreak => out.push('\n'),
+            Inline::LineBreak => out.push_str("<br>\n"),
+        }
+    }
+}
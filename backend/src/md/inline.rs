@@ -0,0 +1,822 @@
+//! # Usage
+//! Parses the inline content of paragraphs and headings:
+//! emphasis, code spans, links, images, autolinks and raw HTML.
+
+use super::{
+    ast::Inline,
+    cite, environment,
+    lexer::Line,
+    links, macros,
+    parser::{self, Parser},
+    tex, Severity, Span,
+};
+
+/// # Usage
+/// The text of a paragraph with its lines joined by `\n`.
+/// Keeps track of where each line came from so that
+/// positions can be mapped back onto the source.
+pub struct Text {
+    pub s: String,
+    /// Pairs of (offset in `s`, offset in source), one per line.
+    map: Vec<(usize, usize)>,
+}
+
+impl Text {
+    /// # Usage
+    /// Joins the lines of a paragraph, trimming the
+    /// indentation of each and the end of the last.
+    pub fn new(lines: &[Line]) -> Self {
+        let mut s = String::new();
+        let mut map = Vec::new();
+
+        for (i, line) in lines.iter().enumerate() {
+            let line = line.trim_start();
+            if i != 0 {
+                s.push('\n');
+            }
+            map.push((s.len(), line.offset));
+            if i == lines.len() - 1 {
+                s.push_str(line.text.trim_end());
+            } else {
+                s.push_str(line.text);
+            }
+        }
+
+        return Text { s, map };
+    }
+
+    /// # Usage
+    /// Joins lines as they are, only trimming the
+    /// indentation of the first.
+    pub fn raw(lines: &[Line]) -> Self {
+        let mut s = String::new();
+        let mut map = Vec::new();
+
+        for (i, line) in lines.iter().enumerate() {
+            let line = if i == 0 { line.trim_start() } else { *line };
+            if i != 0 {
+                s.push('\n');
+            }
+            map.push((s.len(), line.offset));
+            s.push_str(line.text);
+        }
+
+        return Text { s, map };
+    }
+
+    /// # Usage
+    /// Text that does not come from the source, such as
+    /// the output of a macro.
+    pub fn plain(s: String) -> Self {
+        return Text {
+            s,
+            map: vec![(0, 0)],
+        };
+    }
+
+    /// # Usage
+    /// Maps an offset in the joined text onto the source.
+    pub fn source(&self, i: usize) -> usize {
+        let (start, offset) = self
+            .map
+            .iter()
+            .rev()
+            .find(|x| x.0 <= i)
+            .copied()
+            .unwrap_or((0, 0));
+        return offset + (i - start);
+    }
+}
+
+enum Item {
+    Inline(Inline),
+    Delim {
+        ch: char,
+        count: usize,
+        orig: usize,
+        open: bool,
+        close: bool,
+    },
+}
+
+fn char_at(s: &str, i: usize) -> Option<char> {
+    return s.get(i..).and_then(|x| x.chars().next());
+}
+
+fn char_before(s: &str, i: usize) -> Option<char> {
+    return s.get(..i).and_then(|x| x.chars().next_back());
+}
+
+fn is_punct(c: char) -> bool {
+    return c.is_ascii_punctuation() || (!c.is_alphanumeric() && !c.is_whitespace());
+}
+
+impl<'s> Parser<'s> {
+    /// # Usage
+    /// Parses the content of a paragraph or heading.
+    pub fn inline(&mut self, lines: &[Line]) -> Vec<Inline> {
+        let text = Text::new(lines);
+        return self.inlines(&text, 0, text.s.len());
+    }
+
+    /// # Usage
+    /// Parses the range `start..end` of `text`. The text of links
+    /// is parsed recursively, and left as it is once content is
+    /// nested [too deeply](parser::MAX_NESTING).
+    pub fn inlines(&mut self, text: &Text, start: usize, end: usize) -> Vec<Inline> {
+        if self.nesting == parser::MAX_NESTING {
+            self.report(
+                Span::at(text.source(start)),
+                Severity::Error,
+                "Content is nested too deeply",
+            );
+            return vec![Inline::Text(text.s[start..end].to_string())];
+        }
+        self.nesting += 1;
+        let out = self.spans(text, start, end);
+        self.nesting -= 1;
+        return out;
+    }
+
+    fn spans(&mut self, text: &Text, start: usize, end: usize) -> Vec<Inline> {
+        let s = text.s.as_str();
+        let mut items: Vec<Item> = Vec::new();
+        let mut buf = String::new();
+        let mut i = start;
+
+        macro_rules! emit {
+            ($inline:expr) => {{
+                if !buf.is_empty() {
+                    items.push(Item::Inline(Inline::Text(std::mem::take(&mut buf))));
+                }
+                items.push(Item::Inline($inline));
+            }};
+        }
+
+        while i < end {
+            let c = char_at(s, i).unwrap();
+            match c {
+                '\\' => match char_at(s, i + 1).filter(|_| i + 1 < end) {
+                    Some('\n') => {
+                        emit!(Inline::LineBreak);
+                        i += 2;
+                    }
+                    Some(n) if n.is_ascii_punctuation() => {
+                        buf.push(n);
+                        i += 2;
+                    }
+                    _ => {
+                        buf.push('\\');
+                        i += 1;
+                    }
+                },
+                '`' => {
+                    let (inline, next) = code_span(s, i, end);
+                    match inline {
+                        Some(inline) => emit!(inline),
+                        None => {
+                            self.report(
+                                Span::at(text.source(i)),
+                                Severity::Warning,
+                                "Code span is never closed",
+                            );
+                            buf.push_str(&s[i..next]);
+                        }
+                    }
+                    i = next;
+                }
+                '*' | '_' => {
+                    let count = s[i..end].chars().take_while(|x| *x == c).count();
+                    let before = char_before(s, i).filter(|_| i > start).unwrap_or(' ');
+                    let after = char_at(s, i + count)
+                        .filter(|_| i + count < end)
+                        .unwrap_or(' ');
+
+                    let left = !after.is_whitespace()
+                        && (!is_punct(after) || before.is_whitespace() || is_punct(before));
+                    let right = !before.is_whitespace()
+                        && (!is_punct(before) || after.is_whitespace() || is_punct(after));
+                    let (open, close) = if c == '*' {
+                        (left, right)
+                    } else {
+                        (
+                            left && (!right || is_punct(before)),
+                            right && (!left || is_punct(after)),
+                        )
+                    };
+
+                    if !buf.is_empty() {
+                        items.push(Item::Inline(Inline::Text(std::mem::take(&mut buf))));
+                    }
+                    items.push(Item::Delim {
+                        ch: c,
+                        count,
+                        orig: count,
+                        open,
+                        close,
+                    });
+                    i += count;
+                }
+                '!' if char_at(s, i + 1) == Some('[') => match self.link(text, i + 1, end) {
+                    Some((href, title, content, next)) => {
+                        let alt = Inline::plain(&self.inlines(text, content.0, content.1));
+                        emit!(Inline::Image {
+                            src: href,
+                            title,
+                            alt,
+                        });
+                        i = next;
+                    }
+                    None => {
+                        buf.push('!');
+                        i += 1;
+                    }
+                },
+                '[' if s[i..end].starts_with("[[") && links::wiki(&s[i..end]).is_some() => {
+                    let link = links::wiki(&s[i..end]).unwrap();
+                    let inline = self.wiki_link(&link, text, i);
+                    emit!(inline);
+                    i += link.len;
+                }
+                '[' if cite::footnote(&s[i..end]).is_some() => {
+                    let (label, len) = cite::footnote(&s[i..end]).unwrap();
+                    let span = Span {
+                        start: text.source(i),
+                        end: text.source(i + len),
+                    };
+                    emit!(Inline::Footnote {
+                        label,
+                        span: self.expansion.unwrap_or(span),
+                        content: None,
+                        number: None,
+                    });
+                    i += len;
+                }
+                '[' if cite::citation(&s[i..end]).is_some() => {
+                    let (cites, len) = cite::citation(&s[i..end]).unwrap();
+                    let span = Span {
+                        start: text.source(i),
+                        end: text.source(i + len),
+                    };
+                    emit!(Inline::Citation {
+                        cites,
+                        span: self.expansion.unwrap_or(span),
+                    });
+                    i += len;
+                }
+                '[' => match self.link(text, i, end) {
+                    Some((href, title, content, next)) => {
+                        let content = self.inlines(text, content.0, content.1);
+                        emit!(Inline::Link {
+                            href,
+                            title,
+                            content,
+                        });
+                        i = next;
+                    }
+                    None => {
+                        buf.push('[');
+                        i += 1;
+                    }
+                },
+                '<' => match angle(&s[i..end]) {
+                    Some((inline, len)) => {
+                        if let Inline::Html(html) = &inline {
+                            self.html_tags(html, |x| text.source(i + x));
+                        }
+                        emit!(inline);
+                        i += len;
+                    }
+                    None => {
+                        buf.push('<');
+                        i += 1;
+                    }
+                },
+                '@' if s[i..end].starts_with("@ref(") => match environment::reference(&s[i..end]) {
+                    Some((label, len)) => {
+                        let span = Span {
+                            start: text.source(i),
+                            end: text.source(i + len),
+                        };
+                        emit!(Inline::Ref {
+                            label,
+                            span: self.expansion.unwrap_or(span),
+                            text: None,
+                        });
+                        i += len;
+                    }
+                    None => {
+                        self.report(
+                            Span::at(text.source(i)),
+                            Severity::Error,
+                            "Expected a label, as in `@ref(label)`",
+                        );
+                        buf.push('@');
+                        i += 1;
+                    }
+                },
+                '@' if !char_before(s, i)
+                    .filter(|_| i > start)
+                    .is_some_and(|x| x.is_alphanumeric()) =>
+                {
+                    match macros::call(&s[i..end]) {
+                        Ok(Some(call)) => {
+                            let span = Span {
+                                start: text.source(i),
+                                end: text.source(i + call.len),
+                            };
+                            match self.invoke(&call, text, i) {
+                                Some(expanded) => {
+                                    let expanded = Text::plain(expanded);
+                                    let inner = self.expanding(span, |parser| {
+                                        parser.inlines(&expanded, 0, expanded.s.len())
+                                    });
+                                    for inline in inner {
+                                        emit!(inline);
+                                    }
+                                }
+                                None => buf.push_str(&s[i..i + call.len]),
+                            }
+                            i += call.len;
+                        }
+                        Ok(None) => {
+                            buf.push('@');
+                            i += 1;
+                        }
+                        Err((offset, message)) => {
+                            self.report(
+                                Span::at(text.source(i + offset)),
+                                Severity::Error,
+                                message,
+                            );
+                            buf.push('@');
+                            i += 1;
+                        }
+                    }
+                }
+                '$' => {
+                    let display = s[i + 1..end].starts_with('$');
+                    let open = if display { 2 } else { 1 };
+                    match math_end(&s[..end], i + open, display) {
+                        Some(close) => {
+                            let body = &s[i + open..close];
+                            match tex::to_mathml(body, display) {
+                                Ok(mathml) => emit!(Inline::Math {
+                                    tex: body.to_string(),
+                                    mathml,
+                                }),
+                                Err((offset, message)) => {
+                                    self.report(
+                                        Span::at(text.source(i + open + offset)),
+                                        Severity::Error,
+                                        message,
+                                    );
+                                    emit!(Inline::Code(body.to_string()));
+                                }
+                            }
+                            i = close + open;
+                        }
+                        None => {
+                            if display {
+                                self.report(
+                                    Span::at(text.source(i)),
+                                    Severity::Error,
+                                    "Math is never closed with `$$`",
+                                );
+                            }
+                            buf.push_str(&s[i..i + open]);
+                            i += open;
+                        }
+                    }
+                }
+                '&' => match entity(&s[i..end]) {
+                    Some(len) => {
+                        emit!(Inline::Html(s[i..i + len].to_string()));
+                        i += len;
+                    }
+                    None => {
+                        buf.push('&');
+                        i += 1;
+                    }
+                },
+                '\n' => {
+                    let trimmed = buf.trim_end_matches(' ').len();
+                    let hard = buf.len() - trimmed >= 2;
+                    buf.truncate(trimmed);
+                    emit!(if hard {
+                        Inline::LineBreak
+                    } else {
+                        Inline::SoftBreak
+                    });
+                    i += 1;
+                }
+                _ => {
+                    buf.push(c);
+                    i += c.len_utf8();
+                }
+            }
+        }
+
+        if !buf.is_empty() {
+            items.push(Item::Inline(Inline::Text(buf)));
+        }
+
+        emphasis(&mut items);
+        return finalize(items);
+    }
+
+    /// # Usage
+    /// Attempts to parse a link starting at the `[` at `i`.
+    /// Returns the destination, title, range of the link text
+    /// and the offset just past the link.
+    #[allow(clippy::type_complexity)]
+    fn link(
+        &mut self,
+        text: &Text,
+        i: usize,
+        end: usize,
+    ) -> Option<(String, Option<String>, (usize, usize), usize)> {
+        let s = &text.s[..end];
+        let close = bracket(s, i)?;
+        let content = (i + 1, close);
+        let after = close + 1;
+
+        // Inline link: [text](destination "title")
+        if char_at(s, after) == Some('(') {
+            if let Some((href, title, next)) = destination(s, after + 1) {
+                return Some((href, title, content, next));
+            }
+        }
+
+        // Reference links: [text][label], [label][] and [label]
+        let (name, next) = if char_at(s, after) == Some('[') {
+            let end = bracket(s, after)?;
+            let name = &s[after + 1..end];
+            if name.trim().is_empty() {
+                (&s[content.0..content.1], end + 1)
+            } else {
+                (name, end + 1)
+            }
+        } else {
+            (&s[content.0..content.1], after)
+        };
+
+        let def = self.links.get(&parser::label(name))?;
+        return Some((def.href.clone(), def.title.clone(), content, next));
+    }
+}
+
+/// # Usage
+/// Finds the closing `$` or `$$` of math whose content starts at `i`.
+/// Like pandoc, inline math may not start or end with whitespace and
+/// may not be followed by a digit, so that prices such as "$5 and $10"
+/// remain text.
+fn math_end(s: &str, i: usize, display: bool) -> Option<usize> {
+    if !display && char_at(s, i).is_none_or(|x| x.is_whitespace()) {
+        return None;
+    }
+
+    let mut j = i;
+    while j < s.len() {
+        let c = char_at(s, j)?;
+        match c {
+            '\\' => {
+                j += 1 + char_at(s, j + 1).map(|x| x.len_utf8()).unwrap_or(0);
+                continue;
+            }
+            '$' if display && s[j..].starts_with("$$") => return Some(j),
+            '$' if display => {}
+            '$' => {
+                let before = char_before(s, j).unwrap_or(' ');
+                let after = char_at(s, j + 1).unwrap_or(' ');
+                if j > i && !before.is_whitespace() && !after.is_ascii_digit() {
+                    return Some(j);
+                }
+            }
+            _ => {}
+        }
+        j += c.len_utf8();
+    }
+    return None;
+}
+
+/// # Usage
+/// Finds the `]` matching the `[` at `i`, skipping over
+/// escapes, code spans and nested brackets.
+fn bracket(s: &str, i: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut j = i;
+    while j < s.len() {
+        let c = char_at(s, j)?;
+        match c {
+            '\\' => {
+                j += 1 + char_at(s, j + 1).map(|x| x.len_utf8()).unwrap_or(0);
+                continue;
+            }
+            '`' => {
+                let (_, next) = code_span(s, j, s.len());
+                j = next;
+                continue;
+            }
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(j);
+                }
+            }
+            _ => {}
+        }
+        j += c.len_utf8();
+    }
+    return None;
+}
+
+/// # Usage
+/// Parses `destination "title")` following the `(` of an inline link.
+fn destination(s: &str, i: usize) -> Option<(String, Option<String>, usize)> {
+    let skip = |j: usize| j + s[j..].len() - s[j..].trim_start().len();
+    let mut j = skip(i);
+
+    let href = if char_at(s, j) == Some('<') {
+        let end = s[j..].find('>')? + j;
+        let href = &s[j + 1..end];
+        if href.contains(['\n', '<']) {
+            return None;
+        }
+        j = end + 1;
+        href.to_string()
+    } else {
+        let begin = j;
+        let mut depth = 0;
+        while let Some(c) = char_at(s, j) {
+            match c {
+                '\\' => {
+                    j += 1 + char_at(s, j + 1).map(|x| x.len_utf8()).unwrap_or(0);
+                    continue;
+                }
+                '(' => depth += 1,
+                ')' if depth == 0 => break,
+                ')' => depth -= 1,
+                c if c.is_whitespace() || c.is_control() => break,
+                _ => {}
+            }
+            j += c.len_utf8();
+        }
+        unescape(&s[begin..j])
+    };
+
+    let after = j;
+    j = skip(j);
+    let mut title = None;
+    if j > after {
+        let close = match char_at(s, j) {
+            Some('"') => Some('"'),
+            Some('\'') => Some('\''),
+            Some('(') => Some(')'),
+            _ => None,
+        };
+        if let Some(close) = close {
+            let end = s[j + 1..].find(close)? + j + 1;
+            title = Some(unescape(&s[j + 1..end]));
+            j = skip(end + 1);
+        }
+    }
+
+    if char_at(s, j) != Some(')') {
+        return None;
+    }
+
+    return Some((href, title, j + 1));
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek().is_some_and(|x| x.is_ascii_punctuation()) {
+            out.push(chars.next().unwrap());
+        } else {
+            out.push(c);
+        }
+    }
+    return out;
+}
+
+/// # Usage
+/// Parses a code span starting at the backtick run at `i`.
+/// Returns the span (if it is closed) and the offset past it.
+/// Unclosed runs are literal text.
+fn code_span(s: &str, i: usize, end: usize) -> (Option<Inline>, usize) {
+    let count = s[i..end].chars().take_while(|x| *x == '`').count();
+    let mut j = i + count;
+
+    while j < end {
+        match s[j..end].find('`') {
+            None => break,
+            Some(k) => {
+                let run_start = j + k;
+                let run = s[run_start..end].chars().take_while(|x| *x == '`').count();
+                if run == count {
+                    let mut code = s[i + count..run_start].replace('\n', " ");
+                    if code.len() >= 2
+                        && code.starts_with(' ')
+                        && code.ends_with(' ')
+                        && !code.trim().is_empty()
+                    {
+                        code = code[1..code.len() - 1].to_string();
+                    }
+                    return (Some(Inline::Code(code)), run_start + run);
+                }
+                j = run_start + run;
+            }
+        }
+    }
+
+    return (None, i + count);
+}
+
+/// # Usage
+/// Parses an autolink or raw HTML tag at the start of `s`,
+/// returning it with its length.
+fn angle(s: &str) -> Option<(Inline, usize)> {
+    if s.starts_with("<!--") {
+        let end = s.find("-->")? + 3;
+        return Some((Inline::Html(s[..end].to_string()), end));
+    }
+
+    let close = s.find('>');
+
+    // Autolinks
+    if let Some(close) = close {
+        let inner = &s[1..close];
+        if !inner.contains(|c: char| c.is_whitespace() || c == '<') {
+            if let Some(colon) = inner.find(':') {
+                let scheme = &inner[..colon];
+                if (2..=32).contains(&scheme.len())
+                    && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && scheme
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c))
+                {
+                    return Some((
+                        Inline::Link {
+                            href: inner.to_string(),
+                            title: None,
+                            content: vec![Inline::Text(inner.to_string())],
+                        },
+                        close + 1,
+                    ));
+                }
+            }
+
+            if inner.matches('@').count() == 1
+                && !inner.starts_with('@')
+                && !inner.ends_with('@')
+                && !inner.contains('/')
+            {
+                return Some((
+                    Inline::Link {
+                        href: format!("mailto:{}", inner),
+                        title: None,
+                        content: vec![Inline::Text(inner.to_string())],
+                    },
+                    close + 1,
+                ));
+            }
+        }
+    }
+
+    // Open and closing tags
+    let rest = s[1..].strip_prefix('/').unwrap_or(&s[1..]);
+    if !rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let mut quote: Option<char> = None;
+    for (j, c) in s.char_indices().skip(1) {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('<', None) => return None,
+            ('>', None) => return Some((Inline::Html(s[..=j].to_string()), j + 1)),
+            _ => {}
+        }
+    }
+
+    return None;
+}
+
+/// # Usage
+/// Returns the length of an HTML entity at the start of `s`.
+fn entity(s: &str) -> Option<usize> {
+    let end = s.find(';')?;
+    let body = &s[1..end];
+    let valid = if let Some(hex) = body.strip_prefix("#x").or(body.strip_prefix("#X")) {
+        (1..=6).contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    } else if let Some(dec) = body.strip_prefix('#') {
+        (1..=7).contains(&dec.len()) && dec.chars().all(|c| c.is_ascii_digit())
+    } else {
+        (2..=32).contains(&body.len())
+            && body.starts_with(|c: char| c.is_ascii_alphabetic())
+            && body.chars().all(|c| c.is_ascii_alphanumeric())
+    };
+
+    return if valid { Some(end + 1) } else { None };
+}
+
+/// # Usage
+/// Matches delimiter runs into emphasis and strong emphasis,
+/// following the CommonMark delimiter algorithm.
+fn emphasis(items: &mut Vec<Item>) {
+    let mut closer = 0;
+
+    while closer < items.len() {
+        let (ch, count, orig, open) = match items[closer] {
+            Item::Delim {
+                ch,
+                count,
+                orig,
+                open,
+                close: true,
+            } if count > 0 => (ch, count, orig, open),
+            _ => {
+                closer += 1;
+                continue;
+            }
+        };
+
+        let opener = (0..closer).rev().find(|o| match items[*o] {
+            Item::Delim {
+                ch: c,
+                count: n,
+                orig: o_orig,
+                open: true,
+                close: o_close,
+            } if c == ch && n > 0 => {
+                // The "rule of three"
+                !((o_close || open)
+                    && (o_orig + orig) % 3 == 0
+                    && (o_orig % 3 != 0 || orig % 3 != 0))
+            }
+            _ => false,
+        });
+
+        let opener = match opener {
+            Some(o) => o,
+            None => {
+                closer += 1;
+                continue;
+            }
+        };
+
+        let o_count = match items[opener] {
+            Item::Delim { count, .. } => count,
+            _ => unreachable!(),
+        };
+        let used = if count >= 2 && o_count >= 2 { 2 } else { 1 };
+
+        for j in [opener, closer] {
+            if let Item::Delim { count, .. } = &mut items[j] {
+                *count -= used;
+            }
+        }
+
+        let inner = finalize(items.drain(opener + 1..closer).collect());
+        let node = if used == 2 {
+            Inline::Strong(inner)
+        } else {
+            Inline::Emphasis(inner)
+        };
+        items.insert(opener + 1, Item::Inline(node));
+        closer = opener + 2;
+
+        if matches!(items[opener], Item::Delim { count: 0, .. }) {
+            items.remove(opener);
+            closer -= 1;
+        }
+        if matches!(items[closer], Item::Delim { count: 0, .. }) {
+            items.remove(closer);
+        }
+    }
+}
+
+/// # Usage
+/// Turns unmatched delimiters back into text and
+/// merges adjacent text nodes.
+fn finalize(items: Vec<Item>) -> Vec<Inline> {
+    let mut out: Vec<Inline> = Vec::new();
+    for item in items {
+        let inline = match item {
+            Item::Inline(inline) => inline,
+            Item::Delim { count: 0, .. } => continue,
+            Item::Delim { ch, count, .. } => Inline::Text(ch.to_string().repeat(count)),
+        };
+
+        match (out.last_mut(), inline) {
+            (Some(Inline::Text(last)), Inline::Text(text)) => last.push_str(&text),
+            (_, inline) => out.push(inline),
+        }
+    }
+    return out;
+}
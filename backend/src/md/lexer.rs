@@ -0,0 +1,463 @@
+//! # Usage
+//! Splits a source into lines and classifies each one
+//! by the block it can start. The [parser](super::parser)
+//! decides how consecutive lines group together.
+
+/// # Usage
+/// A single line of source, without its line ending.
+/// `offset` is the byte offset of `text` in the original
+/// source, so that positions can be reported back to the author.
+#[derive(Debug, Clone, Copy)]
+pub struct Line<'a> {
+    pub text: &'a str,
+    pub offset: usize,
+}
+
+impl<'a> Line<'a> {
+    /// # Usage
+    /// Returns the line with the first `bytes` bytes removed.
+    pub fn advance(&self, bytes: usize) -> Line<'a> {
+        let bytes = bytes.min(self.text.len());
+        return Line {
+            text: &self.text[bytes..],
+            offset: self.offset + bytes,
+        };
+    }
+
+    pub fn is_blank(&self) -> bool {
+        return self.text.trim().is_empty();
+    }
+
+    /// # Usage
+    /// The width of the leading whitespace, counting tabs as four columns.
+    pub fn indent(&self) -> usize {
+        let mut width = 0;
+        for c in self.text.chars() {
+            match c {
+                ' ' => width += 1,
+                '\t' => width += 4 - width % 4,
+                _ => break,
+            }
+        }
+        return width;
+    }
+
+    /// # Usage
+    /// Removes up to `columns` columns of leading whitespace.
+    pub fn dedent(&self, columns: usize) -> Line<'a> {
+        let mut width = 0;
+        let mut bytes = 0;
+        for c in self.text.chars() {
+            if width >= columns {
+                break;
+            }
+            match c {
+                ' ' => width += 1,
+                '\t' => width += 4 - width % 4,
+                _ => break,
+            }
+            bytes += 1;
+        }
+        return self.advance(bytes);
+    }
+
+    /// # Usage
+    /// Removes all leading whitespace.
+    pub fn trim_start(&self) -> Line<'a> {
+        return self.advance(self.text.len() - self.text.trim_start().len());
+    }
+}
+
+/// # Usage
+/// Splits `source` into lines, dropping `\n` and `\r\n` endings.
+/// `base` is added to every offset, which allows lexing a
+/// fragment of a larger source.
+pub fn lines(source: &str, base: usize) -> Vec<Line<'_>> {
+    let mut out = Vec::new();
+    let mut offset = 0;
+    for raw in source.split('\n') {
+        out.push(Line {
+            text: raw.strip_suffix('\r').unwrap_or(raw),
+            offset: base + offset,
+        });
+        offset += raw.len() + 1;
+    }
+
+    // A trailing newline does not start another line.
+    if source.ends_with('\n') {
+        out.pop();
+    }
+
+    return out;
+}
+
+/// # Usage
+/// How the lines of an HTML block end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HtmlEnd {
+    /// `<pre>`, `<script>`, `<style>` and `<textarea>` run until their closing tag.
+    Tag(&'static str),
+    /// `<!-- ... -->`
+    Comment,
+    /// Block level tags run until a blank line.
+    Blank,
+}
+
+/// # Usage
+/// The block a line can start.
+#[derive(Debug, Clone, Copy)]
+pub enum LineKind<'a> {
+    Blank,
+    Heading {
+        level: u8,
+        content: Line<'a>,
+    },
+    Fence {
+        marker: char,
+        len: usize,
+        indent: usize,
+        info: &'a str,
+    },
+    Rule,
+    /// The line with its `>` marker removed.
+    Quote(Line<'a>),
+    Item {
+        start: Option<u64>,
+        /// `-`, `+`, `*`, `.` or `)`
+        marker: char,
+        /// Column at which the content of the item starts.
+        width: usize,
+        content: Line<'a>,
+    },
+    Html(HtmlEnd),
+    /// `@macro name(params)`, the start of a macro definition.
+    Define,
+    /// `@end`, the end of a macro definition.
+    End,
+    /// `@name(`, possibly a block level macro invocation.
+    Call,
+    /// `$$`, the start of display math.
+    Math,
+    /// `:::name`, the start of an [environment](super::environment),
+    /// with the rest of the line holding its title and label.
+    Environment {
+        name: &'a str,
+        rest: Line<'a>,
+    },
+    /// `:::` on its own, the end of an environment.
+    EnvironmentEnd,
+    Text,
+}
+
+const RAW_TAGS: [&str; 4] = ["pre", "script", "style", "textarea"];
+
+const BLOCK_TAGS: [&str; 62] = [
+    "address",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "iframe",
+    "legend",
+    "li",
+    "link",
+    "main",
+    "menu",
+    "menuitem",
+    "nav",
+    "noframes",
+    "ol",
+    "optgroup",
+    "option",
+    "p",
+    "param",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+    "math",
+];
+
+/// # Usage
+/// Classifies a single line. Lines indented by four or more
+/// columns are always [LineKind::Text]; indentation is only
+/// significant inside of lists, since notes are often written
+/// as indented HTML.
+pub fn classify(line: Line<'_>) -> LineKind<'_> {
+    if line.is_blank() {
+        return LineKind::Blank;
+    }
+
+    let indent = line.indent();
+    if indent >= 4 {
+        return LineKind::Text;
+    }
+
+    let line = line.trim_start();
+    let text = line.text;
+    let first = text.chars().next().unwrap_or(' ');
+
+    if first == '#' {
+        if let Some(kind) = heading(line) {
+            return kind;
+        }
+    }
+
+    if first == '`' || first == '~' {
+        let len = text.chars().take_while(|c| *c == first).count();
+        let info = text[len..].trim();
+        if len >= 3 && !(first == '`' && info.contains('`')) {
+            return LineKind::Fence {
+                marker: first,
+                len,
+                indent,
+                info,
+            };
+        }
+    }
+
+    if is_rule(text) {
+        return LineKind::Rule;
+    }
+
+    if first == '>' {
+        let rest = line.advance(1);
+        let rest = match rest.text.chars().next() {
+            Some(' ') | Some('\t') => rest.advance(1),
+            _ => rest,
+        };
+        return LineKind::Quote(rest);
+    }
+
+    if let Some(kind) = item(line, indent) {
+        return kind;
+    }
+
+    if first == '<' {
+        if let Some(end) = html(text) {
+            return LineKind::Html(end);
+        }
+    }
+
+    if text.starts_with("$$") {
+        return LineKind::Math;
+    }
+
+    if let Some(rest) = text.strip_prefix(":::") {
+        let name = rest.chars().take_while(|c| c.is_ascii_alphabetic()).count();
+        if rest.trim().is_empty() {
+            return LineKind::EnvironmentEnd;
+        }
+        if name > 0 && (rest.len() == name || rest[name..].starts_with([' ', '\t'])) {
+            return LineKind::Environment {
+                name: &rest[..name],
+                rest: line.advance(3 + name).trim_start(),
+            };
+        }
+    }
+
+    if first == '@' {
+        let name = text[1..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .count();
+        if text.starts_with("@macro ") {
+            return LineKind::Define;
+        }
+        if text.trim_end() == "@end" {
+            return LineKind::End;
+        }
+        if name > 0 && text[1 + name..].starts_with('(') {
+            return LineKind::Call;
+        }
+    }
+
+    return LineKind::Text;
+}
+
+/// # Usage
+/// Whether the line is a setext underline, returning
+/// the heading level it produces.
+pub fn setext(line: Line) -> Option<u8> {
+    if line.indent() >= 4 {
+        return None;
+    }
+
+    let text = line.text.trim();
+    if !text.is_empty() && text.chars().all(|c| c == '=') {
+        return Some(1);
+    }
+    if !text.is_empty() && text.chars().all(|c| c == '-') {
+        return Some(2);
+    }
+    return None;
+}
+
+fn heading(line: Line<'_>) -> Option<LineKind<'_>> {
+    let level = line.text.chars().take_while(|c| *c == '#').count();
+    if level > 6 {
+        return None;
+    }
+
+    let rest = line.advance(level);
+    if !rest.text.is_empty() && !rest.text.starts_with([' ', '\t']) {
+        return None;
+    }
+
+    let content = rest.trim_start();
+    let mut text = content.text.trim_end();
+
+    // Optional closing sequence of '#'s
+    let stripped = text.trim_end_matches('#');
+    if stripped.is_empty() || stripped.ends_with([' ', '\t']) {
+        text = stripped.trim_end();
+    }
+
+    return Some(LineKind::Heading {
+        level: level as u8,
+        content: Line {
+            text,
+            offset: content.offset,
+        },
+    });
+}
+
+fn is_rule(text: &str) -> bool {
+    let text = text.trim_end();
+    let marker = match text.chars().next() {
+        Some(c @ ('-' | '*' | '_')) => c,
+        _ => return false,
+    };
+
+    let mut count = 0;
+    for c in text.chars() {
+        if c == marker {
+            count += 1;
+        } else if c != ' ' && c != '\t' {
+            return false;
+        }
+    }
+
+    return count >= 3;
+}
+
+fn item(line: Line<'_>, indent: usize) -> Option<LineKind<'_>> {
+    let text = line.text;
+    let digits = text.chars().take_while(|c| c.is_ascii_digit()).count();
+
+    let (start, marker, len) = if digits == 0 {
+        match text.chars().next() {
+            Some(c @ ('-' | '+' | '*')) => (None, c, 1),
+            _ => return None,
+        }
+    } else if digits <= 9 {
+        match text[digits..].chars().next() {
+            Some(c @ ('.' | ')')) => (Some(text[..digits].parse().ok()?), c, digits + 1),
+            _ => return None,
+        }
+    } else {
+        return None;
+    };
+
+    let rest = line.advance(len);
+    if rest.is_blank() {
+        return Some(LineKind::Item {
+            start,
+            marker,
+            width: indent + len + 1,
+            content: Line {
+                text: "",
+                offset: rest.offset,
+            },
+        });
+    }
+
+    if !rest.text.starts_with([' ', '\t']) {
+        return None;
+    }
+
+    // One to four spaces separate the marker from the content. With
+    // more, the content is considered indented by a single space.
+    let spaces = rest.indent();
+    let (width, content) = if spaces > 4 {
+        (indent + len + 1, rest.dedent(1))
+    } else {
+        (indent + len + spaces, rest.trim_start())
+    };
+
+    return Some(LineKind::Item {
+        start,
+        marker,
+        width,
+        content,
+    });
+}
+
+fn html(text: &str) -> Option<HtmlEnd> {
+    if text.starts_with("<!--") {
+        return Some(HtmlEnd::Comment);
+    }
+
+    let rest = text.strip_prefix('<')?;
+    let rest = rest.strip_prefix('/').unwrap_or(rest);
+    let name = rest
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    let after = rest[name.len()..].chars().next();
+    if name.is_empty() || !matches!(after, None | Some(' ' | '\t' | '>' | '/')) {
+        return None;
+    }
+
+    if let Some(tag) = RAW_TAGS.iter().find(|x| **x == name) {
+        if !text.starts_with("</") {
+            return Some(HtmlEnd::Tag(tag));
+        }
+    }
+
+    if BLOCK_TAGS.contains(&name.as_str()) {
+        return Some(HtmlEnd::Blank);
+    }
+
+    return None;
+}
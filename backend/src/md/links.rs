@@ -0,0 +1,315 @@
+//! # Usage
+//! Links between notes. `[[Limits]]` links to the note titled
+//! Limits, matching titles case insensitively as `notes::get`
+//! does. The text shown and a section can also be given:
+//! ```markdown
+//! See [[Limits]], or [[Limits#One sided limits|one sided limits]].
+//!
+//! @include("Limits", section="One sided limits")
+//! ```
+//! `@include` copies a note, or only the section under the
+//! given heading, into the including note. Included notes
+//! may include others, as long as none includes itself.
+//!
+//! Notes are looked up in a [Library], so links to notes
+//! that don't exist are reported as errors. The notes a source
+//! refers to can also be found without compiling it, see [outgoing].
+
+use super::{
+    ast::{Block, Inline},
+    cite::{self, Work},
+    environment, exercise,
+    inline::Text,
+    macros::{call, Call, Value},
+    outline::{self, slug},
+    parser::Parser,
+    Severity, Span,
+};
+use std::collections::HashMap;
+
+/// # Usage
+/// The notes which can be linked to and included, by title,
+/// and the references which can be cited, by key.
+#[derive(Debug, Clone, Default)]
+pub struct Library {
+    /// Pairs of (title, source), by the title in lowercase.
+    notes: HashMap<String, (String, String)>,
+    /// By the key in lowercase.
+    references: HashMap<String, Work>,
+}
+
+impl Library {
+    /// # Usage
+    /// Creates a library from pairs of (title, source).
+    pub fn new(notes: impl IntoIterator<Item = (String, String)>) -> Self {
+        return Library {
+            notes: notes
+                .into_iter()
+                .map(|x| (x.0.to_ascii_lowercase(), x))
+                .collect(),
+            references: HashMap::new(),
+        };
+    }
+
+    /// # Usage
+    /// Adds references which can be cited, from pairs of (key, work).
+    pub fn with_references(mut self, references: impl IntoIterator<Item = (String, Work)>) -> Self {
+        self.references.extend(
+            references
+                .into_iter()
+                .map(|x| (x.0.to_ascii_lowercase(), x.1)),
+        );
+        return self;
+    }
+
+    /// # Usage
+    /// Finds a reference by key, ignoring case.
+    pub fn reference(&self, key: &str) -> Option<&Work> {
+        return self.references.get(&key.to_ascii_lowercase());
+    }
+
+    /// # Usage
+    /// Finds a note by title, ignoring case. Returns
+    /// the title as it was written and the source.
+    pub fn find(&self, title: &str) -> Option<(&str, &str)> {
+        return self
+            .notes
+            .get(&title.trim().to_ascii_lowercase())
+            .map(|x| (x.0.as_str(), x.1.as_str()));
+    }
+}
+
+/// # Usage
+/// Where a note is served, e.g. `/notes/Mean%20value%20theorem`.
+pub fn href(title: &str) -> String {
+    let mut out = String::from("/notes/");
+    for byte in title.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    return out;
+}
+
+/// # Usage
+/// Decodes the `%XX` escapes written by [href].
+fn unescape(s: &str) -> String {
+    let mut out = Vec::new();
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = s
+            .get(i + 1..i + 3)
+            .and_then(|x| u8::from_str_radix(x, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    return String::from_utf8_lossy(&out).into_owned();
+}
+
+/// # Usage
+/// Finds the titles of the notes a source links to or includes,
+/// without compiling it: wiki links, `@include`s and links to
+/// `/notes/<title>`, whether written in markdown or as anchors.
+/// Titles are as written and may not name an existing note.
+pub fn outgoing(source: &str) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    let mut push = |title: String| {
+        let title = title.trim().to_string();
+        if !title.is_empty() && !out.iter().any(|x| x.eq_ignore_ascii_case(&title)) {
+            out.push(title);
+        }
+    };
+
+    for (i, _) in source.match_indices("[[") {
+        if let Some(link) = wiki(&source[i..]) {
+            push(link.title.to_string());
+        }
+    }
+    for (i, _) in source.match_indices("@include(") {
+        if let Ok(Some(call)) = call(&source[i..]) {
+            if let Some(Value::String(title)) = call.args.first().map(|x| &x.value) {
+                push(title.clone());
+            }
+        }
+    }
+    for (i, _) in source.match_indices("/notes/") {
+        // Only links relative to the site, as in `](/notes/` or `href="/notes/`
+        if !source[..i].ends_with(['(', '"', '\'', '<', ' ']) {
+            continue;
+        }
+        let rest = &source[i + "/notes/".len()..];
+        let end = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, ')' | '"' | '\'' | '>' | '#' | '?'))
+            .unwrap_or(rest.len());
+        push(unescape(&rest[..end]));
+    }
+
+    return out;
+}
+
+/// # Usage
+/// A wiki link as written: `[[title#section|text]]`.
+pub struct Wiki<'a> {
+    pub title: &'a str,
+    pub section: Option<&'a str>,
+    pub text: Option<&'a str>,
+    /// Length of the link in bytes, including the brackets.
+    pub len: usize,
+}
+
+/// # Usage
+/// Parses a wiki link at the start of `s`.
+pub fn wiki(s: &str) -> Option<Wiki<'_>> {
+    let rest = s.strip_prefix("[[")?;
+    let close = rest.find("]]")?;
+    let inner = &rest[..close];
+    if inner.trim().is_empty() || inner.contains(['[', ']', '\n']) {
+        return None;
+    }
+
+    let (target, text) = match inner.split_once('|') {
+        Some((target, text)) => (target, Some(text.trim())),
+        None => (inner, None),
+    };
+    let (title, section) = match target.split_once('#') {
+        Some((title, section)) => (title, Some(section.trim())),
+        None => (target, None),
+    };
+
+    return Some(Wiki {
+        title: title.trim(),
+        section,
+        text,
+        len: close + 4,
+    });
+}
+
+impl<'s> Parser<'s> {
+    /// # Usage
+    /// Resolves a wiki link found at offset `at` of `text`,
+    /// reporting it if no note has the title.
+    pub fn wiki_link(&mut self, link: &Wiki, text: &Text, at: usize) -> Inline {
+        let shown = link.text.unwrap_or(link.title).to_string();
+        let Some((title, _)) = self.library.find(link.title) else {
+            self.report(
+                Span::at(text.source(at)),
+                Severity::Error,
+                format!("No note is titled `{}`", link.title),
+            );
+            return Inline::Text(shown);
+        };
+
+        let mut href = href(title);
+        if let Some(section) = link.section {
+            href.push('#');
+            href.push_str(&slug(section));
+        }
+        return Inline::Link {
+            href,
+            title: None,
+            content: vec![Inline::Text(shown)],
+        };
+    }
+
+    /// # Usage
+    /// Expands `@include`, found at offset `at` of `text`, into
+    /// the blocks of the included note or of one of its sections.
+    pub fn include(&mut self, call: &Call, text: &Text, at: usize) -> Vec<Block> {
+        let span = Span {
+            start: text.source(at),
+            end: text.source(at + call.len),
+        };
+        let values = match self.macros["include"].bind(call) {
+            Ok(values) => values,
+            Err(errors) => {
+                for (offset, message) in errors {
+                    self.report(Span::at(text.source(at + offset)), Severity::Error, message);
+                }
+                return Vec::new();
+            }
+        };
+        let (Value::String(name), Value::String(section)) = (&values["note"], &values["section"])
+        else {
+            unreachable!("Arguments are checked by Macro::bind");
+        };
+
+        let Some((title, source)) = self.library.find(name) else {
+            self.report(
+                span,
+                Severity::Error,
+                format!("No note is titled `{}`", name),
+            );
+            return Vec::new();
+        };
+        if self.including.contains(&title.to_ascii_lowercase()) {
+            self.report(
+                span,
+                Severity::Error,
+                format!("`{}` includes itself", title),
+            );
+            return Vec::new();
+        }
+
+        let mut parser = Parser::new(source, self.library);
+        parser.including = self.including.clone();
+        parser.including.push(title.to_ascii_lowercase());
+        let mut blocks = parser.parse();
+        let diagnostics = parser.finish();
+
+        // Problems inside of the included note are that note's,
+        // so they are only summarized here.
+        if diagnostics.has_errors() {
+            let cycle = diagnostics
+                .0
+                .iter()
+                .find(|x| x.message.ends_with("includes itself"));
+            let message = match cycle {
+                Some(cycle) => cycle.message.clone(),
+                None => format!("`{}` has errors, so cannot be included", title),
+            };
+            self.report(span, Severity::Error, message);
+            return Vec::new();
+        }
+
+        if !section.is_empty() {
+            outline::outline(&mut blocks);
+            let id = slug(section);
+            let Some(start) = blocks
+                .iter()
+                .position(|x| matches!(x, Block::Heading { id: Some(x), .. } if *x == id))
+            else {
+                self.report(
+                    span,
+                    Severity::Error,
+                    format!("`{}` has no section `{}`", title, section),
+                );
+                return Vec::new();
+            };
+            let Block::Heading { level, .. } = blocks[start] else {
+                unreachable!();
+            };
+            let end = blocks[start + 1..]
+                .iter()
+                .position(|x| matches!(x, Block::Heading { level: l, .. } if *l <= level))
+                .map_or(blocks.len(), |x| start + 1 + x);
+            blocks = blocks.drain(start..end).collect();
+        }
+
+        cite::adopt(&mut blocks, title);
+        exercise::adopt(&mut blocks, title);
+        environment::relocate(&mut blocks, self.expansion.unwrap_or(span));
+        return blocks;
+    }
+}
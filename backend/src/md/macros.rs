@@ -0,0 +1,806 @@
+//! # Usage
+//! User defined macros. A macro is defined once per note
+//! with typed parameters, and expands to markdown wherever
+//! it is invoked.
+//! ```markdown
+//! @macro definition(term: String, body: String, number: Integer = 1)
+//! <div class="definition">
+//!
+//! **Definition {{number}} ({{term}}).** {{body}}
+//!
+//! </div>
+//! @end
+//!
+//! @definition("Limit", body="We say $f$ approaches $L$...")
+//! ```
+//! Invocations standing on their own lines expand to blocks,
+//! any others expand inline. Arguments are either positional
+//! or named, and are checked against the declared [Type]s.
+//!
+//! Some macros are [built in](builtins), such as `@plot`, `@deriv`,
+//! `@include` and `@exercise`.
+//! `@ref` is reserved for references, see [environment](super::environment).
+
+use super::{
+    deriv,
+    inline::Text,
+    lexer::{self, Line, LineKind},
+    parser::Parser,
+    plot, Severity, Span,
+};
+use std::{collections::HashMap, fmt};
+
+/// # Usage
+/// How deeply macros may expand into other macros
+/// before the expansion is assumed to be recursive.
+const MAX_DEPTH: usize = 16;
+
+/// # Usage
+/// A very basic set of primitive
+/// types.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Integer,
+    String,
+    Real,
+    Boolean,
+    /// A pair of reals, written `[a, b]`.
+    Interval,
+}
+
+impl Type {
+    fn parse(name: &str) -> Option<Self> {
+        return match name {
+            "Integer" => Some(Type::Integer),
+            "String" => Some(Type::String),
+            "Real" => Some(Type::Real),
+            "Boolean" => Some(Type::Boolean),
+            "Interval" => Some(Type::Interval),
+            _ => None,
+        };
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{:?}", self);
+    }
+}
+
+/// # Usage
+/// A literal argument or default.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    String(String),
+    Real(f64),
+    Boolean(bool),
+    Interval(f64, f64),
+}
+
+impl Value {
+    pub fn ty(&self) -> Type {
+        return match self {
+            Value::Integer(_) => Type::Integer,
+            Value::String(_) => Type::String,
+            Value::Real(_) => Type::Real,
+            Value::Boolean(_) => Type::Boolean,
+            Value::Interval(..) => Type::Interval,
+        };
+    }
+
+    /// # Usage
+    /// Converts the value to the given type, if the
+    /// value is of that type. Integers widen to reals.
+    pub fn coerce(self, ty: Type) -> Option<Value> {
+        return match (self, ty) {
+            (Value::Integer(x), Type::Real) => Some(Value::Real(x as f64)),
+            (value, ty) if value.ty() == ty => Some(value),
+            _ => None,
+        };
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Value::Integer(x) => write!(f, "{}", x),
+            Value::String(x) => write!(f, "{}", x),
+            Value::Real(x) => write!(f, "{}", x),
+            Value::Boolean(x) => write!(f, "{}", x),
+            Value::Interval(a, b) => write!(f, "[{}, {}]", a, b),
+        };
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: String,
+    pub ty: Type,
+    pub default: Option<Value>,
+}
+
+/// # Usage
+/// What a macro expands to.
+#[derive(Debug, Clone)]
+pub enum Body {
+    /// Markdown in which `{{param}}` is replaced by the argument.
+    Markdown(String),
+    /// A macro built into the compiler, producing markdown
+    /// from the arguments or failing with a message.
+    Builtin(fn(&HashMap<String, Value>) -> Result<String, String>),
+    /// `@include`, expanded by the parser since it produces
+    /// blocks rather than markdown, see [links](super::links).
+    Include,
+    /// `@exercise`, expanded by the parser since its answer must
+    /// not be rendered, see [exercise](super::exercise).
+    Exercise,
+}
+
+/// # Usage
+/// A user defined or built in macro.
+#[derive(Debug, Clone)]
+pub struct Macro {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub body: Body,
+}
+
+/// # Usage
+/// Returns the macros available in every note.
+pub fn builtins() -> HashMap<String, Macro> {
+    let param = |name: &str, ty, default| Param {
+        name: name.to_string(),
+        ty,
+        default,
+    };
+
+    let builtins = [
+        Macro {
+            name: "plot".to_string(),
+            params: vec![
+                param("f", Type::String, None),
+                param("from", Type::Real, Some(Value::Real(-5.0))),
+                param("to", Type::Real, Some(Value::Real(5.0))),
+                param("shade", Type::Interval, Some(Value::Interval(0.0, 0.0))),
+                param("width", Type::Integer, Some(Value::Integer(400))),
+                param("height", Type::Integer, Some(Value::Integer(300))),
+            ],
+            body: Body::Builtin(plot::plot),
+        },
+        Macro {
+            name: "deriv".to_string(),
+            params: vec![
+                param("f", Type::String, None),
+                param("x", Type::String, Some(Value::String("x".to_string()))),
+                param("display", Type::Boolean, Some(Value::Boolean(false))),
+            ],
+            body: Body::Builtin(deriv::deriv),
+        },
+        Macro {
+            name: "include".to_string(),
+            params: vec![
+                param("note", Type::String, None),
+                param("section", Type::String, Some(Value::String(String::new()))),
+            ],
+            body: Body::Include,
+        },
+        Macro {
+            name: "exercise".to_string(),
+            params: vec![
+                param("id", Type::String, None),
+                param("prompt", Type::String, None),
+                param("answer", Type::String, None),
+                param("tolerance", Type::Real, Some(Value::Real(1e-6))),
+                param("hints", Type::String, Some(Value::String(String::new()))),
+                param("solution", Type::String, Some(Value::String(String::new()))),
+            ],
+            body: Body::Exercise,
+        },
+    ];
+
+    return builtins.into_iter().map(|x| (x.name.clone(), x)).collect();
+}
+
+/// # Usage
+/// An argument as written in an invocation. `offset` is
+/// relative to the start of the invocation.
+#[derive(Debug, Clone)]
+pub struct Arg {
+    pub name: Option<String>,
+    pub value: Value,
+    pub offset: usize,
+}
+
+/// # Usage
+/// A parsed invocation such as `@name(1, key="value")`.
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub name: String,
+    pub args: Vec<Arg>,
+    /// Length of the invocation in bytes, including the closing `)`.
+    pub len: usize,
+}
+
+/// # Usage
+/// A position relative to the start of the scanned text and a message.
+pub type Error = (usize, String);
+
+struct Cursor<'a> {
+    s: &'a str,
+    i: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<char> {
+        return self.s[self.i..].chars().next();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.i += c.len_utf8();
+            return true;
+        }
+        return false;
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        if self.eat(c) {
+            return Ok(());
+        }
+        return Err((self.i, format!("Expected `{}`", c)));
+    }
+
+    fn skip(&mut self) {
+        let rest = &self.s[self.i..];
+        self.i += rest.len() - rest.trim_start().len();
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        let len = self.s[self.i..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .count();
+        if len == 0 || self.s[self.i..].starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        let ident = &self.s[self.i..self.i + len];
+        self.i += len;
+        return Some(ident);
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        let start = self.i;
+        match self.peek() {
+            Some('"') => {
+                self.i += 1;
+                let mut out = String::new();
+                loop {
+                    let c = self
+                        .peek()
+                        .ok_or((start, "String is never closed".to_string()))?;
+                    self.i += c.len_utf8();
+                    match c {
+                        '"' => return Ok(Value::String(out)),
+                        '\\' => {
+                            let n = self
+                                .peek()
+                                .ok_or((start, "String is never closed".to_string()))?;
+                            self.i += n.len_utf8();
+                            match n {
+                                'n' => out.push('\n'),
+                                't' => out.push('\t'),
+                                '"' => out.push('"'),
+                                '\\' => out.push('\\'),
+                                // Unknown escapes are kept, so LaTeX survives.
+                                n => {
+                                    out.push('\\');
+                                    out.push(n);
+                                }
+                            }
+                        }
+                        c => out.push(c),
+                    }
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let rest = &self.s[self.i..];
+                let len = rest
+                    .char_indices()
+                    .take_while(|(j, c)| {
+                        c.is_ascii_digit()
+                            || *c == '.'
+                            || *c == 'e'
+                            || *c == 'E'
+                            || ((*c == '-' || *c == '+')
+                                && (*j == 0 || rest[..*j].ends_with(['e', 'E'])))
+                    })
+                    .count();
+                let text = &rest[..len];
+                self.i += len;
+                if let Ok(x) = text.parse::<i64>() {
+                    return Ok(Value::Integer(x));
+                }
+                return text
+                    .parse::<f64>()
+                    .map(Value::Real)
+                    .map_err(|_| (start, format!("Invalid number `{}`", text)));
+            }
+            Some('[') => {
+                self.i += 1;
+                let mut bounds = [0.0; 2];
+                for (j, bound) in bounds.iter_mut().enumerate() {
+                    self.skip();
+                    if j > 0 {
+                        self.expect(',')?;
+                        self.skip();
+                    }
+                    let at = self.i;
+                    *bound = match self.value()?.coerce(Type::Real) {
+                        Some(Value::Real(x)) => x,
+                        _ => return Err((at, "Bounds of an interval should be Real".to_string())),
+                    };
+                }
+                self.skip();
+                self.expect(']')?;
+                return Ok(Value::Interval(bounds[0], bounds[1]));
+            }
+            _ => match self.ident() {
+                Some("true") => Ok(Value::Boolean(true)),
+                Some("false") => Ok(Value::Boolean(false)),
+                _ => Err((start, "Expected a value".to_string())),
+            },
+        }
+    }
+}
+
+/// # Usage
+/// Parses an invocation at the start of `s`. Returns `Ok(None)`
+/// if `s` does not start with `@name(`, in which case it is text.
+pub fn call(s: &str) -> Result<Option<Call>, Error> {
+    let mut cursor = Cursor { s, i: 0 };
+    if !cursor.eat('@') {
+        return Ok(None);
+    }
+    let name = match cursor.ident() {
+        Some(name) => name.to_string(),
+        None => return Ok(None),
+    };
+    if !cursor.eat('(') {
+        return Ok(None);
+    }
+
+    let mut args = Vec::new();
+    cursor.skip();
+    while !cursor.eat(')') {
+        if !args.is_empty() {
+            cursor.expect(',')?;
+            cursor.skip();
+        }
+
+        let offset = cursor.i;
+        let before = cursor.i;
+        let mut arg_name = None;
+        if let Some(ident) = cursor.ident() {
+            cursor.skip();
+            if cursor.eat('=') {
+                arg_name = Some(ident.to_string());
+                cursor.skip();
+            } else {
+                // Not a name, but a value such as `true`
+                cursor.i = before;
+            }
+        }
+
+        if cursor.peek().is_none() {
+            return Err((0, format!("Invocation of `{}` is never closed", name)));
+        }
+
+        let value = cursor.value()?;
+        args.push(Arg {
+            name: arg_name,
+            value,
+            offset,
+        });
+        cursor.skip();
+    }
+
+    return Ok(Some(Call {
+        name,
+        args,
+        len: cursor.i,
+    }));
+}
+
+/// # Usage
+/// Parses the header of a definition: `@macro name(a: Type, b: Type = default)`.
+fn header(s: &str) -> Result<(String, Vec<Param>), Error> {
+    let mut cursor = Cursor { s, i: 0 };
+    cursor.i = "@macro".len();
+    cursor.skip();
+    let name = cursor
+        .ident()
+        .ok_or((cursor.i, "Expected a macro name".to_string()))?
+        .to_string();
+    cursor.skip();
+
+    let mut params: Vec<Param> = Vec::new();
+    if cursor.eat('(') {
+        cursor.skip();
+        while !cursor.eat(')') {
+            if !params.is_empty() {
+                cursor.expect(',')?;
+                cursor.skip();
+            }
+
+            let start = cursor.i;
+            let param = cursor
+                .ident()
+                .ok_or((cursor.i, "Expected a parameter name".to_string()))?
+                .to_string();
+            if params.iter().any(|x| x.name == param) {
+                return Err((start, format!("Parameter `{}` is declared twice", param)));
+            }
+            cursor.skip();
+            cursor.expect(':')?;
+            cursor.skip();
+
+            let at = cursor.i;
+            let ty_name = cursor.ident().unwrap_or("");
+            let ty = Type::parse(ty_name).ok_or((
+                at,
+                format!(
+                    "Unknown type `{}`, expected Integer, String, Real, Boolean or Interval",
+                    ty_name
+                ),
+            ))?;
+            cursor.skip();
+
+            let mut default = None;
+            if cursor.eat('=') {
+                cursor.skip();
+                let at = cursor.i;
+                let value = cursor.value()?;
+                let found = value.ty();
+                default = Some(value.coerce(ty).ok_or((
+                    at,
+                    format!("Default of `{}` should be {}, found {}", param, ty, found),
+                ))?);
+                cursor.skip();
+            }
+
+            params.push(Param {
+                name: param,
+                ty,
+                default,
+            });
+        }
+    }
+
+    cursor.skip();
+    if cursor.i != s.trim_end().len() {
+        return Err((cursor.i, "Unexpected text after macro header".to_string()));
+    }
+
+    return Ok((name, params));
+}
+
+impl Macro {
+    /// # Usage
+    /// Matches the arguments of a call with the parameters of
+    /// the macro, checking their types and filling in defaults.
+    pub fn bind(&self, call: &Call) -> Result<HashMap<String, Value>, Vec<Error>> {
+        let mut values: HashMap<String, Value> = HashMap::new();
+        let mut given: Vec<&str> = Vec::new();
+        let mut errors = Vec::new();
+        let mut named = false;
+
+        for (i, arg) in call.args.iter().enumerate() {
+            let param = match &arg.name {
+                Some(name) => {
+                    named = true;
+                    match self.params.iter().find(|x| &x.name == name) {
+                        Some(param) => param,
+                        None => {
+                            errors.push((
+                                arg.offset,
+                                format!("`{}` has no parameter `{}`", self.name, name),
+                            ));
+                            continue;
+                        }
+                    }
+                }
+                None if named => {
+                    errors.push((
+                        arg.offset,
+                        "Positional arguments must come before named ones".to_string(),
+                    ));
+                    continue;
+                }
+                None => match self.params.get(i) {
+                    Some(param) => param,
+                    None => {
+                        errors.push((
+                            arg.offset,
+                            format!(
+                                "`{}` takes at most {} arguments",
+                                self.name,
+                                self.params.len()
+                            ),
+                        ));
+                        continue;
+                    }
+                },
+            };
+
+            if given.contains(&param.name.as_str()) {
+                errors.push((
+                    arg.offset,
+                    format!("`{}` is given more than once", param.name),
+                ));
+                continue;
+            }
+            given.push(&param.name);
+
+            match arg.value.clone().coerce(param.ty) {
+                Some(value) => {
+                    values.insert(param.name.clone(), value);
+                }
+                None => errors.push((
+                    arg.offset,
+                    format!(
+                        "`{}` of `{}` should be {}, found {}",
+                        param.name,
+                        self.name,
+                        param.ty,
+                        arg.value.ty()
+                    ),
+                )),
+            }
+        }
+
+        for param in &self.params {
+            if given.contains(&param.name.as_str()) {
+                continue;
+            }
+            match &param.default {
+                Some(default) => {
+                    values.insert(param.name.clone(), default.clone());
+                }
+                None => errors.push((
+                    0,
+                    format!("`{}` is missing argument `{}`", self.name, param.name),
+                )),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        return Ok(values);
+    }
+
+    /// # Usage
+    /// Substitutes the bound values into the body, or runs
+    /// a built in macro on them.
+    pub fn expand(&self, values: &HashMap<String, Value>) -> Result<String, String> {
+        let body = match &self.body {
+            Body::Markdown(body) => body,
+            Body::Builtin(f) => return f(values),
+            Body::Include | Body::Exercise => {
+                return Err(format!("`@{}` must stand on its own line", self.name))
+            }
+        };
+
+        let mut out = String::new();
+        let mut rest = body.as_str();
+        while let Some(start) = rest.find("{{") {
+            let end = match rest[start..].find("}}") {
+                Some(end) => start + end,
+                None => break,
+            };
+            out.push_str(&rest[..start]);
+            match values.get(rest[start + 2..end].trim()) {
+                Some(value) => out.push_str(&value.to_string()),
+                None => out.push_str(&rest[start..end + 2]),
+            }
+            rest = &rest[end + 2..];
+        }
+        out.push_str(rest);
+        return Ok(out);
+    }
+
+    /// # Usage
+    /// Placeholders in the body that do not name a parameter.
+    fn unknown(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        let mut rest = match &self.body {
+            Body::Markdown(body) => body.as_str(),
+            Body::Builtin(_) | Body::Include | Body::Exercise => "",
+        };
+        while let Some(start) = rest.find("{{") {
+            let end = match rest[start..].find("}}") {
+                Some(end) => start + end,
+                None => break,
+            };
+            let name = rest[start + 2..end].trim();
+            if !self.params.iter().any(|x| x.name == name) {
+                out.push(name.to_string());
+            }
+            rest = &rest[end + 2..];
+        }
+        return out;
+    }
+}
+
+impl<'s> Parser<'s> {
+    /// # Usage
+    /// Registers the macro defined at `lines[*i]`, advancing
+    /// past its `@end`.
+    pub fn define(&mut self, lines: &[Line], i: &mut usize) {
+        let open = lines[*i].trim_start();
+        self.defined.insert(open.offset);
+        *i += 1;
+
+        let mut body: Vec<&str> = Vec::new();
+        let mut closed = false;
+        while *i < lines.len() {
+            let line = lines[*i];
+            *i += 1;
+            if matches!(lexer::classify(line), LineKind::End) {
+                closed = true;
+                break;
+            }
+            body.push(line.text);
+        }
+
+        if !closed {
+            self.report(
+                Span::at(open.offset),
+                Severity::Error,
+                "Macro definition is missing `@end`",
+            );
+        }
+
+        let (name, params) = match header(open.text) {
+            Ok(header) => header,
+            Err((offset, message)) => {
+                self.report(Span::at(open.offset + offset), Severity::Error, message);
+                return;
+            }
+        };
+
+        let definition = Macro {
+            name,
+            params,
+            body: Body::Markdown(body.join("\n")),
+        };
+
+        for name in definition.unknown() {
+            self.report(
+                Span::at(open.offset),
+                Severity::Error,
+                format!("`{}` has no parameter `{}`", definition.name, name),
+            );
+        }
+
+        // `@ref` is parsed along with the inlines, see [environment]
+        if definition.name == "ref" {
+            self.report(
+                Span::at(open.offset),
+                Severity::Error,
+                "Macro `ref` is built in",
+            );
+            return;
+        }
+
+        if let Some(existing) = self.macros.get(&definition.name) {
+            let message = match existing.body {
+                Body::Markdown(_) => format!("Macro `{}` is defined twice", definition.name),
+                Body::Builtin(_) | Body::Include | Body::Exercise => {
+                    format!("Macro `{}` is built in", definition.name)
+                }
+            };
+            self.report(Span::at(open.offset), Severity::Error, message);
+            return;
+        }
+
+        self.macros.insert(definition.name.clone(), definition);
+    }
+
+    /// # Usage
+    /// Checks whether the invocation starting at `lines[i]` stands on its
+    /// own lines. If so, returns it with the number of lines it spans.
+    pub fn standalone(&self, lines: &[Line], i: usize) -> Option<(Call, Text, usize)> {
+        let text = Text::raw(&lines[i..]);
+        let call = call(&text.s).ok()??;
+        let rest = &text.s[call.len..];
+        let rest = rest.split('\n').next().unwrap_or("");
+        if !rest.trim().is_empty() {
+            return None;
+        }
+
+        let count = text.s[..call.len].matches('\n').count() + 1;
+        return Some((call, text, count));
+    }
+
+    /// # Usage
+    /// Expands an invocation that stands on its own lines into blocks.
+    pub fn block_call(&mut self, lines: &[Line], i: &mut usize) -> Option<Vec<super::ast::Block>> {
+        let (call, text, count) = self.standalone(lines, *i)?;
+        *i += count;
+
+        match self.macros.get(&call.name).map(|x| &x.body) {
+            Some(Body::Include) => return Some(self.include(&call, &text, 0)),
+            Some(Body::Exercise) => return Some(self.exercise(&call, &text, 0)),
+            _ => {}
+        }
+
+        let span = Span {
+            start: text.source(0),
+            end: text.source(call.len),
+        };
+        let expanded = self.invoke(&call, &text, 0)?;
+        let lines = lexer::lines(&expanded, 0);
+
+        return Some(self.expanding(span, |parser| parser.blocks(&lines)));
+    }
+
+    /// # Usage
+    /// Binds and expands an invocation found at offset `at` of `text`,
+    /// reporting any problems. Returns `None` if it could not be expanded.
+    pub fn invoke(&mut self, call: &Call, text: &Text, at: usize) -> Option<String> {
+        let definition = match self.macros.get(&call.name) {
+            Some(definition) => definition,
+            None => {
+                self.report(
+                    Span::at(text.source(at)),
+                    Severity::Error,
+                    format!("Unknown macro `{}`", call.name),
+                );
+                return None;
+            }
+        };
+
+        if self.depth >= MAX_DEPTH {
+            self.report(
+                Span::at(text.source(at)),
+                Severity::Error,
+                format!("`{}` expands too deeply, is it recursive?", call.name),
+            );
+            return None;
+        }
+
+        return match definition.bind(call) {
+            Ok(values) => match definition.expand(&values) {
+                Ok(expanded) => Some(expanded),
+                Err(message) => {
+                    self.report(Span::at(text.source(at)), Severity::Error, message);
+                    None
+                }
+            },
+            Err(errors) => {
+                for (offset, message) in errors {
+                    self.report(Span::at(text.source(at + offset)), Severity::Error, message);
+                }
+                None
+            }
+        };
+    }
+
+    /// # Usage
+    /// Runs `f` on the output of a macro. Problems found inside
+    /// the expansion are reported at the invocation, since the
+    /// expanded text does not exist in the source.
+    pub fn expanding<T>(&mut self, span: Span, f: impl FnOnce(&mut Self) -> T) -> T {
+        let outer = self.expansion;
+        if outer.is_none() {
+            self.expansion = Some(span);
+        }
+        self.depth += 1;
+        let out = f(self);
+        self.depth -= 1;
+        self.expansion = outer;
+        return out;
+    }
+}
@@ -0,0 +1,912 @@
+//! # Usage
+//! This module exposes an interpreter that
+//! takes in the custom markdown employed by the site
+//! and compiles it to HTML (and maybe a little CSS).
+//!
+//! Compilation runs in five stages:
+//! - The [lexer] splits the source into classified lines.
+//! - The [parser] groups them into the blocks and inlines of the [ast].
+//! - The [outline] gives headings anchors and collects them.
+//! - The [html] renderer turns the tree into HTML.
+//! - The [sanitize] stage strips anything that could run script.
+//!
+//! The supported syntax follows CommonMark: ATX and setext headings,
+//! paragraphs, block quotes, lists, fenced code, thematic breaks,
+//! raw HTML, emphasis, code spans, links (inline and reference),
+//! images and autolinks. Indented code blocks are not supported,
+//! so existing notes written as indented HTML keep working.
+//!
+//! Fenced code is colored by language, see [highlight].
+//!
+//! Theorems, definitions, proofs and the like are written as
+//! numbered [environment]s, which `@ref(label)` refers to.
+//! Notes [link](links) to each other as `[[Title]]`, and may
+//! include sections of each other, so compiling a note takes
+//! a [Library] of the others. The library also holds the
+//! references notes may [cite], which are listed at the end of
+//! the note along with its footnotes.
+//!
+//! Practice problems are written with `@exercise`, whose
+//! answers are kept out of the HTML, see [exercise]. Definitions
+//! and theorems double as [flashcards].
+//!
+//! Notes may also define and invoke typed [macros], and write
+//! math as LaTeX between `$...$` or `$$...$$`, which [tex]
+//! compiles to MathML. The built in `@plot` macro draws graphs
+//! of functions as SVG, see [plot], and `@deriv` differentiates
+//! them, see [deriv].
+
+mod ast;
+mod cite;
+mod deriv;
+mod environment;
+mod exercise;
+mod flashcards;
+mod highlight;
+mod html;
+mod inline;
+mod lexer;
+mod links;
+mod macros;
+mod outline;
+mod parser;
+mod plot;
+mod sanitize;
+mod tex;
+mod validate;
+
+pub use cite::Work;
+pub use exercise::{Answer, Exercise};
+pub use flashcards::Flashcard;
+pub use links::{href, outgoing, Library};
+pub use outline::Heading;
+use serde::Serialize;
+use std::fmt;
+
+/// # Usage
+/// Compiled HTML, ready to be served.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Html(String);
+
+impl Html {
+    pub fn as_str(&self) -> &str {
+        return &self.0;
+    }
+}
+
+/// # Usage
+/// A byte range of the source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// # Usage
+    /// An empty span at the given offset.
+    pub fn at(offset: usize) -> Self {
+        return Span {
+            start: offset,
+            end: offset,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// # Usage
+/// A problem found in a source. Lines and columns start at one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+}
+
+/// # Usage
+/// Every problem found while compiling a source.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics {
+    pub fn has_errors(&self) -> bool {
+        return self.0.iter().any(|x| x.severity == Severity::Error);
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.0 {
+            writeln!(
+                f,
+                "{:?} {}:{}: {}",
+                diagnostic.severity, diagnostic.line, diagnostic.column, diagnostic.message
+            )?;
+        }
+        return Ok(());
+    }
+}
+
+/// # Usage
+/// The version of the compiler. Bump it whenever the
+/// output for the same source changes, so that cached
+/// HTML gets recompiled.
+pub const VERSION: u32 = 9;
+
+/// # Usage
+/// Creates a parser for the source of the note titled `title`,
+/// which counts as being included so that it can't include itself.
+fn parser<'s>(title: &str, source: &'s str, library: &'s Library) -> parser::Parser<'s> {
+    let mut parser = parser::Parser::new(source, library);
+    if !title.trim().is_empty() {
+        parser.including.push(title.trim().to_ascii_lowercase());
+    }
+    return parser;
+}
+
+/// # Usage
+/// Checks the source of the note titled `title` without rendering
+/// it, returning every error and warning: unbalanced HTML tags and
+/// math delimiters, unknown or misused macros, malformed math, links
+/// to notes which aren't in `library` and includes of the note itself.
+/// The title may be empty for notes which don't have one yet.
+pub fn validate(title: &str, source: &str, library: &Library) -> Diagnostics {
+    let mut parser = parser(title, source, library);
+    parser.parse();
+    return parser.finish();
+}
+
+/// # Usage
+/// A compiled note.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compiled {
+    pub html: Html,
+    /// The headings of the note, see [outline].
+    pub outline: Vec<Heading>,
+    /// The note's exercises, whose answers are not in the HTML.
+    pub exercises: Vec<Exercise>,
+    /// The note's definitions and theorems as cards.
+    pub flashcards: Vec<Flashcard>,
+}
+
+/// # Usage
+/// Compiles the source of the note titled `title` to HTML, as in
+/// [validate]. Fails if any errors were found; warnings are dropped.
+pub fn compile(title: &str, source: &str, library: &Library) -> Result<Compiled, Diagnostics> {
+    let mut parser = parser(title, source, library);
+    let blocks = parser.parse();
+    let diagnostics = parser.finish();
+
+    if diagnostics.has_errors() {
+        return Err(diagnostics);
+    }
+
+    return Ok(build(blocks));
+}
+
+/// # Usage
+/// Compiles a source as in [compile], but despite any errors, for
+/// notes which have to be shown regardless. Whatever was parsed is
+/// rendered: unknown macros are left as written and unbalanced
+/// tags are closed by [sanitize].
+pub fn render(title: &str, source: &str, library: &Library) -> Compiled {
+    let mut parser = parser(title, source, library);
+    let blocks = parser.parse();
+    return build(blocks);
+}
+
+fn build(mut blocks: Vec<ast::Block>) -> Compiled {
+    let outline = outline::outline(&mut blocks);
+    let html = sanitize::clean(&html::render(&blocks));
+    return Compiled {
+        flashcards: flashcards::flashcards(&html),
+        html: Html(html),
+        outline,
+        exercises: exercise::exercises(&mut blocks),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(source: &str) -> Result<Compiled, Diagnostics> {
+        return super::compile("", source, &Library::default());
+    }
+
+    fn validate(source: &str) -> Diagnostics {
+        return super::validate("", source, &Library::default());
+    }
+
+    fn html(source: &str) -> String {
+        return compile(source).unwrap().html.0;
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(
+            html("# Limits\n\nA *limit* is **close**.\n\n---\n"),
+            "<h1 id=\"limits\">Limits</h1>\n<p>A <em>limit</em> is <strong>close</strong>.</p>\n<hr>\n"
+        );
+        assert_eq!(html("Usage\n====="), "<h1 id=\"usage\">Usage</h1>\n");
+        assert_eq!(
+            html("> quoted\ncontinued"),
+            "<blockquote>\n<p>quoted\ncontinued</p>\n</blockquote>\n"
+        );
+        assert_eq!(
+            html("```haskell\nlet x = 1 < 2;\n```"),
+            "<pre><code class=\"language-haskell\">let x = 1 &lt; 2;\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn highlight() {
+        assert_eq!(
+            html("```rust\nlet x = f(1) < 'a'; // Less\nprintln!(\"{}\", x as Foo);\n```"),
+            "<pre><code class=\"language-rust\">\
+             <span class=\"hl-keyword\">let</span> x = <span class=\"hl-function\">f</span>(\
+             <span class=\"hl-number\">1</span>) &lt; <span class=\"hl-string\">'a'</span>; \
+             <span class=\"hl-comment\">// Less</span>\n\
+             <span class=\"hl-macro\">println!</span>(<span class=\"hl-string\">\"{}\"</span>, \
+             x <span class=\"hl-keyword\">as</span> <span class=\"hl-type\">Foo</span>);\n\
+             </code></pre>\n"
+        );
+        assert_eq!(
+            html("```python\ndef f(x):\n    \"\"\"Doc\n    string\"\"\"\n    return None # x\n```"),
+            "<pre><code class=\"language-python\">\
+             <span class=\"hl-keyword\">def</span> <span class=\"hl-function\">f</span>(x):\n    \
+             <span class=\"hl-string\">\"\"\"Doc\n    string\"\"\"</span>\n    \
+             <span class=\"hl-keyword\">return</span> <span class=\"hl-builtin\">None</span> \
+             <span class=\"hl-comment\"># x</span>\n\
+             </code></pre>\n"
+        );
+        assert_eq!(
+            html("```js\nconst s = `a\nb`; /* <b> */\n```"),
+            "<pre><code class=\"language-js\">\
+             <span class=\"hl-keyword\">const</span> s = <span class=\"hl-string\">`a\nb`</span>; \
+             <span class=\"hl-comment\">/* &lt;b&gt; */</span>\n\
+             </code></pre>\n"
+        );
+        assert_eq!(
+            html("```latex\n\\frac{1}{2} % half\n```"),
+            "<pre><code class=\"language-latex\">\
+             <span class=\"hl-command\">\\frac</span><span class=\"hl-keyword\">{</span>\
+             <span class=\"hl-number\">1</span><span class=\"hl-keyword\">}</span>\
+             <span class=\"hl-keyword\">{</span><span class=\"hl-number\">2</span>\
+             <span class=\"hl-keyword\">}</span> <span class=\"hl-comment\">% half</span>\n\
+             </code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            html("- one\n- two\n  - nested"),
+            "<ul>\n<li>one</li>\n<li>two\n<ul>\n<li>nested</li>\n</ul></li>\n</ul>\n"
+        );
+        assert_eq!(
+            html("3. three\n\n4. four"),
+            "<ol start=\"3\">\n<li>\n<p>three</p>\n</li>\n<li>\n<p>four</p>\n</li>\n</ol>\n"
+        );
+    }
+
+    #[test]
+    fn inlines() {
+        assert_eq!(
+            html("[Stewart](https://example.com \"Calculus\") and `x_1`"),
+            "<p><a href=\"https://example.com\" title=\"Calculus\">Stewart</a> and <code>x_1</code></p>\n"
+        );
+        assert_eq!(
+            html("See [spivak].\n\n[spivak]: /books/spivak"),
+            "<p>See <a href=\"/books/spivak\">spivak</a>.</p>\n"
+        );
+        assert_eq!(html("snake_case_name"), "<p>snake_case_name</p>\n");
+        assert_eq!(
+            html("***both***"),
+            "<p><em><strong>both</strong></em></p>\n"
+        );
+        assert_eq!(
+            html("<https://example.com> \\*x\\*"),
+            "<p><a href=\"https://example.com\">https://example.com</a> *x*</p>\n"
+        );
+    }
+
+    #[test]
+    fn raw_html() {
+        let source = "<h1>Usage</h1> <p>This is almost entirely for testing purposes</p>";
+        assert_eq!(html(source), format!("{}\n", source));
+        assert_eq!(
+            html("An <b>inline</b> tag &amp; entity"),
+            "<p>An <b>inline</b> tag &amp; entity</p>\n"
+        );
+    }
+
+    #[test]
+    fn macros() {
+        let source = "\
+@macro definition(term: String, body: String, number: Integer = 1)
+<div class=\"definition\">
+
+**Definition {{number}}** ({{term}}). {{body}}
+
+</div>
+@end
+
+@macro sq(x: Real)
+{{x}}<sup>2</sup>
+@end
+
+@definition(\"Limit\", body=\"The value *approached*.\", number=3)
+
+Area is @sq(2.5).";
+        assert_eq!(
+            html(source),
+            "<div class=\"definition\">\n<p><strong>Definition 3</strong> (Limit). The value <em>approached</em>.</p>\n</div>\n<p>Area is 2.5<sup>2</sup>.</p>\n"
+        );
+        assert_eq!(html("mail me@example.com"), "<p>mail me@example.com</p>\n");
+    }
+
+    #[test]
+    fn macro_errors() {
+        let source = "@macro m(n: Integer)\n{{n}}\n@end\n\n@m(\"two\")\n\n@missing()";
+        let diagnostics = compile(source).unwrap_err();
+        assert_eq!(diagnostics.0.len(), 2);
+        assert_eq!((diagnostics.0[0].line, diagnostics.0[0].column), (5, 4));
+        assert!(diagnostics.0[0]
+            .message
+            .contains("should be Integer, found String"));
+        assert_eq!(diagnostics.0[1].message, "Unknown macro `missing`");
+
+        let diagnostics = compile("@macro loop()\n@loop()\n@end\n\n@loop()").unwrap_err();
+        assert!(diagnostics.0.iter().all(|x| x.line == 5));
+        assert!(compile("@macro bad(x: Float)\n@end").is_err());
+    }
+
+    #[test]
+    fn environments() {
+        let source = "\
+:::definition Limit {#limit}
+*f* approaches *L*.
+:::
+
+## Continuity
+
+:::theorem *Intermediate* values {#ivt}
+By @ref(limit) and @ref(evt).
+
+:::proof
+Bisect.
+:::
+:::
+
+:::theorem {#evt}
+:::";
+        assert_eq!(
+            html(source),
+            "<section class=\"definition\" id=\"limit\">\n\
+             <p class=\"environment-head\"><strong>Definition 0.1</strong> (Limit).</p>\n\
+             <p><em>f</em> approaches <em>L</em>.</p>\n\
+             </section>\n\
+             <h2 id=\"continuity\">Continuity</h2>\n\
+             <section class=\"theorem\" id=\"ivt\">\n\
+             <p class=\"environment-head\"><strong>Theorem 1.1</strong> (<em>Intermediate</em> values).</p>\n\
+             <p>By <a class=\"ref\" href=\"#limit\">Definition 0.1</a> and \
+             <a class=\"ref\" href=\"#evt\">Theorem 1.2</a>.</p>\n\
+             <section class=\"proof\">\n\
+             <p class=\"environment-head\"><strong>Proof</strong>.</p>\n\
+             <p>Bisect.</p>\n\
+             </section>\n\
+             </section>\n\
+             <section class=\"theorem\" id=\"evt\">\n\
+             <p class=\"environment-head\"><strong>Theorem 1.2</strong>.</p>\n\
+             </section>\n"
+        );
+        assert!(html(":::example\n:::\n\n:::example\n:::").contains("Example 2"));
+
+        let diagnostics = compile(
+            ":::lemma {#a}\n:::\n\n:::lemma {#a}\n:::\n\nSee @ref(b).\n\n:::proof {#c}\n:::\n\n:::axiom\n:::",
+        )
+        .unwrap_err();
+        let found = diagnostics
+            .0
+            .iter()
+            .map(|x| (x.line, x.column, x.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (4, 1, "Label `a` is used twice"),
+                (7, 5, "No environment is labelled `b`"),
+                (9, 10, "A proof is not numbered, so cannot be labelled"),
+                (12, 4, "Unknown environment `axiom`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn links() {
+        let library = Library::new(
+            [
+                ("Limits", "# Limits\n\n## One sided limits\n\nFrom the left.\n\n### Notation\n\nArrows.\n\n## Continuity"),
+                ("Loop A", "@include(\"Loop B\")"),
+                ("Loop B", "@include(\"loop a\")"),
+                ("Broken", "@missing()"),
+            ]
+            .map(|(title, source)| (title.to_string(), source.to_string())),
+        );
+
+        let source = "See [[limits]], [[Limits#One sided limits|the left]].\n\n@include(\"Limits\", section=\"one sided limits\")";
+        assert_eq!(
+            super::compile("", source, &library).unwrap().html.0,
+            "<p>See <a href=\"/notes/Limits\">limits</a>, <a href=\"/notes/Limits#one-sided-limits\">the left</a>.</p>\n\
+             <h2 id=\"one-sided-limits\">One sided limits</h2>\n<p>From the left.</p>\n\
+             <h3 id=\"notation\">Notation</h3>\n<p>Arrows.</p>\n"
+        );
+        assert_eq!(href("Mean value theorem"), "/notes/Mean%20value%20theorem");
+        assert_eq!(
+            outgoing(
+                "[[Limits|limits]], [[limits#Notation]] and [x](/notes/Mean%20value%20theorem#proof)\n\n\
+                 <a href=\"/notes/Derivatives\">d</a> https://example.com/notes/Other\n\n@include(\"Series\")"
+            ),
+            ["Limits", "Series", "Mean value theorem", "Derivatives"]
+        );
+
+        let source = "[[Nothing]]\n\n@include(\"Loop A\")\n\n@include(\"Broken\")\n\n\
+                      @include(\"Limits\", section=\"Derivatives\")\n\nInline @include(\"Limits\").";
+        let diagnostics = super::compile("", source, &library).unwrap_err();
+        let found = diagnostics
+            .0
+            .iter()
+            .map(|x| (x.line, x.column, x.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (1, 1, "No note is titled `Nothing`"),
+                (3, 1, "`Loop A` includes itself"),
+                (5, 1, "`Broken` has errors, so cannot be included"),
+                (7, 1, "`Limits` has no section `Derivatives`"),
+                (9, 8, "`@include` must stand on its own line"),
+            ]
+        );
+
+        // The note's own title counts, even before its source is saved
+        let diagnostics = super::validate("limits", "# Limits\n\n@include(\"Limits\")", &library);
+        assert_eq!(diagnostics.0[0].message, "`Limits` includes itself");
+    }
+
+    #[test]
+    fn citations() {
+        let work = |author: &str, year| Work {
+            author: author.to_string(),
+            title: "Calculus".to_string(),
+            year,
+            publisher: String::new(),
+            url: String::new(),
+        };
+        let library = Library::new([(
+            "History".to_string(),
+            "Weierstrass.[^who]\n\n[^who]: Karl.".to_string(),
+        )])
+        .with_references([
+            ("Stewart".to_string(), work("Stewart", 2015)),
+            ("spivak".to_string(), work("Spivak", 0)),
+        ]);
+
+        let source = "\
+Limits.[^history] See [@stewart, p. 92] or [@spivak; @Stewart].
+
+[^history]: Made rigorous [@spivak].
+
+@include(\"History\")";
+        assert_eq!(
+            super::compile("", source, &library).unwrap().html.0,
+            "<p>Limits.<sup class=\"footnote-ref\"><a href=\"#fn-1\">1</a></sup> See \
+             <span class=\"citation\">[<a href=\"#ref-1\">1</a>, p. 92]</span> or \
+             <span class=\"citation\">[<a href=\"#ref-2\">2</a>; <a href=\"#ref-1\">1</a>]</span>.</p>\n\
+             <p>Weierstrass.<sup class=\"footnote-ref\"><a href=\"#fn-2\">2</a></sup></p>\n\
+             <section class=\"footnotes\">\n<ol>\n\
+             <li id=\"fn-1\">Made rigorous <span class=\"citation\">[<a href=\"#ref-2\">2</a>]</span>.</li>\n\
+             <li id=\"fn-2\">Karl.</li>\n\
+             </ol>\n</section>\n\
+             <section class=\"bibliography\">\n<h2>References</h2>\n<ol>\n\
+             <li id=\"ref-1\">Stewart (2015). <em>Calculus</em>.</li>\n\
+             <li id=\"ref-2\">Spivak. <em>Calculus</em>.</li>\n\
+             </ol>\n</section>\n"
+        );
+
+        let diagnostics =
+            super::compile("", "A[^a] [@nobody].\n\n[^b]: Unused.", &library).unwrap_err();
+        let found = diagnostics
+            .0
+            .iter()
+            .map(|x| (x.line, x.column, x.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (1, 2, "Footnote `a` is never defined"),
+                (1, 7, "No reference has the key `nobody`"),
+                (3, 7, "Footnote `b` is never referenced"),
+            ]
+        );
+    }
+
+    #[test]
+    fn exercises() {
+        let source = "\
+@exercise(
+    id=\"sinc\",
+    prompt=\"Find *the* limit.\",
+    answer=\"1\",
+    tolerance=0.001,
+    hints=\"Squeeze it.\\nOr differentiate.\",
+    solution=\"It is **1**.\"
+)
+
+## Integrals
+
+@exercise(id=\"area\", prompt=\"Integrate.\", answer=\"x^2/2\", hints=\"Power rule.\")";
+        let compiled = compile(source).unwrap();
+        assert_eq!(
+            compiled.html.0,
+            "<section class=\"exercise\" id=\"exercise-sinc\">\n\
+             <p class=\"environment-head\"><strong>Exercise</strong>.</p>\n\
+             <p>Find <em>the</em> limit.</p>\n\
+             <details class=\"hint\">\n<summary>Hint 1</summary>\n<p>Squeeze it.</p>\n</details>\n\
+             <details class=\"hint\">\n<summary>Hint 2</summary>\n<p>Or differentiate.</p>\n</details>\n\
+             <details class=\"solution\">\n<summary>Solution</summary>\n<p>It is <strong>1</strong>.</p>\n</details>\n\
+             </section>\n\
+             <h2 id=\"integrals\">Integrals</h2>\n\
+             <section class=\"exercise\" id=\"exercise-area\">\n\
+             <p class=\"environment-head\"><strong>Exercise</strong>.</p>\n\
+             <p>Integrate.</p>\n\
+             <details class=\"hint\">\n<summary>Hint</summary>\n<p>Power rule.</p>\n</details>\n\
+             </section>\n"
+        );
+        assert_eq!(
+            compiled.exercises,
+            [
+                Exercise {
+                    id: "sinc".to_string(),
+                    section: String::new(),
+                    answer: Answer {
+                        expected: "1".to_string(),
+                        tolerance: 0.001
+                    }
+                },
+                Exercise {
+                    id: "area".to_string(),
+                    section: "integrals".to_string(),
+                    answer: Answer {
+                        expected: "x^2/2".to_string(),
+                        tolerance: 1e-6
+                    }
+                },
+            ]
+        );
+
+        // Included exercises are graded as the included note's,
+        // even when included through another note
+        let library = Library::new([
+            ("Sinc".to_string(), source.to_string()),
+            ("Outer".to_string(), "@include(\"Sinc\")".to_string()),
+        ]);
+        for title in ["Sinc", "Outer"] {
+            let call = format!("@include(\"{}\")", title);
+            let included = super::compile("", &call, &library).unwrap();
+            assert!(included
+                .html
+                .0
+                .contains("<section class=\"exercise\" id=\"exercise-area\" data-note=\"Sinc\">"));
+            assert!(included.exercises.is_empty());
+        }
+
+        let diagnostics = compile(
+            "@exercise(id=\"a b\", prompt=\"\", answer=\"x +\")\n\n\
+             @exercise(id=\"c\", prompt=\"\", answer=\"1\", tolerance=0)\n\n\
+             @exercise(id=\"c\", prompt=\"\", answer=\"1\")\n\n\
+             Inline @exercise(id=\"d\", prompt=\"\", answer=\"1\")",
+        )
+        .unwrap_err();
+        let found = diagnostics
+            .0
+            .iter()
+            .map(|x| (x.line, x.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (1, "Invalid exercise id `a b`, use letters, digits and `-_`"),
+                (1, "Expected a value in `x +` at column 4"),
+                (3, "`tolerance` should be positive"),
+                (5, "Exercise `c` is defined twice"),
+                (7, "`@exercise` must stand on its own line"),
+            ]
+        );
+    }
+
+    #[test]
+    fn flashcards() {
+        let source = "\
+:::definition Limit {#limit}
+*f* approaches *L*.
+:::
+
+:::theorem
+:::proof
+Bisect.
+:::
+:::
+
+:::remark
+Not a card.
+:::
+
+<div class=\"lemma\" id=\"squeeze\">
+
+**Squeeze.** Bounded *between*.
+
+</div>
+
+<div class=\"corollary\"><b>Raw</b> card</div>";
+        let cards = compile(source)
+            .unwrap()
+            .flashcards
+            .into_iter()
+            .map(|x| (x.front, x.back, x.label))
+            .collect::<Vec<_>>();
+        let card = |front: &str, back: &str, label: &str| {
+            (front.to_string(), back.to_string(), label.to_string())
+        };
+        assert_eq!(
+            cards,
+            [
+                card(
+                    "Definition 1 (Limit)",
+                    "<p><em>f</em> approaches <em>L</em>.</p>",
+                    "limit"
+                ),
+                card(
+                    "Theorem 2",
+                    "<section class=\"proof\">\n\
+                     <p class=\"environment-head\"><strong>Proof</strong>.</p>\n\
+                     <p>Bisect.</p>\n\
+                     </section>",
+                    ""
+                ),
+                card("Squeeze", "<p>Bounded <em>between</em>.</p>", "squeeze"),
+                card("Corollary", "<b>Raw</b> card", ""),
+            ]
+        );
+
+        // Attribute values may hold `>`, however they are escaped
+        let cards = flashcards::flashcards(
+            "<div class=\"lemma\" title=\"x > 0\" id=\"positive\">\n\
+             <p><strong>Positive.</strong> Above <a title=\"x > 0\" href=\"/zero\">zero</a>.</p>\n\
+             </div>",
+        );
+        assert_eq!(
+            cards,
+            [Flashcard {
+                front: "Positive".to_string(),
+                back: "<p>Above <a title=\"x > 0\" href=\"/zero\">zero</a>.</p>".to_string(),
+                label: "positive".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn math() {
+        let inline = html("Let $f'(x) = x^2$ cost $5 and $10.");
+        assert!(
+            inline.starts_with("<p>Let <math><semantics><mrow><msup><mi>f</mi><mo>′</mo></msup>")
+        );
+        assert!(inline.contains("<msup><mi>x</mi><mn>2</mn></msup>"));
+        assert!(inline.ends_with("cost $5 and $10.</p>\n"));
+
+        let display = html("$$\n\\lim_{h \\to 0} \\frac{f(x+h)-f(x)}{h}\n$$");
+        assert!(display.starts_with("<math display=\"block\">"));
+        assert!(display
+            .contains("<munder><mi>lim</mi><mrow><mi>h</mi><mo>→</mo><mn>0</mn></mrow></munder>"));
+        assert!(display.contains("<mfrac>"));
+
+        let align = html("$$\\begin{align} a &= b \\\\ c &= \\int_0^1 x\\,dx \\end{align}$$");
+        assert!(align.contains("<mtable columnalign"));
+        assert_eq!(align.matches("<mtr>").count(), 2);
+        assert!(align.contains("<msubsup><mo>∫</mo><mn>0</mn><mn>1</mn></msubsup>"));
+
+        let matrix = html("$\\begin{pmatrix} 1 & 0 \\\\ 0 & \\alpha \\end{pmatrix}$");
+        assert!(matrix.contains("<mi>α</mi>"));
+        assert!(matrix.contains(">(</mo>"));
+    }
+
+    #[test]
+    fn math_errors() {
+        let diagnostics = compile("Text\n\nSee $\\frac{1}{2$ and $\\foo$.").unwrap_err();
+        assert_eq!(diagnostics.0.len(), 2);
+        assert_eq!((diagnostics.0[0].line, diagnostics.0[0].column), (3, 14));
+        assert_eq!(diagnostics.0[0].message, "Missing `}`");
+        assert_eq!((diagnostics.0[1].line, diagnostics.0[1].column), (3, 23));
+        assert_eq!(diagnostics.0[1].message, "Unknown command `\\foo`");
+
+        let diagnostics = compile("$$\nx^{2\n$$").unwrap_err();
+        assert_eq!((diagnostics.0[0].line, diagnostics.0[0].column), (2, 3));
+        assert!(compile("$$ x").is_err());
+    }
+
+    #[test]
+    fn unclosed_fence() {
+        let diagnostics = validate("text\n\n```\ncode");
+        assert_eq!(diagnostics.0.len(), 1);
+        assert_eq!(diagnostics.0[0].line, 3);
+        assert_eq!(diagnostics.0[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn nesting() {
+        let quotes = html(&format!("{}deep", "> ".repeat(10)));
+        assert_eq!(quotes.matches("<blockquote>").count(), 10);
+        assert!(quotes.contains("<p>deep</p>"));
+
+        for source in [
+            format!("{}deep", "> ".repeat(5000)),
+            format!("{}deep", "- ".repeat(5000)),
+            format!("{}x{}", ":::proof\n".repeat(5000), "\n:::".repeat(5000)),
+            format!("{}x{}", "[".repeat(5000), "](a)".repeat(5000)),
+            format!("${}x{}$", "{".repeat(5000), "}".repeat(5000)),
+            format!("$x{}$", "^{x".repeat(5000) + &"}".repeat(5000)),
+            format!("${}x$", "\\hat ".repeat(5000)),
+        ] {
+            let diagnostics = compile(&source).unwrap_err();
+            assert!(diagnostics
+                .0
+                .iter()
+                .any(|x| x.message.ends_with("nested too deeply")));
+        }
+    }
+
+    #[test]
+    fn plot() {
+        let svg = html("@plot(f=\"x^2; 2x - 1\", from=-2, to=2, shade=[0, 1])");
+        assert!(svg.starts_with("<figure class=\"plot\">\n<svg "));
+        assert_eq!(svg.matches("class=\"plot-curve\"").count(), 2);
+        assert_eq!(svg.matches("class=\"plot-shade\"").count(), 1);
+        assert!(svg.contains(">y = 2x - 1</text>"));
+        assert!(svg.contains("text-anchor=\"middle\">-2</text>"));
+
+        // The asymptote at 0 leaves a gap rather than a vertical line
+        let svg = html("@plot(f=\"1/x\")");
+        assert_eq!(svg.matches('M').count(), 2);
+
+        let diagnostics = compile("@plot(f=\"x^\")\n\n@plot(f=\"t\", from=1, to=0)").unwrap_err();
+        assert_eq!(
+            diagnostics.0[0].message,
+            "Expected a value in `x^` at column 3"
+        );
+        assert_eq!(diagnostics.0[1].message, "`from` should be less than `to`");
+        assert!(compile("@plot(f=\"t\")").is_err());
+        assert!(compile("@plot(f=\"x\", shade=[0, \"a\"])").is_err());
+
+        // Values too large to scale onto the SVG aren't drawn as NaN
+        let diagnostics = compile("@plot(f=\"1e308*x\")\n\n@plot(f=\"x\", to=1e999)").unwrap_err();
+        assert_eq!(
+            diagnostics.0[0].message,
+            "`1e308*x` is too large to plot between `from` and `to`"
+        );
+        assert_eq!(
+            diagnostics.0[1].message,
+            "`from` and `to` are too far apart to plot"
+        );
+        let diagnostics = compile("@plot(f=\"x\", shade=[1, 0])").unwrap_err();
+        assert_eq!(
+            diagnostics.0[0].message,
+            "`shade` should go from the lower bound to the higher"
+        );
+        assert!(compile("@macro plot()\n@end").is_err());
+    }
+
+    #[test]
+    fn deriv() {
+        let inline = html("So @deriv(f=\"sin(t)*t^2\", x=\"t\").");
+        assert!(inline.starts_with("<p>So <math>"));
+        assert!(inline.contains("<annotation encoding=\"application/x-tex\">\\frac{d}{dt}\\left(\\sin\\left(t\\right) t^{2}\\right) = t^{2} \\cos\\left(t\\right) + 2 t \\sin\\left(t\\right)</annotation>"));
+
+        let display = html("@deriv(f=\"x^3\", display=true)");
+        assert!(display.starts_with("<math display=\"block\">"));
+
+        // Powers of `exp` are parenthesized rather than stacked
+        let exp = html("@deriv(f=\"exp(x)^2\")");
+        assert!(exp.contains("\\frac{d}{dx}\\left(\\left(e^{x}\\right)^{2}\\right) = "));
+        assert!(!exp.contains("}^{"));
+
+        let diagnostics = compile("@deriv(f=\"x^\")\n\n@deriv(f=\"x\", x=\"xy\")").unwrap_err();
+        assert_eq!(
+            diagnostics.0[0].message,
+            "Expected a value in `x^` at column 3"
+        );
+        assert_eq!(
+            diagnostics.0[1].message,
+            "`x` should be a single letter, found `xy`"
+        );
+    }
+
+    #[test]
+    fn sanitize() {
+        assert_eq!(
+            html("<div onclick=\"steal()\">a</div>\n\n<script>steal()</script>"),
+            "<div>a</div>\n\n"
+        );
+        assert_eq!(
+            html("[x](javascript:steal()) <img src=\"x\" onerror=\"steal()\">"),
+            "<p><a>x</a> <img src=\"x\"></p>\n"
+        );
+
+        let plot = "<svg viewBox=\"0 0 10 10\"><path d=\"M0 0L10 10\" stroke=\"red\" onload=\"steal()\"></path></svg>";
+        assert_eq!(
+            html(plot),
+            "<p><svg viewBox=\"0 0 10 10\"><path d=\"M0 0L10 10\" stroke=\"red\"></path></svg></p>\n"
+        );
+        assert!(html("$x$").contains("<annotation encoding=\"application/x-tex\">x</annotation>"));
+        assert!(html("<table class=\"grid\"><tr><td>1</td></tr></table>")
+            .starts_with("<table class=\"grid\"><tbody><tr><td>1</td>"));
+    }
+
+    #[test]
+    fn unbalanced_html() {
+        assert!(!validate("<div>\n\n*a*\n\n</div>\n\n<ul><li>one<li>two</ul>").has_errors());
+        assert!(!validate("a <span>b</span> <br> <img src=\"x\" />").has_errors());
+
+        let diagnostics = validate("<div>\n<span>\n</div>\n\ntext </em> `code");
+        let found = diagnostics
+            .0
+            .iter()
+            .map(|x| (x.line, x.column, x.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (2, 1, "`<span>` is never closed"),
+                (5, 6, "`</em>` has no matching `<em>`"),
+                (5, 12, "Code span is never closed"),
+            ]
+        );
+        assert_eq!(diagnostics.0[2].severity, Severity::Warning);
+
+        // Notes with errors can still be shown, as well as they parse
+        let rendered = render("", "<div>\n<span>\n</div>\n\ntext @missing()", &Library::default());
+        assert_eq!(
+            rendered.html.as_str(),
+            "<div>\n<span>\n</span></div>\n<p>text @missing()</p>\n"
+        );
+    }
+
+    #[test]
+    fn outline() {
+        let compiled = compile(
+            "# Limits\n## The ε-δ *definition*\n### Examples\n## Examples\n# Limits!\n> # Quoted",
+        )
+        .unwrap();
+        let slugs = |x: &[Heading]| x.iter().map(|x| x.slug.clone()).collect::<Vec<_>>();
+
+        assert_eq!(slugs(&compiled.outline), ["limits", "limits-1"]);
+        let children = &compiled.outline[0].children;
+        assert_eq!(slugs(children), ["the-ε-δ-definition", "examples-1"]);
+        assert_eq!(children[0].title, "The ε-δ definition");
+        assert_eq!(slugs(&children[0].children), ["examples"]);
+        assert!(compiled.outline[1].children.is_empty());
+
+        assert!(compiled.html.0.contains("<h2 id=\"the-ε-δ-definition\">"));
+        assert!(compiled.html.0.contains("<h1>Quoted</h1>"));
+        assert_eq!(outline::slug(" -- "), "section");
+    }
+}
@@ -0,0 +1,92 @@
+//! # Usage
+//! Collects the headings of a note into a nested outline for
+//! a table of contents, giving each one an anchor. Anchors are
+//! slugs of the heading text, so they stay the same as long as
+//! the heading does:
+//! ```markdown
+//! ## The ε-δ definition   -> #the-ε-δ-definition
+//! ## Examples             -> #examples
+//! ## Examples             -> #examples-1
+//! ```
+//! Only headings at the top level of a note are included, not
+//! those inside of quotes or lists.
+
+use super::ast::{Block, Inline};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// # Usage
+/// A heading along with the headings below it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Heading {
+    pub level: u8,
+    pub title: String,
+    pub slug: String,
+    pub children: Vec<Heading>,
+}
+
+/// # Usage
+/// Turns text into an anchor: lowercase letters and
+/// digits, with words joined by `-`.
+pub fn slug(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() {
+            out.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+
+    let out = out.trim_matches('-');
+    if out.is_empty() {
+        return "section".to_string();
+    }
+    return out.to_string();
+}
+
+/// # Usage
+/// Gives every top level heading an anchor, returning the outline.
+pub fn outline(blocks: &mut [Block]) -> Vec<Heading> {
+    let mut used: HashSet<String> = HashSet::new();
+    let mut flat: Vec<Heading> = Vec::new();
+
+    for block in blocks {
+        if let Block::Heading { level, content, id } = block {
+            let title = Inline::plain(content);
+            let base = slug(&title);
+            let mut slug = base.clone();
+            let mut n = 0;
+            while used.contains(&slug) {
+                n += 1;
+                slug = format!("{}-{}", base, n);
+            }
+            used.insert(slug.clone());
+
+            *id = Some(slug.clone());
+            flat.push(Heading {
+                level: *level,
+                title,
+                slug,
+                children: Vec::new(),
+            });
+        }
+    }
+
+    return nest(&mut flat.into_iter().peekable(), 0);
+}
+
+/// # Usage
+/// Takes headings deeper than `level` off the front of
+/// `headings`, each one holding those deeper than itself.
+fn nest(
+    headings: &mut std::iter::Peekable<impl Iterator<Item = Heading>>,
+    level: u8,
+) -> Vec<Heading> {
+    let mut out = Vec::new();
+    while let Some(mut heading) = headings.next_if(|x| x.level > level) {
+        heading.children = nest(headings, heading.level);
+        out.push(heading);
+    }
+    return out;
+}
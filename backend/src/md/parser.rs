@@ -0,0 +1,569 @@
+//! # Usage
+//! Groups the lines produced by the [lexer](super::lexer)
+//! into [blocks](super::ast::Block). Inline content is
+//! handled in [inline](super::inline).
+
+use super::{
+    ast::Block,
+    cite,
+    inline::Text,
+    lexer::{self, HtmlEnd, Line, LineKind},
+    links::Library,
+    macros::{self, Macro},
+    tex,
+    validate::Tag,
+    Diagnostic, Diagnostics, Severity, Span,
+};
+use std::collections::{HashMap, HashSet};
+
+/// How deeply blocks, such as quotes and lists, and the text of
+/// links may nest, so that notes can't recurse without bound.
+pub const MAX_NESTING: usize = 64;
+
+/// # Usage
+/// The target of a reference style link, e.g.
+/// ```markdown
+/// [stewart]: https://www.stewartcalculus.com "Stewart's Calculus"
+/// ```
+pub struct LinkDef {
+    pub href: String,
+    pub title: Option<String>,
+}
+
+/// # Usage
+/// Holds the state of a single compilation: the source,
+/// link and macro definitions and the problems found so far.
+pub struct Parser<'s> {
+    pub source: &'s str,
+    pub links: HashMap<String, LinkDef>,
+    /// The text of each footnote, by label.
+    pub footnotes: HashMap<String, Line<'s>>,
+    pub macros: HashMap<String, Macro>,
+    /// Offsets of the definitions registered ahead of time.
+    pub defined: HashSet<usize>,
+    /// How many macros are currently being expanded.
+    pub depth: usize,
+    /// How many blocks and links are open around the current content.
+    pub nesting: usize,
+    /// The invocation being expanded, where problems are reported.
+    pub expansion: Option<Span>,
+    /// Tags of raw HTML in the order they appear.
+    pub tags: Vec<Tag>,
+    /// The notes which can be linked to and included.
+    pub library: &'s Library,
+    /// Titles of the notes being included, in lowercase,
+    /// so that notes including themselves are caught.
+    pub including: Vec<String>,
+    /// Ids of the exercises of the note, which must be unique.
+    pub exercises: HashSet<String>,
+    reports: Vec<(Span, Severity, String)>,
+}
+
+impl<'s> Parser<'s> {
+    pub fn new(source: &'s str, library: &'s Library) -> Self {
+        return Parser {
+            source,
+            links: HashMap::new(),
+            footnotes: HashMap::new(),
+            macros: macros::builtins(),
+            defined: HashSet::new(),
+            depth: 0,
+            nesting: 0,
+            expansion: None,
+            tags: Vec::new(),
+            library,
+            including: Vec::new(),
+            exercises: HashSet::new(),
+            reports: Vec::new(),
+        };
+    }
+
+    /// # Usage
+    /// Parses the whole source into a list of blocks.
+    pub fn parse(&mut self) -> Vec<Block> {
+        let lines = lexer::lines(self.source, 0);
+        self.definitions(&lines);
+        let mut blocks = self.blocks(&lines);
+        self.number(&mut blocks);
+        self.cite(&mut blocks);
+        return blocks;
+    }
+
+    /// # Usage
+    /// Records a problem at the given span of the source.
+    pub fn report(&mut self, span: Span, severity: Severity, message: impl Into<String>) {
+        let span = self.expansion.unwrap_or(span);
+        self.reports.push((span, severity, message.into()));
+    }
+
+    /// # Usage
+    /// Consumes the parser, checking that the HTML of the note
+    /// is balanced and resolving the byte offsets of every
+    /// report into lines and columns.
+    pub fn finish(mut self) -> Diagnostics {
+        self.balance();
+        let mut out = Vec::new();
+        for (span, severity, message) in self.reports {
+            let (line, column) = position(self.source, span.start);
+            out.push(Diagnostic {
+                message,
+                line,
+                column,
+                severity,
+            });
+        }
+        out.sort_by_key(|x| (x.line, x.column));
+        return Diagnostics(out);
+    }
+
+    /// # Usage
+    /// Parses a run of lines into blocks. Container blocks
+    /// strip their markers and call this recursively, up to
+    /// [MAX_NESTING] deep.
+    pub fn blocks(&mut self, lines: &[Line]) -> Vec<Block> {
+        if self.nesting == MAX_NESTING {
+            if let Some(line) = lines.iter().find(|x| !x.is_blank()) {
+                self.report(
+                    Span::at(line.trim_start().offset),
+                    Severity::Error,
+                    "Content is nested too deeply",
+                );
+            }
+            return Vec::new();
+        }
+        self.nesting += 1;
+        let out = self.nested(lines);
+        self.nesting -= 1;
+        return out;
+    }
+
+    fn nested(&mut self, lines: &[Line]) -> Vec<Block> {
+        let mut out = Vec::new();
+        let mut i = 0;
+
+        while i < lines.len() {
+            let line = lines[i];
+            match lexer::classify(line) {
+                LineKind::Blank => i += 1,
+                LineKind::Heading { level, content } => {
+                    let content = self.inline(&[content]);
+                    out.push(Block::Heading {
+                        level,
+                        content,
+                        id: None,
+                    });
+                    i += 1;
+                }
+                LineKind::Fence {
+                    marker,
+                    len,
+                    indent,
+                    info,
+                } => out.push(self.fence(lines, &mut i, marker, len, indent, info)),
+                LineKind::Rule => {
+                    out.push(Block::Rule);
+                    i += 1;
+                }
+                LineKind::Quote(_) => out.push(self.quote(lines, &mut i)),
+                LineKind::Item { .. } => out.push(self.list(lines, &mut i)),
+                LineKind::Html(end) => out.push(self.html(lines, &mut i, end)),
+                LineKind::Math => out.push(self.math(lines, &mut i)),
+                LineKind::Environment { name, rest } => {
+                    out.extend(self.environment(lines, &mut i, name, rest))
+                }
+                LineKind::EnvironmentEnd => {
+                    self.report(
+                        Span::at(line.trim_start().offset),
+                        Severity::Error,
+                        "`:::` without a matching environment",
+                    );
+                    i += 1;
+                }
+                LineKind::Define => {
+                    if !self.defined.contains(&line.trim_start().offset) {
+                        self.report(
+                            Span::at(line.trim_start().offset),
+                            Severity::Error,
+                            "Macros can only be defined at the top level of a note",
+                        );
+                    }
+                    i += 1;
+                    while i < lines.len() && !matches!(lexer::classify(lines[i]), LineKind::End) {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                LineKind::End => {
+                    self.report(
+                        Span::at(line.trim_start().offset),
+                        Severity::Error,
+                        "`@end` without a matching `@macro`",
+                    );
+                    i += 1;
+                }
+                LineKind::Call => {
+                    let before = i;
+                    match self.block_call(lines, &mut i) {
+                        Some(blocks) => out.extend(blocks),
+                        None if i == before => out.push(self.paragraph(lines, &mut i)),
+                        None => {}
+                    }
+                }
+                LineKind::Text => {
+                    if cite::defines(line) || definition(line.text).is_some() {
+                        i += 1;
+                        continue;
+                    }
+                    out.push(self.paragraph(lines, &mut i));
+                }
+            }
+        }
+
+        return out;
+    }
+
+    fn paragraph(&mut self, lines: &[Line], i: &mut usize) -> Block {
+        let mut content = vec![lines[*i]];
+        *i += 1;
+
+        while *i < lines.len() {
+            let line = lines[*i];
+            if let Some(level) = lexer::setext(line) {
+                *i += 1;
+                return Block::Heading {
+                    level,
+                    content: self.inline(&content),
+                    id: None,
+                };
+            }
+
+            let interrupts = match lexer::classify(line) {
+                LineKind::Text => false,
+                LineKind::Call => self.standalone(lines, *i).is_some(),
+                // Only non empty lists starting at one may interrupt a paragraph.
+                LineKind::Item { start, content, .. } => {
+                    !content.is_blank() && matches!(start, None | Some(1))
+                }
+                _ => true,
+            };
+            if interrupts {
+                break;
+            }
+
+            content.push(line);
+            *i += 1;
+        }
+
+        return Block::Paragraph(self.inline(&content));
+    }
+
+    fn fence(
+        &mut self,
+        lines: &[Line],
+        i: &mut usize,
+        marker: char,
+        len: usize,
+        indent: usize,
+        info: &str,
+    ) -> Block {
+        let open = lines[*i];
+        let mut text = String::new();
+        let mut closed = false;
+        *i += 1;
+
+        while *i < lines.len() {
+            let line = lines[*i];
+            *i += 1;
+            if let LineKind::Fence {
+                marker: m,
+                len: l,
+                info: "",
+                ..
+            } = lexer::classify(line)
+            {
+                if m == marker && l >= len {
+                    closed = true;
+                    break;
+                }
+            }
+            text.push_str(line.dedent(indent).text);
+            text.push('\n');
+        }
+
+        if !closed {
+            self.report(
+                Span::at(open.offset + indent),
+                Severity::Warning,
+                "Code block is never closed",
+            );
+        }
+
+        let lang = info.split_whitespace().next().map(|x| x.to_string());
+        return Block::Code { lang, text };
+    }
+
+    fn html(&mut self, lines: &[Line], i: &mut usize, end: HtmlEnd) -> Block {
+        let start = *i;
+
+        while *i < lines.len() {
+            let line = lines[*i];
+            if end == HtmlEnd::Blank && line.is_blank() {
+                break;
+            }
+            *i += 1;
+
+            let done = match end {
+                HtmlEnd::Blank => false,
+                HtmlEnd::Comment => line.text.contains("-->"),
+                HtmlEnd::Tag(tag) => line
+                    .text
+                    .to_ascii_lowercase()
+                    .contains(&format!("</{}>", tag)),
+            };
+            if done {
+                break;
+            }
+        }
+
+        let text = Text::raw(&lines[start..*i]);
+        self.html_tags(&text.s, |x| text.source(x));
+        return Block::Html(text.s);
+    }
+
+    /// # Usage
+    /// Parses display math, which runs from a line starting
+    /// with `$$` to a line ending with `$$`.
+    fn math(&mut self, lines: &[Line], i: &mut usize) -> Block {
+        let start = *i;
+        let open = lines[start].trim_start();
+        let mut closed = open.text.trim_end().len() >= 4 && open.text.trim_end().ends_with("$$");
+        *i += 1;
+
+        while !closed && *i < lines.len() {
+            closed = lines[*i].text.trim_end().ends_with("$$");
+            *i += 1;
+        }
+
+        let text = Text::raw(&lines[start..*i]);
+        let body = text.s.trim_end();
+        let body = &body[2..];
+        let body = if closed {
+            &body[..body.len() - 2]
+        } else {
+            self.report(
+                Span::at(open.offset),
+                Severity::Error,
+                "Display math is never closed with `$$`",
+            );
+            body
+        };
+
+        return match tex::to_mathml(body, true) {
+            Ok(mathml) => Block::Math {
+                tex: body.to_string(),
+                mathml,
+            },
+            Err((offset, message)) => {
+                self.report(Span::at(text.source(2 + offset)), Severity::Error, message);
+                Block::Code {
+                    lang: None,
+                    text: body.to_string(),
+                }
+            }
+        };
+    }
+
+    fn quote(&mut self, lines: &[Line], i: &mut usize) -> Block {
+        let mut inner: Vec<Line> = Vec::new();
+
+        while *i < lines.len() {
+            let line = lines[*i];
+            match lexer::classify(line) {
+                LineKind::Quote(rest) => inner.push(rest),
+                // Lazy continuation of a paragraph inside the quote
+                LineKind::Text if inner.last().is_some_and(|x| !x.is_blank()) => inner.push(line),
+                _ => break,
+            }
+            *i += 1;
+        }
+
+        return Block::Quote(self.blocks(&inner));
+    }
+
+    fn list(&mut self, lines: &[Line], i: &mut usize) -> Block {
+        let (first, bullet) = match lexer::classify(lines[*i]) {
+            LineKind::Item { start, marker, .. } => (start, marker),
+            _ => unreachable!("A list must start with an item"),
+        };
+
+        let mut items = Vec::new();
+        let mut tight = true;
+
+        while *i < lines.len() {
+            let (width, content) = match lexer::classify(lines[*i]) {
+                LineKind::Item {
+                    start,
+                    marker,
+                    width,
+                    content,
+                } if marker == bullet && start.is_some() == first.is_some() => (width, content),
+                _ => break,
+            };
+            *i += 1;
+
+            let mut inner = vec![content];
+            while *i < lines.len() {
+                let line = lines[*i];
+                if line.is_blank() {
+                    inner.push(line.advance(line.text.len()));
+                } else if line.indent() >= width {
+                    inner.push(line.dedent(width));
+                } else if !inner.last().unwrap().is_blank()
+                    && matches!(lexer::classify(line), LineKind::Text)
+                    && matches!(lexer::classify(*inner.last().unwrap()), LineKind::Text)
+                {
+                    inner.push(line);
+                } else {
+                    break;
+                }
+                *i += 1;
+            }
+
+            let mut trailing = 0;
+            while inner.len() > 1 && inner.last().unwrap().is_blank() {
+                inner.pop();
+                trailing += 1;
+            }
+
+            // Blank lines between the blocks of an item make the list loose
+            if inner
+                .windows(2)
+                .any(|x| x[0].is_blank() && !x[1].is_blank())
+            {
+                tight = false;
+            }
+
+            items.push(self.blocks(&inner));
+
+            if trailing > 0 {
+                match lines.get(*i).map(|x| lexer::classify(*x)) {
+                    Some(LineKind::Item { marker, start, .. })
+                        if marker == bullet && start.is_some() == first.is_some() =>
+                    {
+                        tight = false
+                    }
+                    _ => break,
+                }
+            }
+        }
+
+        return Block::List {
+            start: first,
+            tight,
+            items,
+        };
+    }
+
+    /// # Usage
+    /// Collects reference link, footnote and macro definitions
+    /// ahead of time, since any may be used before it is defined.
+    fn definitions(&mut self, lines: &[Line<'s>]) {
+        let mut fence: Option<char> = None;
+        let mut i = 0;
+        while i < lines.len() {
+            let line = lines[i];
+            match (lexer::classify(line), fence) {
+                (LineKind::Fence { marker, .. }, None) => fence = Some(marker),
+                (LineKind::Fence { marker, .. }, Some(open)) if marker == open => fence = None,
+                (LineKind::Define, None) => {
+                    self.define(lines, &mut i);
+                    continue;
+                }
+                (LineKind::Text, None) => {
+                    let line = line.trim_start();
+                    if let Some((label, start)) = cite::definition(line.text) {
+                        self.footnotes.entry(label).or_insert(line.advance(start));
+                    } else if let Some((label, def)) = definition(line.text) {
+                        self.links.entry(label).or_insert(def);
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+}
+
+/// # Usage
+/// Normalizes a link label for case insensitive matching.
+pub fn label(text: &str) -> String {
+    return text
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase();
+}
+
+/// # Usage
+/// Parses a line of the form `[label]: destination "title"`.
+fn definition(text: &str) -> Option<(String, LinkDef)> {
+    let text = text.trim();
+    let rest = text.strip_prefix('[')?;
+    let close = rest.find("]:")?;
+    let name = &rest[..close];
+    if name.trim().is_empty() || name.contains(['[', ']']) {
+        return None;
+    }
+
+    let rest = rest[close + 2..].trim_start();
+    let (href, rest) = match rest.strip_prefix('<') {
+        Some(rest) => {
+            let end = rest.find('>')?;
+            (&rest[..end], &rest[end + 1..])
+        }
+        None => {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        }
+    };
+    if href.is_empty() {
+        return None;
+    }
+
+    let rest = rest.trim();
+    let title = if rest.is_empty() {
+        None
+    } else {
+        let first = rest.chars().next()?;
+        let close = match first {
+            '"' => '"',
+            '\'' => '\'',
+            '(' => ')',
+            _ => return None,
+        };
+        if rest.len() < 2 || !rest.ends_with(close) {
+            return None;
+        }
+        Some(rest[1..rest.len() - 1].to_string())
+    };
+
+    return Some((
+        label(name),
+        LinkDef {
+            href: href.to_string(),
+            title,
+        },
+    ));
+}
+
+/// # Usage
+/// Converts a byte offset into a one based line and column.
+/// Columns count characters, not bytes.
+pub fn position(source: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(source.len());
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let start = before.rfind('\n').map(|x| x + 1).unwrap_or(0);
+    let column = source[start..offset].chars().count() + 1;
+    return (line, column);
+}
@@ -0,0 +1,348 @@
+//! # Usage
+//! The built in `@plot` macro, which draws the graphs of
+//! functions as SVG with axes, ticks and labels.
+//! ```markdown
+//! @plot(f="x^2; 2x - 1", from=-2, to=2, shade=[0, 1])
+//! ```
+//! `f` holds one or more functions of `x` separated by `;`, such
+//! as a curve and its tangent line, see [math](crate::math) for
+//! their syntax. The area between the first function and the x
+//! axis is shaded over `shade`, if it isn't empty as it is by
+//! default. The y range fits the functions, leaving out values
+//! that shoot off near asymptotes, and has to be finite.
+
+use super::{html::escape, macros::Value};
+use crate::math::{self, Expr};
+use std::collections::HashMap;
+
+/// How many segments each curve is drawn with.
+const SAMPLES: usize = 400;
+
+/// Space around the plot for tick labels, in pixels.
+const MARGIN: f64 = 30.0;
+
+const COLORS: [&str; 4] = ["#c0392b", "#2980b9", "#27ae60", "#8e44ad"];
+
+/// # Usage
+/// Maps the region being plotted onto the pixels of the SVG.
+struct Frame {
+    from: f64,
+    to: f64,
+    bottom: f64,
+    top: f64,
+    width: f64,
+    height: f64,
+}
+
+impl Frame {
+    fn x(&self, x: f64) -> f64 {
+        return MARGIN + (x - self.from) / (self.to - self.from) * (self.width - 2.0 * MARGIN);
+    }
+
+    fn y(&self, y: f64) -> f64 {
+        return self.height
+            - MARGIN
+            - (y - self.bottom) / (self.top - self.bottom) * (self.height - 2.0 * MARGIN);
+    }
+}
+
+/// # Usage
+/// Expands `@plot`, failing if a function doesn't parse
+/// or the arguments don't describe a plot.
+pub fn plot(values: &HashMap<String, Value>) -> Result<String, String> {
+    let (
+        Value::String(f),
+        Value::Real(from),
+        Value::Real(to),
+        Value::Interval(a, b),
+        Value::Integer(width),
+        Value::Integer(height),
+    ) = (
+        &values["f"],
+        &values["from"],
+        &values["to"],
+        &values["shade"],
+        &values["width"],
+        &values["height"],
+    )
+    else {
+        unreachable!("Arguments are checked by Macro::bind");
+    };
+    let (from, to) = (*from, *to);
+
+    if from >= to {
+        return Err("`from` should be less than `to`".to_string());
+    }
+    if !(to - from).is_finite() {
+        return Err("`from` and `to` are too far apart to plot".to_string());
+    }
+    if a > b {
+        return Err("`shade` should go from the lower bound to the higher".to_string());
+    }
+    if !(100..=2000).contains(width) || !(100..=2000).contains(height) {
+        return Err("`width` and `height` should be between 100 and 2000".to_string());
+    }
+
+    let mut functions: Vec<(&str, Expr)> = Vec::new();
+    for text in f.split(';').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        let expr = math::parse(text).map_err(|(offset, message)| {
+            format!("{} in `{}` at column {}", message, text, offset + 1)
+        })?;
+        if let Some(var) = expr.variables().into_iter().find(|x| *x != 'x') {
+            return Err(format!(
+                "`{}` should only use the variable `x`, found `{}`",
+                text, var
+            ));
+        }
+        functions.push((text, expr));
+    }
+    if functions.is_empty() {
+        return Err("`f` should contain a function to plot".to_string());
+    }
+
+    let samples = |expr: &Expr, a: f64, b: f64| {
+        return (0..=SAMPLES)
+            .map(|i| {
+                let x = a + (b - a) * i as f64 / SAMPLES as f64;
+                (x, expr.at(x).unwrap_or(f64::NAN))
+            })
+            .collect::<Vec<(f64, f64)>>();
+    };
+    let curves = functions
+        .iter()
+        .map(|(_, expr)| samples(expr, from, to))
+        .collect::<Vec<_>>();
+
+    let (bottom, top) = range(curves.iter().flatten().map(|x| x.1))
+        .ok_or(format!("`{}` is undefined between `from` and `to`", f))?;
+    if !(top - bottom).is_finite() {
+        return Err(format!(
+            "`{}` is too large to plot between `from` and `to`",
+            f
+        ));
+    }
+    let frame = Frame {
+        from,
+        to,
+        bottom,
+        top,
+        width: *width as f64,
+        height: *height as f64,
+    };
+
+    let mut svg: Vec<String> = vec![format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {w} {h}\" width=\"{w}\" height=\"{h}\">",
+        w = width,
+        h = height
+    )];
+
+    // Shading under the first function
+    let (a, b) = (a.max(from), b.min(to));
+    if a < b {
+        let base = frame.y(0.0_f64.clamp(bottom, top));
+        let mut d = format!("M{:.1} {:.1}", frame.x(a), base);
+        for (x, y) in samples(&functions[0].1, a, b) {
+            let y = if y.is_finite() {
+                frame.y(y.clamp(bottom, top))
+            } else {
+                base
+            };
+            d.push_str(&format!("L{:.1} {:.1}", frame.x(x), y));
+        }
+        d.push_str(&format!("L{:.1} {:.1}Z", frame.x(b), base));
+        svg.push(format!(
+            "<path class=\"plot-shade\" d=\"{}\" fill=\"{}\" fill-opacity=\"0.25\" stroke=\"none\"/>",
+            d, COLORS[0]
+        ));
+    }
+
+    axes(&mut svg, &frame);
+
+    for (i, curve) in curves.iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        svg.push(format!(
+            "<path class=\"plot-curve\" d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>",
+            path(curve, &frame),
+            color
+        ));
+        svg.push(format!(
+            "<text class=\"plot-legend\" x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"12\">y = {}</text>",
+            MARGIN + 6.0,
+            MARGIN + 12.0 + 14.0 * i as f64,
+            color,
+            escape(functions[i].0)
+        ));
+    }
+
+    svg.push("</svg>".to_string());
+    return Ok(format!(
+        "<figure class=\"plot\">\n{}\n</figure>",
+        svg.join("\n")
+    ));
+}
+
+/// # Usage
+/// Picks the y range to show. Values far beyond the bulk
+/// of the others, as near an asymptote, are left out.
+fn range(ys: impl Iterator<Item = f64>) -> Option<(f64, f64)> {
+    let mut ys = ys.filter(|x| x.is_finite()).collect::<Vec<f64>>();
+    if ys.is_empty() {
+        return None;
+    }
+    ys.sort_by(|a, b| a.total_cmp(b));
+
+    let n = ys.len() - 1;
+    let (lo, hi) = (ys[n * 2 / 100], ys[n * 98 / 100]);
+    let spread = hi - lo;
+    let mut bottom = if ys[0] >= lo - spread { ys[0] } else { lo };
+    let mut top = if ys[n] <= hi + spread { ys[n] } else { hi };
+
+    if top - bottom < 1e-9 {
+        bottom -= 1.0;
+        top += 1.0;
+    }
+    let pad = (top - bottom) * 0.05;
+    return Some((bottom - pad, top + pad));
+}
+
+/// # Usage
+/// Returns evenly spaced round numbers covering `lo..=hi`,
+/// along with how many decimals they need.
+fn ticks(lo: f64, hi: f64) -> (Vec<f64>, usize) {
+    let rough = (hi - lo) / 6.0;
+    let magnitude = 10_f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|x| x * magnitude)
+        .find(|x| *x >= rough)
+        .unwrap_or(10.0 * magnitude);
+
+    let mut out = Vec::new();
+    let mut tick = (lo / step).ceil() * step;
+    while tick <= hi + step * 1e-9 {
+        // Avoid printing `-0`
+        out.push(if tick.abs() < step * 1e-9 { 0.0 } else { tick });
+        tick += step;
+    }
+
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    return (out, decimals);
+}
+
+/// # Usage
+/// Draws the axes, through the origin where it is visible and
+/// along the edges otherwise, with tick marks and labels.
+fn axes(svg: &mut Vec<String>, frame: &Frame) {
+    let origin_x = frame.x(0.0_f64.clamp(frame.from, frame.to));
+    let origin_y = frame.y(0.0_f64.clamp(frame.bottom, frame.top));
+    let (left, right) = (frame.x(frame.from), frame.x(frame.to));
+    let (bottom, top) = (frame.y(frame.bottom), frame.y(frame.top));
+
+    let mut lines = vec![
+        format!(
+            "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\"/>",
+            left, origin_y, right, origin_y
+        ),
+        format!(
+            "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\"/>",
+            origin_x, bottom, origin_x, top
+        ),
+    ];
+    let mut labels = Vec::new();
+
+    let (xs, decimals) = ticks(frame.from, frame.to);
+    for x in xs {
+        let px = frame.x(x);
+        lines.push(format!(
+            "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\"/>",
+            px,
+            origin_y - 3.0,
+            px,
+            origin_y + 3.0
+        ));
+        if (px - origin_x).abs() > 1.0 {
+            labels.push(format!(
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{:.*}</text>",
+                px,
+                origin_y + 14.0,
+                decimals,
+                x
+            ));
+        }
+    }
+
+    let (ys, decimals) = ticks(frame.bottom, frame.top);
+    for y in ys {
+        let py = frame.y(y);
+        lines.push(format!(
+            "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\"/>",
+            origin_x - 3.0,
+            py,
+            origin_x + 3.0,
+            py
+        ));
+        if (py - origin_y).abs() > 1.0 {
+            labels.push(format!(
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{:.*}</text>",
+                origin_x - 5.0,
+                py + 3.5,
+                decimals,
+                y
+            ));
+        }
+    }
+
+    svg.push(format!(
+        "<g class=\"plot-axes\" stroke=\"currentColor\" stroke-width=\"1\">{}</g>",
+        lines.join("")
+    ));
+    svg.push(format!(
+        "<g class=\"plot-labels\" fill=\"currentColor\" font-size=\"10\">{}</g>",
+        labels.join("")
+    ));
+}
+
+/// # Usage
+/// Turns the samples of a curve into path data, clipped to
+/// the frame. Gaps are left where the function is undefined
+/// or jumps across an asymptote.
+fn path(curve: &[(f64, f64)], frame: &Frame) -> String {
+    let height = frame.top - frame.bottom;
+    let mut d = String::new();
+    let mut last: Option<(f64, f64)> = None;
+
+    for pair in curve.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        if !y0.is_finite() || !y1.is_finite() || (y1 - y0).abs() > 2.0 * height {
+            continue;
+        }
+
+        // Clip the segment to the visible range of y
+        let (mut t0, mut t1) = (0.0_f64, 1.0_f64);
+        let dy = y1 - y0;
+        let mut visible = true;
+        for (p, q) in [(-dy, y0 - frame.bottom), (dy, frame.top - y0)] {
+            if p == 0.0 {
+                visible &= q >= 0.0;
+            } else if p < 0.0 {
+                t0 = t0.max(q / p);
+            } else {
+                t1 = t1.min(q / p);
+            }
+        }
+        if !visible || t0 > t1 {
+            continue;
+        }
+
+        let at = |t: f64| (frame.x(x0 + (x1 - x0) * t), frame.y(y0 + dy * t));
+        let (start, end) = (at(t0), at(t1));
+        let joined = last.is_some_and(|x| (x.0 - start.0).abs() + (x.1 - start.1).abs() < 0.01);
+        if !joined {
+            d.push_str(&format!("M{:.1} {:.1}", start.0, start.1));
+        }
+        d.push_str(&format!("L{:.1} {:.1}", end.0, end.1));
+        last = Some(end);
+    }
+
+    return d;
+}
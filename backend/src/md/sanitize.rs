@@ -0,0 +1,163 @@
+//! # Usage
+//! Cleans the HTML of a note before it is served. Notes may contain
+//! raw HTML, so anything which could run script is removed: `<script>`
+//! and other unknown elements, event handlers such as `onclick`, and
+//! URLs with schemes like `javascript:`.
+
+use ammonia::Builder;
+use once_cell::sync::Lazy;
+
+/// MathML elements, as produced by the [tex](super::tex) compiler.
+const MATHML: [&str; 29] = [
+    "math",
+    "semantics",
+    "annotation",
+    "mrow",
+    "mi",
+    "mn",
+    "mo",
+    "ms",
+    "mtext",
+    "mspace",
+    "mfrac",
+    "msqrt",
+    "mroot",
+    "msub",
+    "msup",
+    "msubsup",
+    "munder",
+    "mover",
+    "munderover",
+    "mmultiscripts",
+    "mprescripts",
+    "none",
+    "mtable",
+    "mtr",
+    "mtd",
+    "mstyle",
+    "mpadded",
+    "mphantom",
+    "menclose",
+];
+
+const MATHML_ATTRIBUTES: [&str; 22] = [
+    "display",
+    "encoding",
+    "mathvariant",
+    "mathsize",
+    "displaystyle",
+    "scriptlevel",
+    "stretchy",
+    "fence",
+    "separator",
+    "symmetric",
+    "largeop",
+    "movablelimits",
+    "lspace",
+    "rspace",
+    "minsize",
+    "maxsize",
+    "accent",
+    "accentunder",
+    "linethickness",
+    "columnalign",
+    "rowspacing",
+    "columnspacing",
+];
+
+/// SVG elements for drawings and plots. `<use>`, `<image>` and
+/// `<foreignObject>` are left out since they can load content.
+const SVG: [&str; 18] = [
+    "svg",
+    "g",
+    "defs",
+    "title",
+    "desc",
+    "path",
+    "line",
+    "polyline",
+    "polygon",
+    "rect",
+    "circle",
+    "ellipse",
+    "text",
+    "tspan",
+    "clipPath",
+    "marker",
+    "linearGradient",
+    "stop",
+];
+
+const SVG_ATTRIBUTES: [&str; 43] = [
+    "xmlns",
+    "viewBox",
+    "preserveAspectRatio",
+    "width",
+    "height",
+    "x",
+    "y",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "cx",
+    "cy",
+    "r",
+    "rx",
+    "ry",
+    "dx",
+    "dy",
+    "d",
+    "points",
+    "transform",
+    "fill",
+    "fill-opacity",
+    "stroke",
+    "stroke-width",
+    "stroke-opacity",
+    "stroke-dasharray",
+    "stroke-linecap",
+    "opacity",
+    "font-size",
+    "font-family",
+    "font-style",
+    "text-anchor",
+    "dominant-baseline",
+    "clip-path",
+    "clipPathUnits",
+    "marker-start",
+    "marker-end",
+    "markerWidth",
+    "markerHeight",
+    "refX",
+    "refY",
+    "orient",
+];
+
+static SANITIZER: Lazy<Builder<'static>> = Lazy::new(|| {
+    let mut builder = Builder::default();
+    builder
+        .link_rel(None)
+        .add_tags(["section"])
+        .add_generic_attributes(["class", "id"])
+        // The note an included exercise is graded as
+        .add_tag_attributes("section", ["data-note"])
+        .add_tags(MATHML)
+        .add_tags(SVG)
+        .add_tag_attributes("stop", ["offset", "stop-color", "stop-opacity"]);
+
+    for tag in MATHML {
+        builder.add_tag_attributes(tag, MATHML_ATTRIBUTES);
+    }
+    for tag in SVG {
+        builder.add_tag_attributes(tag, SVG_ATTRIBUTES);
+    }
+
+    return builder;
+});
+
+/// # Usage
+/// Removes everything which isn't on the allowlist from `html`.
+pub fn clean(html: &str) -> String {
+    return SANITIZER.clean(html).to_string();
+}
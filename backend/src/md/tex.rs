@@ -0,0 +1,853 @@
+//! # Usage
+//! Compiles the LaTeX used between `$...$` and `$$...$$`
+//! to MathML, so that math renders without any client side
+//! JavaScript. Supports a practical subset of LaTeX:
+//! - Greek letters, operators, relations, arrows and other symbols
+//! - Sub and superscripts, primes
+//! - `\frac`, `\dfrac`, `\binom`, `\sqrt`
+//! - `\int`, `\sum`, `\prod`, `\lim` and friends, with limits
+//! - Named functions such as `\sin` and `\log`, and `\operatorname`
+//! - `\left`/`\right`, `\text`, fonts (`\mathbb`, `\mathrm`, ...) and accents
+//! - `matrix`, `pmatrix`, `bmatrix`, `vmatrix`, `cases`, `align` and `aligned`
+
+use super::html::escape;
+
+/// How deeply atoms may nest, through groups, scripts,
+/// `\left`/`\right` and the arguments of commands.
+const MAX_DEPTH: usize = 200;
+
+/// # Usage
+/// A byte offset into the LaTeX and a message.
+pub type Error = (usize, String);
+
+const GREEK: [(&str, char); 40] = [
+    ("alpha", 'α'),
+    ("beta", 'β'),
+    ("gamma", 'γ'),
+    ("delta", 'δ'),
+    ("epsilon", 'ϵ'),
+    ("varepsilon", 'ε'),
+    ("zeta", 'ζ'),
+    ("eta", 'η'),
+    ("theta", 'θ'),
+    ("vartheta", 'ϑ'),
+    ("iota", 'ι'),
+    ("kappa", 'κ'),
+    ("lambda", 'λ'),
+    ("mu", 'μ'),
+    ("nu", 'ν'),
+    ("xi", 'ξ'),
+    ("pi", 'π'),
+    ("varpi", 'ϖ'),
+    ("rho", 'ρ'),
+    ("varrho", 'ϱ'),
+    ("sigma", 'σ'),
+    ("varsigma", 'ς'),
+    ("tau", 'τ'),
+    ("upsilon", 'υ'),
+    ("phi", 'ϕ'),
+    ("varphi", 'φ'),
+    ("chi", 'χ'),
+    ("psi", 'ψ'),
+    ("omega", 'ω'),
+    ("Gamma", 'Γ'),
+    ("Delta", 'Δ'),
+    ("Theta", 'Θ'),
+    ("Lambda", 'Λ'),
+    ("Xi", 'Ξ'),
+    ("Pi", 'Π'),
+    ("Sigma", 'Σ'),
+    ("Upsilon", 'Υ'),
+    ("Phi", 'Φ'),
+    ("Psi", 'Ψ'),
+    ("Omega", 'Ω'),
+];
+
+/// Symbols rendered as `<mi>`.
+const IDENTIFIERS: [(&str, &str); 14] = [
+    ("infty", "∞"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("emptyset", "∅"),
+    ("varnothing", "∅"),
+    ("ell", "ℓ"),
+    ("hbar", "ℏ"),
+    ("aleph", "ℵ"),
+    ("Re", "ℜ"),
+    ("Im", "ℑ"),
+    ("angle", "∠"),
+    ("triangle", "△"),
+    ("prime", "′"),
+    ("degree", "°"),
+];
+
+/// Symbols rendered as `<mo>`.
+const OPERATORS: [(&str, &str); 76] = [
+    ("leq", "≤"),
+    ("le", "≤"),
+    ("geq", "≥"),
+    ("ge", "≥"),
+    ("neq", "≠"),
+    ("ne", "≠"),
+    ("approx", "≈"),
+    ("equiv", "≡"),
+    ("sim", "∼"),
+    ("simeq", "≃"),
+    ("cong", "≅"),
+    ("propto", "∝"),
+    ("ll", "≪"),
+    ("gg", "≫"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("gets", "←"),
+    ("leftrightarrow", "↔"),
+    ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("Leftrightarrow", "⇔"),
+    ("implies", "⟹"),
+    ("impliedby", "⟸"),
+    ("iff", "⟺"),
+    ("mapsto", "↦"),
+    ("uparrow", "↑"),
+    ("downarrow", "↓"),
+    ("nearrow", "↗"),
+    ("searrow", "↘"),
+    ("cdot", "⋅"),
+    ("times", "×"),
+    ("div", "÷"),
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("ast", "∗"),
+    ("star", "⋆"),
+    ("circ", "∘"),
+    ("bullet", "∙"),
+    ("oplus", "⊕"),
+    ("otimes", "⊗"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("ni", "∋"),
+    ("subset", "⊂"),
+    ("subseteq", "⊆"),
+    ("supset", "⊃"),
+    ("supseteq", "⊇"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("setminus", "∖"),
+    ("wedge", "∧"),
+    ("land", "∧"),
+    ("vee", "∨"),
+    ("lor", "∨"),
+    ("neg", "¬"),
+    ("lnot", "¬"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("nexists", "∄"),
+    ("mid", "∣"),
+    ("parallel", "∥"),
+    ("perp", "⊥"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("cdots", "⋯"),
+    ("vdots", "⋮"),
+    ("ddots", "⋱"),
+    ("colon", ":"),
+    ("therefore", "∴"),
+    ("because", "∵"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("lfloor", "⌊"),
+    ("rfloor", "⌋"),
+    ("lceil", "⌈"),
+];
+
+/// Delimiters which may follow `\left` and `\right`,
+/// besides the single characters `( ) [ ] | / .`
+const DELIMITERS: [(&str, &str); 11] = [
+    ("{", "{"),
+    ("}", "}"),
+    ("|", "‖"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("lfloor", "⌊"),
+    ("rfloor", "⌋"),
+    ("lceil", "⌈"),
+    ("rceil", "⌉"),
+    ("vert", "|"),
+    ("Vert", "‖"),
+];
+
+/// Big operators whose limits go above and below in display math.
+const LIMITS: [(&str, &str); 6] = [
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("coprod", "∐"),
+    ("bigcup", "⋃"),
+    ("bigcap", "⋂"),
+    ("bigoplus", "⨁"),
+];
+
+/// Integrals, whose limits always go to the side.
+const INTEGRALS: [(&str, &str); 4] = [("int", "∫"), ("iint", "∬"), ("iiint", "∭"), ("oint", "∮")];
+
+/// Named functions, and whether they take limits like `\lim`.
+const FUNCTIONS: [(&str, &str, bool); 30] = [
+    ("sin", "sin", false),
+    ("cos", "cos", false),
+    ("tan", "tan", false),
+    ("sec", "sec", false),
+    ("csc", "csc", false),
+    ("cot", "cot", false),
+    ("arcsin", "arcsin", false),
+    ("arccos", "arccos", false),
+    ("arctan", "arctan", false),
+    ("sinh", "sinh", false),
+    ("cosh", "cosh", false),
+    ("tanh", "tanh", false),
+    ("ln", "ln", false),
+    ("log", "log", false),
+    ("lg", "lg", false),
+    ("exp", "exp", false),
+    ("det", "det", true),
+    ("dim", "dim", false),
+    ("deg", "deg", false),
+    ("arg", "arg", false),
+    ("ker", "ker", false),
+    ("gcd", "gcd", true),
+    ("Pr", "Pr", true),
+    ("lim", "lim", true),
+    ("limsup", "lim sup", true),
+    ("liminf", "lim inf", true),
+    ("max", "max", true),
+    ("min", "min", true),
+    ("sup", "sup", true),
+    ("inf", "inf", true),
+];
+
+const FONTS: [(&str, &str); 9] = [
+    ("mathrm", "normal"),
+    ("mathbf", "bold"),
+    ("mathit", "italic"),
+    ("mathbb", "double-struck"),
+    ("mathcal", "script"),
+    ("mathscr", "script"),
+    ("mathfrak", "fraktur"),
+    ("mathsf", "sans-serif"),
+    ("boldsymbol", "bold-italic"),
+];
+
+const ACCENTS: [(&str, &str, bool); 10] = [
+    ("hat", "^", true),
+    ("widehat", "^", true),
+    ("bar", "¯", true),
+    ("overline", "‾", true),
+    ("vec", "→", true),
+    ("dot", "˙", true),
+    ("ddot", "¨", true),
+    ("tilde", "~", true),
+    ("widetilde", "~", true),
+    ("underline", "_", false),
+];
+
+const SPACES: [(&str, &str); 8] = [
+    (",", "0.1667em"),
+    (":", "0.2222em"),
+    (">", "0.2222em"),
+    (";", "0.2778em"),
+    ("!", "-0.1667em"),
+    (" ", "0.25em"),
+    ("quad", "1em"),
+    ("qquad", "2em"),
+];
+
+fn lookup<T: Copy>(table: &[(&str, T)], name: &str) -> Option<T> {
+    return table.iter().find(|x| x.0 == name).map(|x| x.1);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tok<'a> {
+    Cmd(&'a str),
+    Letter(char),
+    Number(&'a str),
+    Sym(char),
+    Open,
+    Close,
+    Sup,
+    Sub,
+    Amp,
+    /// `\\`
+    Newline,
+    Prime,
+    End,
+}
+
+fn tokenize(tex: &str) -> Vec<(Tok<'_>, usize)> {
+    let mut out = Vec::new();
+    let mut chars = tex.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let tok = match c {
+            c if c.is_whitespace() => continue,
+            '%' => {
+                while chars.next_if(|x| x.1 != '\n').is_some() {}
+                continue;
+            }
+            '\\' => match chars.peek().copied() {
+                Some((_, '\\')) => {
+                    chars.next();
+                    Tok::Newline
+                }
+                Some((j, n)) if n.is_ascii_alphabetic() => {
+                    let mut end = j;
+                    while let Some((k, x)) = chars.next_if(|x| x.1.is_ascii_alphabetic()) {
+                        end = k + x.len_utf8();
+                    }
+                    Tok::Cmd(&tex[j..end])
+                }
+                Some((j, n)) => {
+                    chars.next();
+                    Tok::Cmd(&tex[j..j + n.len_utf8()])
+                }
+                None => Tok::Sym('\\'),
+            },
+            c if c.is_ascii_digit()
+                || (c == '.' && chars.peek().is_some_and(|x| x.1.is_ascii_digit())) =>
+            {
+                let mut end = i + 1;
+                while let Some((k, x)) = chars.next_if(|x| x.1.is_ascii_digit() || x.1 == '.') {
+                    end = k + x.len_utf8();
+                }
+                Tok::Number(&tex[i..end])
+            }
+            c if c.is_alphabetic() => Tok::Letter(c),
+            '{' => Tok::Open,
+            '}' => Tok::Close,
+            '^' => Tok::Sup,
+            '_' => Tok::Sub,
+            '&' => Tok::Amp,
+            '\'' => Tok::Prime,
+            c => Tok::Sym(c),
+        };
+        out.push((tok, i));
+    }
+
+    out.push((Tok::End, tex.len()));
+    return out;
+}
+
+/// # Usage
+/// How an atom treats sub and superscripts.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Ord,
+    /// Limits above and below in display math
+    Limits,
+}
+
+struct Parser<'a> {
+    tex: &'a str,
+    toks: Vec<(Tok<'a>, usize)>,
+    pos: usize,
+    display: bool,
+    /// How many atoms are being parsed.
+    depth: usize,
+}
+
+/// # Usage
+/// Compiles LaTeX to a `<math>` element. `display` selects
+/// block (`$$`) rather than inline (`$`) layout.
+pub fn to_mathml(tex: &str, display: bool) -> Result<String, Error> {
+    let mut parser = Parser {
+        tex,
+        toks: tokenize(tex),
+        pos: 0,
+        display,
+        depth: 0,
+    };
+
+    let content = parser.row(false)?;
+    match parser.peek() {
+        Tok::End => {}
+        Tok::Close => return Err((parser.offset(), "Unmatched `}`".to_string())),
+        Tok::Amp | Tok::Newline => {
+            return Err((
+                parser.offset(),
+                "`&` and `\\\\` are only allowed inside of an environment such as `align`"
+                    .to_string(),
+            ))
+        }
+        Tok::Cmd("right") => {
+            return Err((parser.offset(), "`\\right` without `\\left`".to_string()))
+        }
+        Tok::Cmd("end") => return Err((parser.offset(), "`\\end` without `\\begin`".to_string())),
+        _ => return Err((parser.offset(), "Unexpected token".to_string())),
+    }
+
+    return Ok(format!(
+        "<math{}><semantics>{}<annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        if display { " display=\"block\"" } else { "" },
+        wrap(content),
+        escape(tex.trim())
+    ));
+}
+
+/// # Usage
+/// Wraps several elements in an `<mrow>`, so they act as one.
+fn wrap(items: Vec<String>) -> String {
+    if items.len() == 1 {
+        return items.into_iter().next().unwrap();
+    }
+    return format!("<mrow>{}</mrow>", items.concat());
+}
+
+fn mo(op: &str) -> String {
+    return format!("<mo>{}</mo>", escape(op));
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Tok<'a> {
+        return self.toks[self.pos].0;
+    }
+
+    fn offset(&self) -> usize {
+        return self.toks[self.pos].1;
+    }
+
+    fn next(&mut self) -> (Tok<'a>, usize) {
+        let tok = self.toks[self.pos];
+        if tok.0 != Tok::End {
+            self.pos += 1;
+        }
+        return tok;
+    }
+
+    /// # Usage
+    /// Parses atoms until a token that ends a row:
+    /// `}`, `&`, `\\`, `\right`, `\end` or the end of input.
+    /// With `bracket`, a `]` ends the row too.
+    fn row(&mut self, bracket: bool) -> Result<Vec<String>, Error> {
+        let mut out = Vec::new();
+        loop {
+            match self.peek() {
+                Tok::End | Tok::Close | Tok::Amp | Tok::Newline => break,
+                Tok::Cmd("right") | Tok::Cmd("end") => break,
+                Tok::Sym(']') if bracket => break,
+                _ => out.push(self.scripted()?),
+            }
+        }
+        return Ok(out);
+    }
+
+    /// # Usage
+    /// Parses `{...}`, returning its content as one element.
+    fn group(&mut self) -> Result<String, Error> {
+        return self.nested(Self::grouped);
+    }
+
+    fn grouped(&mut self) -> Result<String, Error> {
+        let (_, open) = self.next();
+        let content = self.row(false)?;
+        if self.peek() != Tok::Close {
+            return Err((open, "Missing `}`".to_string()));
+        }
+        self.next();
+        return Ok(wrap(content));
+    }
+
+    /// # Usage
+    /// Parses the required argument of a command: a group or a single token.
+    fn arg(&mut self, command: &str) -> Result<String, Error> {
+        return match self.peek() {
+            Tok::Open => self.group(),
+            Tok::End | Tok::Close | Tok::Amp | Tok::Newline | Tok::Sup | Tok::Sub => Err((
+                self.offset(),
+                format!("Missing argument for `\\{}`", command),
+            )),
+            _ => Ok(self.atom()?.0),
+        };
+    }
+
+    /// # Usage
+    /// Returns the source of a `{...}` group verbatim, used by `\text`.
+    fn raw(&mut self, command: &str) -> Result<&'a str, Error> {
+        let (tok, open) = self.next();
+        if tok != Tok::Open {
+            return Err((
+                open,
+                format!("`\\{}` expects a `{{...}}` argument", command),
+            ));
+        }
+
+        let mut depth = 1;
+        loop {
+            let (tok, at) = self.next();
+            match tok {
+                Tok::Open => depth += 1,
+                Tok::Close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(&self.tex[open + 1..at]);
+                    }
+                }
+                Tok::End => return Err((open, "Missing `}`".to_string())),
+                _ => {}
+            }
+        }
+    }
+
+    /// # Usage
+    /// Parses an atom followed by any sub and superscripts.
+    fn scripted(&mut self) -> Result<String, Error> {
+        let (base, kind) = match self.peek() {
+            // A script with no base, e.g. `{}^{14}C`
+            Tok::Sup | Tok::Sub => ("<mrow></mrow>".to_string(), Kind::Ord),
+            _ => self.atom()?,
+        };
+
+        let mut sub: Option<String> = None;
+        let mut sup: Option<String> = None;
+        let mut primes = String::new();
+
+        loop {
+            match self.peek() {
+                Tok::Prime => {
+                    self.next();
+                    primes.push('′');
+                }
+                Tok::Sub => {
+                    let (_, at) = self.next();
+                    if sub.is_some() {
+                        return Err((at, "Double subscript".to_string()));
+                    }
+                    sub = Some(self.arg("_")?);
+                }
+                Tok::Sup => {
+                    let (_, at) = self.next();
+                    if sup.is_some() {
+                        return Err((at, "Double superscript".to_string()));
+                    }
+                    sup = Some(self.arg("^")?);
+                }
+                _ => break,
+            }
+        }
+
+        if !primes.is_empty() {
+            sup = Some(match sup {
+                Some(sup) => format!("<mrow>{}{}</mrow>", mo(&primes), sup),
+                None => mo(&primes),
+            });
+        }
+
+        let under = kind == Kind::Limits && self.display;
+        return Ok(match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) if under => format!("<munder>{}{}</munder>", base, sub),
+            (None, Some(sup)) if under => format!("<mover>{}{}</mover>", base, sup),
+            (Some(sub), Some(sup)) if under => {
+                format!("<munderover>{}{}{}</munderover>", base, sub, sup)
+            }
+            (Some(sub), None) => format!("<msub>{}{}</msub>", base, sub),
+            (None, Some(sup)) => format!("<msup>{}{}</msup>", base, sup),
+            (Some(sub), Some(sup)) => format!("<msubsup>{}{}{}</msubsup>", base, sub, sup),
+        });
+    }
+
+    /// # Usage
+    /// Calls `parse` one level deeper, failing past [MAX_DEPTH].
+    /// Every way of nesting goes through an atom or a group.
+    fn nested<T>(&mut self, parse: fn(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err((self.offset(), "Math is nested too deeply".to_string()));
+        }
+        self.depth += 1;
+        let out = parse(self);
+        self.depth -= 1;
+        return out;
+    }
+
+    fn atom(&mut self) -> Result<(String, Kind), Error> {
+        return self.nested(Self::single);
+    }
+
+    fn single(&mut self) -> Result<(String, Kind), Error> {
+        let (tok, at) = self.next();
+        let out = match tok {
+            Tok::Open => {
+                self.pos -= 1;
+                self.group()?
+            }
+            Tok::Letter(c) => format!("<mi>{}</mi>", c),
+            Tok::Number(n) => format!("<mn>{}</mn>", n),
+            Tok::Sym('-') => mo("−"),
+            Tok::Sym('*') => mo("∗"),
+            Tok::Sym(c) => mo(&c.to_string()),
+            Tok::Prime => mo("′"),
+            Tok::Cmd(name) => return self.command(name, at),
+            Tok::Close => return Err((at, "Unmatched `}`".to_string())),
+            Tok::End => return Err((at, "Unexpected end of math".to_string())),
+            Tok::Sup | Tok::Sub | Tok::Amp | Tok::Newline => {
+                return Err((at, "Unexpected token".to_string()))
+            }
+        };
+        return Ok((out, Kind::Ord));
+    }
+
+    fn command(&mut self, name: &'a str, at: usize) -> Result<(String, Kind), Error> {
+        if let Some(c) = lookup(&GREEK, name) {
+            let variant = if c.is_uppercase() {
+                " mathvariant=\"normal\""
+            } else {
+                ""
+            };
+            return Ok((format!("<mi{}>{}</mi>", variant, c), Kind::Ord));
+        }
+        if let Some(symbol) = lookup(&IDENTIFIERS, name) {
+            return Ok((format!("<mi>{}</mi>", symbol), Kind::Ord));
+        }
+        if let Some(symbol) = lookup(&OPERATORS, name).or(lookup(&DELIMITERS, name)) {
+            return Ok((mo(symbol), Kind::Ord));
+        }
+        if let Some(symbol) = lookup(&LIMITS, name) {
+            return Ok((mo(symbol), Kind::Limits));
+        }
+        if let Some(symbol) = lookup(&INTEGRALS, name) {
+            return Ok((mo(symbol), Kind::Ord));
+        }
+        if let Some((_, text, limits)) = FUNCTIONS.iter().find(|x| x.0 == name) {
+            let kind = if *limits { Kind::Limits } else { Kind::Ord };
+            return Ok((format!("<mi>{}</mi>", text), kind));
+        }
+        if let Some(width) = lookup(&SPACES, name) {
+            return Ok((format!("<mspace width=\"{}\"></mspace>", width), Kind::Ord));
+        }
+        if let Some(variant) = lookup(&FONTS, name) {
+            return Ok((self.font(name, variant)?, Kind::Ord));
+        }
+        if let Some((_, accent, over)) = ACCENTS.iter().find(|x| x.0 == name) {
+            let base = self.arg(name)?;
+            let out = if *over {
+                format!("<mover accent=\"true\">{}{}</mover>", base, mo(accent))
+            } else {
+                format!(
+                    "<munder accentunder=\"true\">{}{}</munder>",
+                    base,
+                    mo(accent)
+                )
+            };
+            return Ok((out, Kind::Ord));
+        }
+
+        let out = match name {
+            "frac" | "dfrac" | "tfrac" => {
+                let num = self.arg(name)?;
+                let den = self.arg(name)?;
+                let frac = format!("<mfrac>{}{}</mfrac>", num, den);
+                match name {
+                    "dfrac" => format!("<mstyle displaystyle=\"true\">{}</mstyle>", frac),
+                    "tfrac" => format!("<mstyle displaystyle=\"false\">{}</mstyle>", frac),
+                    _ => frac,
+                }
+            }
+            "binom" => {
+                let n = self.arg(name)?;
+                let k = self.arg(name)?;
+                format!(
+                    "<mrow>{}<mfrac linethickness=\"0\">{}{}</mfrac>{}</mrow>",
+                    mo("("),
+                    n,
+                    k,
+                    mo(")")
+                )
+            }
+            "sqrt" => {
+                if self.peek() == Tok::Sym('[') {
+                    let (_, open) = self.next();
+                    let index = wrap(self.row(true)?);
+                    if self.peek() != Tok::Sym(']') {
+                        return Err((open, "Missing `]`".to_string()));
+                    }
+                    self.next();
+                    format!("<mroot>{}{}</mroot>", self.arg(name)?, index)
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.arg(name)?)
+                }
+            }
+            "text" | "textrm" | "mbox" | "textit" | "textbf" => {
+                let text = self.raw(name)?;
+                format!("<mtext>{}</mtext>", escape(text))
+            }
+            "operatorname" => {
+                let text = self.raw(name)?;
+                format!("<mi>{}</mi>", escape(text.trim()))
+            }
+            "left" => self.fenced(at)?,
+            "begin" => self.environment(at)?,
+            "{" | "}" | "%" | "$" | "#" | "&" | "_" => mo(name),
+            _ => return Err((at, format!("Unknown command `\\{}`", name))),
+        };
+
+        return Ok((out, Kind::Ord));
+    }
+
+    /// # Usage
+    /// Applies a font to plain text, e.g. `\mathbb{R}`.
+    fn font(&mut self, name: &str, variant: &str) -> Result<String, Error> {
+        if self.peek() != Tok::Open {
+            let (base, _) = self.atom()?;
+            return Ok(base.replacen("<mi>", &format!("<mi mathvariant=\"{}\">", variant), 1));
+        }
+
+        let start = self.offset() + 1;
+        let text = self.raw(name)?;
+        if text.chars().all(|c| c.is_alphanumeric() || c == ' ') {
+            let text = text.replace(' ', "");
+            let tag = if text.chars().all(|c| c.is_ascii_digit()) {
+                "mn"
+            } else {
+                "mi"
+            };
+            return Ok(format!(
+                "<{} mathvariant=\"{}\">{}</{}>",
+                tag, variant, text, tag
+            ));
+        }
+
+        // Anything more complex keeps its default font.
+        let mut inner = Parser {
+            tex: text,
+            toks: tokenize(text),
+            pos: 0,
+            display: self.display,
+            depth: self.depth,
+        };
+        let content = inner.row(false).map_err(|(at, x)| (start + at, x))?;
+        if inner.peek() != Tok::End {
+            return Err((start + inner.offset(), "Unexpected token".to_string()));
+        }
+        return Ok(wrap(content));
+    }
+
+    /// # Usage
+    /// Reads the delimiter following `\left` or `\right`.
+    fn delimiter(&mut self, command: &str) -> Result<String, Error> {
+        let (tok, at) = self.next();
+        let symbol = match tok {
+            Tok::Sym('.') => return Ok(String::new()),
+            Tok::Sym(c @ ('(' | ')' | '[' | ']' | '|' | '/' | '<' | '>')) => match c {
+                '<' => "⟨".to_string(),
+                '>' => "⟩".to_string(),
+                c => c.to_string(),
+            },
+            Tok::Cmd(name) => match lookup(&DELIMITERS, name) {
+                Some(symbol) => symbol.to_string(),
+                None => return Err((at, format!("`\\{}` is not a delimiter", name))),
+            },
+            _ => return Err((at, format!("Missing delimiter after `\\{}`", command))),
+        };
+        return Ok(format!(
+            "<mo fence=\"true\" stretchy=\"true\">{}</mo>",
+            escape(&symbol)
+        ));
+    }
+
+    /// # Usage
+    /// Parses `\left( ... \right)`, after the `\left`.
+    fn fenced(&mut self, at: usize) -> Result<String, Error> {
+        let open = self.delimiter("left")?;
+        let content = self.row(false)?;
+        if self.peek() != Tok::Cmd("right") {
+            return Err((at, "`\\left` without `\\right`".to_string()));
+        }
+        self.next();
+        let close = self.delimiter("right")?;
+        return Ok(format!(
+            "<mrow>{}{}{}</mrow>",
+            open,
+            content.concat(),
+            close
+        ));
+    }
+
+    /// # Usage
+    /// Parses `\begin{name} ... \end{name}`, after the `\begin`.
+    fn environment(&mut self, at: usize) -> Result<String, Error> {
+        let name = self.raw("begin")?.trim();
+        let (open, close, align) = match name {
+            "matrix" | "smallmatrix" => ("", "", ""),
+            "pmatrix" => ("(", ")", ""),
+            "bmatrix" => ("[", "]", ""),
+            "Bmatrix" => ("{", "}", ""),
+            "vmatrix" => ("|", "|", ""),
+            "Vmatrix" => ("‖", "‖", ""),
+            "cases" => ("{", "", " columnalign=\"left left\""),
+            "align" | "align*" | "aligned" | "split" => {
+                ("", "", " columnalign=\"right left right left right left\"")
+            }
+            "gather" | "gather*" | "gathered" => ("", "", ""),
+            "array" => {
+                // Column alignment is ignored
+                self.raw("begin{array}")?;
+                ("", "", "")
+            }
+            _ => return Err((at, format!("Unknown environment `{}`", name))),
+        };
+
+        let mut rows: Vec<Vec<String>> = vec![Vec::new()];
+        loop {
+            let cell = self.row(false)?;
+            rows.last_mut()
+                .unwrap()
+                .push(format!("<mtd>{}</mtd>", cell.concat()));
+            let (tok, end_at) = self.next();
+            match tok {
+                Tok::Amp => {}
+                Tok::Newline => rows.push(Vec::new()),
+                Tok::Cmd("end") => {
+                    let end = self.raw("end")?.trim();
+                    if end != name {
+                        return Err((
+                            end_at,
+                            format!("Expected `\\end{{{}}}`, found `\\end{{{}}}`", name, end),
+                        ));
+                    }
+                    break;
+                }
+                Tok::End => return Err((at, format!("`\\begin{{{}}}` is never closed", name))),
+                Tok::Close => return Err((end_at, "Unmatched `}`".to_string())),
+                _ => return Err((end_at, "Unexpected token".to_string())),
+            }
+        }
+
+        // A trailing `\\` leaves an empty row
+        if rows.len() > 1 && rows.last().unwrap().iter().all(|x| x == "<mtd></mtd>") {
+            rows.pop();
+        }
+
+        let table = format!(
+            "<mtable{}>{}</mtable>",
+            align,
+            rows.iter()
+                .map(|row| format!("<mtr>{}</mtr>", row.concat()))
+                .collect::<String>()
+        );
+
+        if open.is_empty() && close.is_empty() {
+            return Ok(table);
+        }
+
+        let fence = |x: &str| {
+            if x.is_empty() {
+                String::new()
+            } else {
+                format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", escape(x))
+            }
+        };
+        return Ok(format!(
+            "<mrow>{}{}{}</mrow>",
+            fence(open),
+            table,
+            fence(close)
+        ));
+    }
+}
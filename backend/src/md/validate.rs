@@ -0,0 +1,149 @@
+//! # Usage
+//! Checks that the raw HTML of a note is balanced. Problems in
+//! markdown, macros and math are found by the parser itself; this
+//! pass adds the ones that only show up across the whole note,
+//! such as a `<div>` opened in one block and never closed.
+
+use super::{parser::Parser, Severity, Span};
+
+/// Elements which never have a closing tag.
+const VOID: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose closing tag may be left out.
+const OPTIONAL: [&str; 12] = [
+    "p", "li", "dt", "dd", "tr", "td", "th", "thead", "tbody", "tfoot", "option", "colgroup",
+];
+
+/// # Usage
+/// An opening or closing tag found in the source.
+pub struct Tag {
+    pub offset: usize,
+    pub name: String,
+    pub closing: bool,
+}
+
+/// # Usage
+/// Finds the tags in a piece of raw HTML. `offset` maps a
+/// position in `html` onto the source.
+pub fn scan(html: &str, offset: impl Fn(usize) -> usize) -> Vec<Tag> {
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while let Some(start) = html[i..].find('<').map(|x| x + i) {
+        let rest = &html[start..];
+        if rest.starts_with("<!--") {
+            i = rest
+                .find("-->")
+                .map(|x| start + x + 3)
+                .unwrap_or(html.len());
+            continue;
+        }
+
+        let closing = rest.starts_with("</");
+        let name_start = if closing { 2 } else { 1 };
+        let name = rest[name_start..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            i = start + 1;
+            continue;
+        }
+
+        // Find the end of the tag, skipping quoted attribute values
+        let mut quote: Option<char> = None;
+        let mut end = None;
+        for (j, c) in rest.char_indices().skip(name_start + name.len()) {
+            match (c, quote) {
+                ('"' | '\'', None) => quote = Some(c),
+                (c, Some(q)) if c == q => quote = None,
+                ('>', None) => {
+                    end = Some(j);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let end = match end {
+            Some(end) => end,
+            None => {
+                i = start + 1;
+                continue;
+            }
+        };
+
+        let self_closing = rest[..end].ends_with('/');
+        if !VOID.contains(&name.as_str()) && !self_closing {
+            out.push(Tag {
+                offset: offset(start),
+                name,
+                closing,
+            });
+        }
+        i = start + end + 1;
+    }
+
+    return out;
+}
+
+impl<'s> Parser<'s> {
+    /// # Usage
+    /// Records the tags of a piece of raw HTML for [Parser::balance].
+    pub fn html_tags(&mut self, html: &str, offset: impl Fn(usize) -> usize) {
+        let expansion = self.expansion;
+        for mut tag in scan(html, offset) {
+            if let Some(span) = expansion {
+                tag.offset = span.start;
+            }
+            self.tags.push(tag);
+        }
+    }
+
+    /// # Usage
+    /// Reports tags that are never closed, or closed without
+    /// having been opened.
+    pub fn balance(&mut self) {
+        let tags = std::mem::take(&mut self.tags);
+        let mut open: Vec<&Tag> = Vec::new();
+
+        for tag in &tags {
+            if !tag.closing {
+                open.push(tag);
+                continue;
+            }
+
+            match open.iter().rposition(|x| x.name == tag.name) {
+                Some(position) => {
+                    for unclosed in open.drain(position..).skip(1) {
+                        if !OPTIONAL.contains(&unclosed.name.as_str()) {
+                            self.report(
+                                Span::at(unclosed.offset),
+                                Severity::Error,
+                                format!("`<{}>` is never closed", unclosed.name),
+                            );
+                        }
+                    }
+                }
+                None => self.report(
+                    Span::at(tag.offset),
+                    Severity::Error,
+                    format!("`</{}>` has no matching `<{}>`", tag.name, tag.name),
+                ),
+            }
+        }
+
+        for unclosed in open {
+            if !OPTIONAL.contains(&unclosed.name.as_str()) {
+                self.report(
+                    Span::at(unclosed.offset),
+                    Severity::Error,
+                    format!("`<{}>` is never closed", unclosed.name),
+                );
+            }
+        }
+    }
+}
@@ -1,10 +1,24 @@
+use crate::{
+    md::{self, Answer, Compiled, Diagnostics, Exercise, Heading, Library, Work},
+    review::Schedule,
+};
 use anyhow::{anyhow, Result};
 use backend_derive::{self, Table};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use sqlx::sqlite::SqlitePool;
-use std::{collections::HashMap, env, sync::Arc};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use sqlx::{
+    query::Query,
+    sqlite::{Sqlite, SqliteArguments, SqlitePool, SqliteRow},
+    FromRow, Row,
+};
+use std::{
+    collections::HashMap,
+    env,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 #[derive(Serialize, Deserialize)]
 pub enum SqliteType {
@@ -13,14 +27,40 @@ pub enum SqliteType {
     Integer,
 }
 
+impl SqliteType {
+    /// # Usage
+    /// Whether a JSON value can be written to a column of the type.
+    /// Integers are also accepted as reals.
+    pub fn accepts(&self, value: &Value) -> bool {
+        return match self {
+            SqliteType::Integer => value.is_i64(),
+            SqliteType::Real => value.is_number(),
+            SqliteType::Text => value.is_string(),
+        };
+    }
+}
+
 pub trait Table {
     /// # Usage
     /// Returns a map of field definitions.
     fn fields() -> Arc<HashMap<String, SqliteType>>; 
     fn name() -> &'static str;
+
+    /// # Usage
+    /// Returns columns which aren't fields of the table but are
+    /// computed from the values being set, such as a cache. They
+    /// are written in the same statement by [ModelController::update].
+    /// `library` holds the notes as they were before the update.
+    fn derived(
+        _set: &HashMap<String, Value>,
+        _library: &Library,
+    ) -> Result<Vec<(&'static str, String)>> {
+        return Ok(Vec::new());
+    }
 }
 
 #[derive(sqlx::FromRow, Deserialize, Serialize, Table)]
+#[table(derived = "Note::compiled")]
 pub struct Note {
     title: String,
     author: String,
@@ -28,8 +68,287 @@ pub struct Note {
     pub_date: i64,
 }
 
-#[derive(Serialize, Deserialize)]
-struct FieldCondition(String, String, Value);
+impl Note {
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// # Usage
+    /// Compiles a new source, so that its HTML, outline and
+    /// hash are cached in NoteTable along with it. The title
+    /// is only known here if it is being set too.
+    fn compiled(
+        set: &HashMap<String, Value>,
+        library: &Library,
+    ) -> Result<Vec<(&'static str, String)>> {
+        let source = match set.get("source") {
+            Some(source) => source.as_str().ok_or(anyhow!("Invalid type"))?,
+            None => return Ok(Vec::new()),
+        };
+        let title = set.get("title").and_then(Value::as_str).unwrap_or("");
+        let compiled = md::compile(title, source, library)
+            .map_err(|x| anyhow!("Failed to compile source:\n{}", x))?;
+
+        return Ok(vec![
+            ("html", compiled.html.as_str().to_string()),
+            ("outline", serde_json::to_string(&compiled.outline)?),
+            ("source_hash", source_hash(source)),
+        ]);
+    }
+}
+
+/// # Usage
+/// A note without its source, as it is sent to readers. Sources
+/// hold the hidden answers to exercises, so only admins read them.
+#[derive(Serialize)]
+pub struct Summary {
+    title: String,
+    author: String,
+    pub_date: i64,
+}
+
+impl Summary {
+    /// # Usage
+    /// Whether readers may see a field of a note, and so
+    /// filter, sort or list notes by it.
+    pub fn public(field: &str) -> bool {
+        return field != "source";
+    }
+}
+
+impl From<Note> for Summary {
+    fn from(note: Note) -> Self {
+        return Summary {
+            title: note.title,
+            author: note.author,
+            pub_date: note.pub_date,
+        };
+    }
+}
+
+/// # Usage
+/// A work which notes can cite by its key, listed
+/// in the bibliography of the notes which cite it.
+#[derive(sqlx::FromRow, Deserialize, Serialize, Table)]
+pub struct Reference {
+    key: String,
+    author: String,
+    title: String,
+    year: i64,
+    publisher: String,
+    url: String,
+}
+
+impl Reference {
+    /// # Usage
+    /// The key along with the entry of the bibliography.
+    fn work(self) -> (String, Work) {
+        return (
+            self.key,
+            Work {
+                author: self.author,
+                title: self.title,
+                year: self.year,
+                publisher: self.publisher,
+                url: self.url,
+            },
+        );
+    }
+}
+
+/// # Usage
+/// A note along with the HTML and outline cached for it, and the
+/// hash of the source last compiled, even if that failed.
+#[derive(sqlx::FromRow)]
+pub struct CachedNote {
+    #[sqlx(flatten)]
+    pub note: Note,
+    pub html: String,
+    outline: String,
+    source_hash: String,
+}
+
+impl CachedNote {
+    /// # Usage
+    /// Whether the source changed since it was last compiled,
+    /// such as for notes which have never been compiled.
+    pub fn stale(&self) -> bool {
+        return self.source_hash != source_hash(self.note.source());
+    }
+
+    /// # Usage
+    /// The cached outline of the note's headings.
+    pub fn outline(&self) -> Result<Vec<Heading>> {
+        return Ok(serde_json::from_str(&self.outline)?);
+    }
+}
+
+/// # Usage
+/// How far a user has read a note. Times are in seconds
+/// since the epoch, or `0` for never.
+#[derive(sqlx::FromRow, Serialize)]
+pub struct Progress {
+    pub note: String,
+    /// The slug of the last heading reached.
+    pub section: String,
+    /// The furthest fraction of the note scrolled through.
+    pub scroll: f64,
+    pub completed: bool,
+    pub opened_at: i64,
+    pub updated_at: i64,
+    pub completed_at: i64,
+}
+
+/// # Usage
+/// Progress made while reading a note. Fields which are
+/// `None` are left as they were; scrolling back up does
+/// not undo progress.
+#[derive(Deserialize)]
+pub struct Read {
+    pub section: Option<String>,
+    pub scroll: Option<f64>,
+    pub completed: Option<bool>,
+}
+
+/// # Usage
+/// A section of a note which a user reviews, see [review](crate::review).
+#[derive(sqlx::FromRow, Serialize)]
+pub struct Card {
+    pub note: String,
+    /// The slug of the section's heading, or empty for
+    /// notes without headings.
+    pub section: String,
+    /// The heading, or the note's title.
+    pub title: String,
+    pub ease: f64,
+    pub interval: i64,
+    pub repetitions: i64,
+    pub due: i64,
+    pub reviewed_at: i64,
+}
+
+/// # Usage
+/// A definition or theorem of a note, as a flashcard.
+/// See [flashcards](crate::md::Flashcard).
+#[derive(sqlx::FromRow, Serialize)]
+pub struct Flashcard {
+    pub front: String,
+    pub back: String,
+    pub note: String,
+    /// The id of the definition or theorem, or empty if it has none.
+    pub label: String,
+}
+
+/// # Usage
+/// The current time in seconds since the epoch.
+fn now() -> Result<i64> {
+    return Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64);
+}
+
+/// # Usage
+/// Hashes a source along with the version of the
+/// compiler, so either changing makes the cache stale.
+fn source_hash(source: &str) -> String {
+    return Sha256::new()
+        .chain_update(md::VERSION.to_le_bytes())
+        .chain_update(source.as_bytes())
+        .finalize()
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect();
+}
+
+/// # Usage
+/// A value which can't be written to a column of a table, either
+/// as it has the wrong type or as the table has no such column.
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// # Usage
+/// Checks the values to write to the columns of `T`, returning
+/// an error for each column which isn't a field of `T` or whose
+/// value has the wrong type, sorted by column.
+pub fn check<T: Table>(values: &HashMap<String, Value>) -> Result<(), Vec<FieldError>> {
+    let fields = T::fields();
+    let mut errors = values
+        .iter()
+        .filter_map(|(field, value)| {
+            let message = match fields.get(field) {
+                None => format!("{} has no field `{}`", T::name(), field),
+                Some(kind) if !kind.accepts(value) => {
+                    let expected = match kind {
+                        SqliteType::Integer => "an integer",
+                        SqliteType::Real => "a number",
+                        SqliteType::Text => "a string",
+                    };
+                    let found = match value {
+                        Value::Null => "null",
+                        Value::Bool(_) => "a boolean",
+                        Value::Number(x) if x.is_f64() => "a real number",
+                        Value::Number(_) => "an integer",
+                        Value::String(_) => "a string",
+                        Value::Array(_) => "an array",
+                        Value::Object(_) => "an object",
+                    };
+                    format!("`{}` should be {}, found {}", field, expected, found)
+                }
+                Some(_) => return None,
+            };
+            return Some(FieldError {
+                field: field.clone(),
+                message,
+            });
+        })
+        .collect::<Vec<_>>();
+    errors.sort_by(|a, b| a.field.cmp(&b.field));
+
+    return match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    };
+}
+
+/// # Usage
+/// Joins field errors into one, for callers which
+/// don't report them separately.
+fn combine(errors: Vec<FieldError>) -> anyhow::Error {
+    let messages = errors.into_iter().map(|x| x.message).collect::<Vec<_>>();
+    return anyhow!("Invalid fields: {}", messages.join("; "));
+}
+
+/// # Usage
+/// Binds a JSON value to a query as the given column type.
+fn bind<'q>(
+    query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    kind: &SqliteType,
+    value: &'q Value,
+) -> Result<Query<'q, Sqlite, SqliteArguments<'q>>> {
+    return Ok(match kind {
+        SqliteType::Integer => query.bind(value.as_i64().ok_or(anyhow!("Invalid type"))?),
+        SqliteType::Real => query.bind(value.as_f64().ok_or(anyhow!("Invalid type"))?),
+        SqliteType::Text => query.bind(value.as_str().ok_or(anyhow!("Invalid type"))?),
+    });
+}
+
+/// # Usage
+/// A condition on a field, written as `["FIELD", "OPERATOR", CONSTANT]`.
+/// The operators, and the constants they take, are:
+/// - `=`, `!=`, `<`, `<=`, `>` and `>=`, a value of the field's type.
+/// - `LIKE` and `NOT LIKE`, a pattern for a text field, in which `%`
+///   matches any text and `_` any character. `\` escapes either,
+///   as in `"100\\%"`. Letters match either case.
+/// - `IN` and `NOT IN`, an array of values of the field's type.
+/// - `BETWEEN`, an array of the lower and upper bound, inclusive.
+/// - `IS NULL` and `IS NOT NULL`, none: the constant may be left out.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FieldCondition(String, String, #[serde(default)] Value);
 
 impl FieldCondition {
     fn name(&self) -> &str {
@@ -44,98 +363,396 @@ impl FieldCondition {
         &self.2
     }
 
+    /// # Usage
+    /// The values to bind for the condition, in order.
+    fn values(&self) -> Vec<&Value> {
+        return match self.op() {
+            "IN" | "NOT IN" | "BETWEEN" => self.value().as_array().into_iter().flatten().collect(),
+            "IS NULL" | "IS NOT NULL" => Vec::new(),
+            _ => vec![self.value()],
+        };
+    }
+
+    /// # Usage
+    /// Whether the condition is on a field of `T`, with a
+    /// known operator given a constant of the field's type.
     fn valid<T: Table>(&self) -> bool {
-        if match self.op() {
-            "=" => true,
-            ">=" => true,
-            "<=" => true,
-            "<" => true,
-            ">" => true,
-            "!=" => true,
+        let fields = T::fields();
+        let Some(kind) = fields.get(self.name()) else {
+            return false;
+        };
+
+        let value = self.value();
+        let all = |values: &Vec<Value>| values.iter().all(|x| kind.accepts(x));
+        return match self.op() {
+            "=" | ">=" | "<=" | "<" | ">" | "!=" => kind.accepts(value),
+            "LIKE" | "NOT LIKE" => matches!(kind, SqliteType::Text) && value.is_string(),
+            "IN" | "NOT IN" => value.as_array().is_some_and(all),
+            "BETWEEN" => value.as_array().is_some_and(|x| x.len() == 2 && all(x)),
+            "IS NULL" | "IS NOT NULL" => value.is_null(),
             _ => false,
-        } {
-            return T::fields().contains_key(self.name());
-        }
+        };
+    }
 
-        return false;
+    /// # Usage
+    /// Generates the SQL of the condition, with
+    /// a placeholder for each of its [values](Self::values).
+    fn sql(&self) -> String {
+        return match self.op() {
+            "IN" | "NOT IN" => format!(
+                "{} {} ({})",
+                self.name(),
+                self.op(),
+                vec!["?"; self.values().len()].join(", ")
+            ),
+            "BETWEEN" => format!("{} BETWEEN ? AND ?", self.name()),
+            "LIKE" | "NOT LIKE" => format!("{} {} ? ESCAPE '\\'", self.name(), self.op()),
+            "IS NULL" | "IS NOT NULL" => format!("{} {}", self.name(), self.op()),
+            _ => format!("{} {} ?", self.name(), self.op()),
+        };
     }
 }
 
 /// # Usage
 /// Provides the some of flexiblity  of the sqlite WHERE clause in
-/// JSON form.
+/// JSON form, as a tree of conditions joined by `and`, `or` and `not`.
 ///
 /// Serializes to JSON as follows:
 /// ```Javascript
+/// {
+///     "and": [
+///         { "or": [["author", "=", "A"], ["author", "=", "B"]] },
+///         { "not": ["pub_date", "<", 1000] }
+///     ]
+/// }
+/// ```
+/// An empty `and` matches every row, and an empty `or` none.
+///
+/// The older flat format is still accepted:
+/// ```Javascript
 /// [
 ///     [["FIELD", "OPERATOR", CONSTANT], "GATE"],
 ///     [["FIELD", "OPERATOR", CONSTANT], ""]
 /// ]
 /// ```
-/// The last "GATE" variable must always be empty.
+/// where each "GATE" is `AND` or `OR` and the last is always empty.
+/// As in SQL, `AND` binds tighter than `OR`.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(try_from = "Expression", into = "Expression")]
+pub enum TableFilter {
+    And(Vec<TableFilter>),
+    Or(Vec<TableFilter>),
+    Not(Box<TableFilter>),
+    Condition(FieldCondition),
+}
+
+/// # Usage
+/// The JSON forms of a [TableFilter].
 #[derive(Deserialize, Serialize)]
-pub struct TableFilter(Vec<(FieldCondition, String)>);
+#[serde(untagged)]
+enum Expression {
+    Group(Group),
+    Condition(FieldCondition),
+    Gated(Vec<(FieldCondition, String)>),
+}
 
-impl TableFilter {
-    fn expr(&self) -> &Vec<(FieldCondition, String)> {
-        return &self.0;
-    }
+/// # Usage
+/// An `and`, `or` or `not` of an [Expression]. Any other key, or more
+/// than one of these, is refused rather than dropped, so that a filter
+/// like `{"and": [...], "not": [...]}` can't match more than it says.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Group {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    and: Option<Vec<TableFilter>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    or: Option<Vec<TableFilter>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    not: Option<Box<TableFilter>>,
+}
 
-    fn valid<T: Table>(&self) -> bool {
-        if self.expr().len() == 0 {
-            return true;
-        }
+impl TryFrom<Expression> for TableFilter {
+    type Error = String;
 
-        for cond in &self.expr()[..self.expr().len() - 1] {
-            if !cond.0.valid::<T>() {
-                return false;
+    fn try_from(expression: Expression) -> Result<Self, Self::Error> {
+        let gated = match expression {
+            Expression::Group(group) => {
+                return match (group.and, group.or, group.not) {
+                    (Some(and), None, None) => Ok(TableFilter::And(and)),
+                    (None, Some(or), None) => Ok(TableFilter::Or(or)),
+                    (None, None, Some(not)) => Ok(TableFilter::Not(not)),
+                    _ => Err("Expected exactly one of `and`, `or` and `not`".to_string()),
+                };
             }
+            Expression::Condition(cond) => return Ok(TableFilter::Condition(cond)),
+            Expression::Gated(gated) => gated,
+        };
 
-            if !match cond.1.as_str() {
-                "OR" => true,
-                "AND" => true,
-                _ => false,
-            } {
-                return false;
+        // Conditions joined by `AND` are grouped first, then the groups by `OR`
+        let last = gated.len().saturating_sub(1);
+        let mut groups = vec![Vec::new()];
+        for (i, (cond, gate)) in gated.into_iter().enumerate() {
+            groups.last_mut().unwrap().push(TableFilter::Condition(cond));
+            match gate.as_str() {
+                "AND" if i < last => {}
+                "OR" if i < last => groups.push(Vec::new()),
+                "" if i == last => {}
+                _ => return Err(format!("Invalid gate `{}`", gate)),
             }
         }
 
-        if !self.expr().last().unwrap().0.valid::<T>() || !self.expr().last().unwrap().1.is_empty()
-        {
-            return false;
+        let mut groups = groups
+            .into_iter()
+            .map(|mut x| match x.len() {
+                1 => x.remove(0),
+                _ => TableFilter::And(x),
+            })
+            .collect::<Vec<_>>();
+        return Ok(match groups.len() {
+            1 => groups.remove(0),
+            _ => TableFilter::Or(groups),
+        });
+    }
+}
+
+impl From<TableFilter> for Expression {
+    fn from(filter: TableFilter) -> Self {
+        let group = match filter {
+            TableFilter::And(and) => Group {
+                and: Some(and),
+                ..Default::default()
+            },
+            TableFilter::Or(or) => Group {
+                or: Some(or),
+                ..Default::default()
+            },
+            TableFilter::Not(not) => Group {
+                not: Some(not),
+                ..Default::default()
+            },
+            TableFilter::Condition(cond) => return Expression::Condition(cond),
+        };
+        return Expression::Group(group);
+    }
+}
+
+impl Default for TableFilter {
+    /// # Usage
+    /// A filter matching every row.
+    fn default() -> Self {
+        return TableFilter::And(Vec::new());
+    }
+}
+
+impl TableFilter {
+    /// # Usage
+    /// Every condition of the filter, in the order
+    /// their values are bound in the SQL.
+    fn conditions(&self) -> Vec<&FieldCondition> {
+        let mut out = Vec::new();
+        let mut stack = vec![self];
+        while let Some(filter) = stack.pop() {
+            match filter {
+                TableFilter::And(filters) | TableFilter::Or(filters) => {
+                    stack.extend(filters.iter().rev())
+                }
+                TableFilter::Not(filter) => stack.push(filter),
+                TableFilter::Condition(cond) => out.push(cond),
+            }
         }
+        return out;
+    }
 
-        return true;
+    /// # Usage
+    /// The fields the conditions of the filter are on.
+    pub fn fields(&self) -> Vec<&str> {
+        return self.conditions().iter().map(|x| x.name()).collect();
+    }
+
+    /// # Usage
+    /// Whether the filter has no conditions. Such filters
+    /// match every row, unless they are built of `or`s.
+    pub fn is_empty(&self) -> bool {
+        return self.conditions().is_empty();
+    }
+
+    /// # Usage
+    /// Whether every condition is on a field of `T`
+    /// with a known operator.
+    pub fn valid<T: Table>(&self) -> bool {
+        return self.conditions().iter().all(|x| x.valid::<T>());
+    }
+
+    /// # Usage
+    /// Generates the SQL of the expression, with every
+    /// group of conditions in parentheses.
+    fn expr(&self) -> String {
+        let join = |filters: &[TableFilter], gate: &str, empty: &str| {
+            return match filters.is_empty() {
+                true => empty.to_string(),
+                false => format!(
+                    "({})",
+                    filters
+                        .iter()
+                        .map(|x| x.expr())
+                        .collect::<Vec<_>>()
+                        .join(gate)
+                ),
+            };
+        };
+        return match self {
+            TableFilter::And(filters) => join(filters, " AND ", "1"),
+            TableFilter::Or(filters) => join(filters, " OR ", "0"),
+            TableFilter::Not(filter) => match filter.as_ref() {
+                TableFilter::Condition(_) => format!("NOT ({})", filter.expr()),
+                _ => format!("NOT {}", filter.expr()),
+            },
+            TableFilter::Condition(cond) => cond.sql(),
+        };
+    }
+
+    /// # Usage
+    /// Binds the values of the conditions to a query
+    /// of the SQL generated by [Self::sql].
+    fn bind<'q, T: Table>(
+        &'q self,
+        mut query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    ) -> Result<Query<'q, Sqlite, SqliteArguments<'q>>> {
+        for cond in self.conditions() {
+            for value in cond.values() {
+                query = bind(query, &T::fields()[cond.name()], value)?;
+            }
+        }
+        return Ok(query);
     }
 
     /// # Usage
     /// Generates incomplete sql code for a WHERE clause.
-    /// Values still need to be bound
+    /// Values still need to be bound, see [Self::bind].
     fn sql(&self) -> String {
-        if self.expr().len() == 0 {
+        if matches!(self, TableFilter::And(x) if x.is_empty()) {
             return String::new();
         }
 
-        let mut sql = String::new();
-        sql.push_str("WHERE ");
+        return format!("WHERE {}", self.expr());
+    }
+}
 
-        for cond in &self.expr()[0..self.expr().len() - 1] {
-            sql.push_str(format!("{} {} ? {} ", cond.0.name(), cond.0.op(), cond.1).as_str())
-        }
+/// # Usage
+/// How to sort selected rows, by one of the table's fields.
+/// Serializes into JSON as follows:
+/// ```javascript
+/// { "by": "pub_date", "descending": true }
+/// ```
+#[derive(Deserialize, Serialize)]
+pub struct Order {
+    by: String,
+    #[serde(default)]
+    descending: bool,
+}
 
-        sql.push_str(
-            format!(
-                "{} {} ?",
-                self.expr().last().unwrap().0.name(),
-                self.expr().last().unwrap().0.op()
-            )
-            .as_str(),
+impl Order {
+    pub fn new(by: String, descending: bool) -> Self {
+        return Order { by, descending };
+    }
+
+    pub fn by(&self) -> &str {
+        &self.by
+    }
+
+    pub fn valid<T: Table>(&self) -> bool {
+        return T::fields().contains_key(&self.by);
+    }
+
+    /// # Usage
+    /// Generates an ORDER BY clause. Ties are broken by rowid,
+    /// so that pages of the rows don't overlap.
+    fn sql(&self) -> String {
+        return format!(
+            "ORDER BY {} {}, rowid",
+            self.by,
+            match self.descending {
+                true => "DESC",
+                false => "ASC",
+            }
         );
+    }
+}
 
-        return sql;
+/// # Usage
+/// Where a page of rows ended: the field the rows are sorted by,
+/// its value in the last row of the page and that row's rowid, or
+/// just the rowid for unsorted rows. The next page starts after it,
+/// even if rows before it were since inserted or deleted.
+///
+/// Written as the hex of the JSON `["FIELD", VALUE, ROWID]`,
+/// so that it can be passed around as is.
+#[derive(Deserialize, Serialize)]
+pub struct Cursor(Option<String>, Value, i64);
+
+impl Cursor {
+    pub fn parse(s: &str) -> Option<Self> {
+        // Odd lengths leave a single digit, which `get` fails on
+        let bytes = (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<_>>>()?;
+        return serde_json::from_slice(&bytes).ok();
+    }
+
+    fn encode(&self) -> Result<String> {
+        return Ok(serde_json::to_vec(self)?
+            .iter()
+            .map(|x| format!("{:02x}", x))
+            .collect());
+    }
+
+    /// # Usage
+    /// Whether the cursor ended a page of rows of `T` sorted by `order`.
+    pub fn valid<T: Table>(&self, order: Option<&Order>) -> bool {
+        return match (order, &self.0) {
+            (Some(order), Some(field)) => {
+                *field == order.by
+                    && T::fields()
+                        .get(field)
+                        .is_some_and(|x| x.accepts(&self.1))
+            }
+            (None, None) => self.1.is_null(),
+            _ => false,
+        };
     }
 }
 
+/// # Usage
+/// Where a page of rows starts.
+pub enum Start {
+    /// After skipping a number of rows.
+    Offset(u32),
+    /// After the end of the previous page.
+    After(Cursor),
+}
+
+/// # Usage
+/// A page of rows, with only some of their fields.
+pub struct Page {
+    pub rows: Vec<Map<String, Value>>,
+    /// The number of rows matching the filter, over every page.
+    pub total: i64,
+    /// Where the next page starts, if the page had a limit
+    /// and there are rows after it.
+    pub next: Option<String>,
+}
+
+/// # Usage
+/// Reads a column of a row as JSON.
+fn read(row: &SqliteRow, column: &str, kind: &SqliteType) -> Result<Value> {
+    return Ok(match kind {
+        SqliteType::Integer => Value::from(row.try_get::<i64, _>(column)?),
+        SqliteType::Real => Value::from(row.try_get::<f64, _>(column)?),
+        SqliteType::Text => Value::from(row.try_get::<String, _>(column)?),
+    });
+}
+
 /// # Usage
 /// Locates and updates a set of rows in the databse.
 /// Serializes into JSON as follows:
@@ -166,19 +783,28 @@ impl Updater {
         if !self.at.valid::<T>() {
             return false;
         }
-        if self.set.len() == 0 {
+        if self.set.is_empty() {
             return false;
         }
 
-        for val in &self.set {
-            if !T::fields().contains_key(val.0) {
-                return false;
-            }
-        }
-
         return true;
     }
 
+    /// # Usage
+    /// Checks the values being set, see [check].
+    pub fn check<T: Table>(&self) -> Result<(), Vec<FieldError>> {
+        return check::<T>(&self.set);
+    }
+
+    /// # Usage
+    /// The columns being set along with their values, sorted by
+    /// column, which is the order they are written and bound in.
+    fn columns(&self) -> Vec<(&String, &Value)> {
+        let mut columns = self.set.iter().collect::<Vec<_>>();
+        columns.sort_by_key(|x| x.0);
+        return columns;
+    }
+
     /// # Usage
     /// Creates incomplete SQL in the form
     /// ```SQL
@@ -186,12 +812,16 @@ impl Updater {
     /// SET COLUMN1 = ?, COLUMN2 = ?
     /// WHERE COLUMN5 OPERATOR ?
     /// ```
-    fn sql<T: Table>(&self) -> String {
+    /// followed by the `derived` columns in the SET branch.
+    fn sql<T: Table>(&self, derived: &[(&str, String)]) -> String {
         let mut sql = String::new();
         sql.push_str(format!("UPDATE {} ", T::name()).as_str());
         sql.push_str("SET ");
 
-        for value in &self.set {
+        for value in self.columns() {
+            sql.push_str(format!("{} = ?, ", value.0).as_str());
+        }
+        for value in derived {
             sql.push_str(format!("{} = ?, ", value.0).as_str());
         }
         sql.remove(sql.len() - 2);
@@ -213,10 +843,15 @@ pub struct ModelController {
 impl ModelController {
     /// # Usage
     /// Creates a new model controller.
+    /// Runs any pending migrations.
     pub async fn new() -> Result<Self> {
-        Ok(ModelController {
-            pool: SqlitePool::connect(&env::var("DATABASE_URL")?).await?,
-        })
+        let pool = SqlitePool::connect(&env::var("DATABASE_URL")?).await?;
+        sqlx::migrate!().run(&pool).await?;
+
+        let mc = ModelController { pool };
+        mc.relink_notes().await?;
+
+        Ok(mc)
     }
 
     pub fn pool(&self) -> &SqlitePool {
@@ -227,29 +862,1110 @@ impl ModelController {
         if !updater.valid::<T>() {
             return Err(anyhow!("Invalid updater"));
         }
+        updater.check::<T>().map_err(combine)?;
 
         let mut conn = self.pool.acquire().await?;
 
-        let query_str = updater.sql::<T>();
+        // Notes whose links may change, found before their titles do
+        let relinked = T::name() == Note::name()
+            && (updater.set.contains_key("source") || updater.set.contains_key("title"));
+        let rows = match relinked {
+            true => self.rows::<T>(&updater.at).await?,
+            false => Vec::new(),
+        };
+
+        let derived = T::derived(&updater.set, &self.library().await?)?;
+        let query_str = updater.sql::<T>(&derived);
+        log::info!("query_str: \n {}", query_str);
+        let mut query = sqlx::query(query_str.as_str());
+
+        for value in updater.columns() {
+            query = bind(query, &T::fields()[value.0], value.1)?;
+        }
+        for value in &derived {
+            query = query.bind(value.1.as_str());
+        }
+        query = updater.at.bind::<T>(query)?;
+
+        query.execute(&mut conn).await?;
+
+        // Notes referring to others may now compile differently
+        self.invalidate::<T>().await?;
+        if relinked {
+            self.relink(&rows).await?;
+            self.recache(&rows).await?;
+            self.retitle(&rows).await?;
+        }
+
+        return Ok(());
+    }
+
+    /// # Usage
+    /// Inserts a row, given the value of every field of `T` and no
+    /// others, along with its `derived` columns. Returns whether it
+    /// was inserted, which it isn't if a row with the same key exists.
+    /// Notes are also refused if a note's title differs only in case.
+    pub async fn insert<T: Table>(&self, row: &HashMap<String, Value>) -> Result<bool> {
+        let fields = T::fields();
+        check::<T>(row).map_err(combine)?;
+        if row.len() != fields.len() {
+            return Err(anyhow!("Missing fields"));
+        }
+
+        let title = match T::name() == Note::name() {
+            true => Some(row["title"].as_str().ok_or(anyhow!("Invalid type"))?),
+            false => None,
+        };
+        if let Some(title) = title {
+            let existing = sqlx::query("SELECT 1 FROM NoteTable WHERE title = ? COLLATE NOCASE")
+                .bind(title)
+                .fetch_optional(&self.pool)
+                .await?;
+            if existing.is_some() {
+                return Ok(false);
+            }
+        }
+
+        let derived = T::derived(row, &self.library().await?)?;
+        let mut values = row.iter().collect::<Vec<_>>();
+        values.sort_by_key(|x| x.0);
+        let columns = values
+            .iter()
+            .map(|x| x.0.as_str())
+            .chain(derived.iter().map(|x| x.0))
+            .collect::<Vec<_>>();
+        let query_str = format!(
+            "INSERT OR IGNORE INTO {} ({}) VALUES ({})",
+            T::name(),
+            columns.join(", "),
+            vec!["?"; columns.len()].join(", ")
+        );
         log::info!("query_str: \n {}", query_str);
         let mut query = sqlx::query(query_str.as_str());
 
-        for cond in updater.at.expr() {
-            query = match T::fields()[cond.0.name()] {
-                SqliteType::Integer => {
-                    query.bind(cond.0.value().as_i64().ok_or(anyhow!("Invalid type"))?)
+        for value in values {
+            query = bind(query, &fields[value.0], value.1)?;
+        }
+        for value in &derived {
+            query = query.bind(value.1.as_str());
+        }
+
+        let result = query.execute(&self.pool).await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        // Notes linking to the new one may now compile differently
+        self.invalidate::<T>().await?;
+        if let Some(title) = title {
+            let rows = [(result.last_insert_rowid(), title.to_string())];
+            self.relink(&rows).await?;
+            self.recache(&rows).await?;
+        }
+
+        return Ok(true);
+    }
+
+    /// # Usage
+    /// Returns the rows matching `filter` sorted by `order`, or in the
+    /// order they were inserted, skipping the first `offset` of them
+    /// and returning at most `limit`. Returns `None` if the filter or
+    /// order isn't on fields of `T`.
+    pub async fn select<T>(
+        &self,
+        filter: &TableFilter,
+        order: Option<&Order>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> Result<Option<Vec<T>>>
+    where
+        T: Table + for<'r> FromRow<'r, SqliteRow>,
+    {
+        if !filter.valid::<T>() || !order.is_none_or(|x| x.valid::<T>()) {
+            return Ok(None);
+        }
+
+        let mut columns = T::fields().keys().cloned().collect::<Vec<_>>();
+        columns.sort();
+        let start = Start::Offset(offset.unwrap_or(0));
+        return self
+            .rows_of::<T>(&columns, filter, order, limit, &start)
+            .await?
+            .iter()
+            .map(|x| Ok(T::from_row(x)?))
+            .collect::<Result<_>>()
+            .map(Some);
+    }
+
+    /// # Usage
+    /// Returns a page of the rows matching `filter`, as in [Self::select],
+    /// with only the given `fields`. Pages start either some rows in, or
+    /// after the [Cursor] ending the previous page, which is returned
+    /// with each page that has a `limit` and rows after it. Returns
+    /// `None` if a field isn't one of `T`'s, or the cursor isn't from
+    /// a page in the same order.
+    pub async fn page<T: Table>(
+        &self,
+        filter: &TableFilter,
+        fields: &[String],
+        order: Option<&Order>,
+        limit: Option<u32>,
+        start: &Start,
+    ) -> Result<Option<Page>> {
+        let all = T::fields();
+        if !filter.valid::<T>()
+            || fields.is_empty()
+            || !fields.iter().all(|x| all.contains_key(x))
+            || !order.is_none_or(|x| x.valid::<T>())
+            || matches!(start, Start::After(x) if !x.valid::<T>(order))
+        {
+            return Ok(None);
+        }
+
+        let query_str = format!("SELECT COUNT(*) FROM {} {}", T::name(), filter.sql());
+        let total: i64 = filter
+            .bind::<T>(sqlx::query(query_str.as_str()))?
+            .fetch_one(&self.pool)
+            .await?
+            .try_get(0)?;
+
+        // The sorted field is read for the cursor, even if not returned
+        let mut columns = fields.to_vec();
+        columns.push("rowid".to_string());
+        if let Some(order) = order {
+            columns.push(order.by.clone());
+        }
+        // One more row than asked for tells whether there is a next page
+        let mut rows = self
+            .rows_of::<T>(
+                &columns,
+                filter,
+                order,
+                limit.map(|x| x.saturating_add(1)),
+                start,
+            )
+            .await?;
+
+        let mut next = None;
+        if let Some(limit) = limit.filter(|x| rows.len() > *x as usize) {
+            rows.truncate(limit as usize);
+            if let Some(last) = rows.last() {
+                let cursor = Cursor(
+                    order.map(|x| x.by.clone()),
+                    match order {
+                        Some(order) => read(last, &order.by, &all[&order.by])?,
+                        None => Value::Null,
+                    },
+                    last.try_get("rowid")?,
+                );
+                next = Some(cursor.encode()?);
+            }
+        }
+
+        let rows = rows
+            .iter()
+            .map(|row| {
+                return fields
+                    .iter()
+                    .map(|x| Ok((x.clone(), read(row, x, &all[x])?)))
+                    .collect::<Result<Map<_, _>>>();
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        return Ok(Some(Page { rows, total, next }));
+    }
+
+    /// # Usage
+    /// Returns the `columns` of the rows matching `filter` sorted by
+    /// `order`, or in the order they were inserted, from `start` on
+    /// and at most `limit` of them. The query behind both
+    /// [Self::select] and [Self::page], which check their arguments.
+    async fn rows_of<T: Table>(
+        &self,
+        columns: &[String],
+        filter: &TableFilter,
+        order: Option<&Order>,
+        limit: Option<u32>,
+        start: &Start,
+    ) -> Result<Vec<SqliteRow>> {
+        let clause = match (start, order) {
+            (Start::Offset(_), _) => filter.sql(),
+            (Start::After(_), Some(order)) => format!(
+                "WHERE {} AND ({1} {2} ? OR ({1} = ? AND rowid > ?))",
+                filter.expr(),
+                order.by,
+                match order.descending {
+                    true => "<",
+                    false => ">",
                 }
-                SqliteType::Real => {
-                    query.bind(cond.0.value().as_f64().ok_or(anyhow!("Invalid type"))?)
+            ),
+            (Start::After(_), None) => format!("WHERE {} AND rowid > ?", filter.expr()),
+        };
+        let query_str = format!(
+            "SELECT {} FROM {} {} {} LIMIT ? OFFSET ?",
+            columns.join(", "),
+            T::name(),
+            clause,
+            order.map_or("ORDER BY rowid".to_string(), |x| x.sql())
+        );
+        log::info!("query_str: \n {}", query_str);
+
+        let mut query = filter.bind::<T>(sqlx::query(query_str.as_str()))?;
+        if let Start::After(cursor) = start {
+            if let Some(order) = order {
+                let field = &T::fields()[&order.by];
+                query = bind(query, field, &cursor.1)?;
+                query = bind(query, field, &cursor.1)?;
+            }
+            query = query.bind(cursor.2);
+        }
+        // A negative limit is no limit
+        return Ok(query
+            .bind(limit.map_or(-1, i64::from))
+            .bind(match start {
+                Start::Offset(offset) => *offset,
+                Start::After(_) => 0,
+            })
+            .fetch_all(&self.pool)
+            .await?);
+    }
+
+    /// # Usage
+    /// Deletes every row matching `filter`, returning how many were
+    /// deleted. An empty filter matches every row. Deleting notes also
+    /// deletes what is stored for them elsewhere, see [Self::purge].
+    pub async fn delete<T: Table>(&self, filter: &TableFilter) -> Result<u64> {
+        if !filter.valid::<T>() {
+            return Err(anyhow!("Invalid filter"));
+        }
+
+        let rows = match T::name() == Note::name() {
+            true => self.rows::<T>(filter).await?,
+            false => Vec::new(),
+        };
+
+        let query_str = format!("DELETE FROM {} {}", T::name(), filter.sql());
+        log::info!("query_str: \n {}", query_str);
+        let mut query = sqlx::query(query_str.as_str());
+        query = filter.bind::<T>(query)?;
+
+        let result = query.execute(&self.pool).await?;
+
+        // Notes referring to deleted rows may now compile differently
+        self.invalidate::<T>().await?;
+        self.purge(&rows).await?;
+
+        return Ok(result.rows_affected());
+    }
+
+    /// # Usage
+    /// Deletes the links, exercises and flashcards of each of the
+    /// given notes, by rowid and title, along with what users have
+    /// done with them, once the notes themselves are deleted.
+    async fn purge(&self, rows: &[(i64, String)]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for (_, title) in rows {
+            for (table, column) in [
+                ("LinkTable", "source"),
+                ("ExerciseTable", "note"),
+                ("FlashcardTable", "note"),
+                ("ProgressTable", "note"),
+                ("CardTable", "note"),
+            ] {
+                sqlx::query(&format!("DELETE FROM {} WHERE {} = ?", table, column))
+                    .bind(title)
+                    .execute(&mut tx)
+                    .await?;
+            }
+        }
+        tx.commit().await?;
+
+        return Ok(());
+    }
+
+    /// # Usage
+    /// Returns the titles the notes changed by `updater` will have,
+    /// so that a new source can be checked as the note it is for.
+    pub async fn titles(&self, updater: &Updater) -> Result<Vec<String>> {
+        if !updater.at.valid::<Note>() {
+            return Err(anyhow!("Invalid filter"));
+        }
+        if let Some(title) = updater.set.get("title").and_then(Value::as_str) {
+            return Ok(vec![title.to_string()]);
+        }
+
+        let rows = self.rows::<Note>(&updater.at).await?;
+        return Ok(rows.into_iter().map(|x| x.1).collect());
+    }
+
+    /// # Usage
+    /// Returns the rowid and title of every row matching `filter`.
+    async fn rows<T: Table>(&self, filter: &TableFilter) -> Result<Vec<(i64, String)>> {
+        let query_str = format!("SELECT rowid, title FROM {} {}", T::name(), filter.sql());
+        let mut query = sqlx::query(query_str.as_str());
+        query = filter.bind::<T>(query)?;
+
+        return query
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|x| Ok((x.try_get("rowid")?, x.try_get("title")?)))
+            .collect();
+    }
+
+    /// # Usage
+    /// Replaces the links from each of the given notes, by rowid and
+    /// the title they had before being updated, in LinkTable.
+    async fn relink(&self, rows: &[(i64, String)]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for (rowid, title) in rows {
+            sqlx::query("DELETE FROM LinkTable WHERE source = ?")
+                .bind(title)
+                .execute(&mut tx)
+                .await?;
+
+            let (title, source) = sqlx::query_as::<_, (String, String)>(
+                "SELECT title, source FROM NoteTable WHERE rowid = ?",
+            )
+            .bind(rowid)
+            .fetch_one(&mut tx)
+            .await?;
+            for target in md::outgoing(&source) {
+                if target.eq_ignore_ascii_case(&title) {
+                    continue;
                 }
-                SqliteType::Text => {
-                    query.bind(cond.0.value().as_str().ok_or(anyhow!("Invalid type"))?)
+                sqlx::query("INSERT OR IGNORE INTO LinkTable (source, target) VALUES (?, ?)")
+                    .bind(&title)
+                    .bind(target)
+                    .execute(&mut tx)
+                    .await?;
+            }
+        }
+        tx.commit().await?;
+
+        return Ok(());
+    }
+
+    /// # Usage
+    /// Recompiles each of the given notes, by rowid and the title they
+    /// had before being updated, so that the answers of their exercises
+    /// and their flashcards are stored under their current title. Notes
+    /// which fail to compile lose both until they are fixed.
+    async fn recache(&self, rows: &[(i64, String)]) -> Result<()> {
+        let library = self.library().await?;
+        for (rowid, title) in rows {
+            for table in ["ExerciseTable", "FlashcardTable"] {
+                sqlx::query(&format!("DELETE FROM {} WHERE note = ?", table))
+                    .bind(title)
+                    .execute(&self.pool)
+                    .await?;
+            }
+
+            let note = sqlx::query_as::<_, Note>(
+                "SELECT title, author, source, pub_date FROM NoteTable WHERE rowid = ?",
+            )
+            .bind(rowid)
+            .fetch_one(&self.pool)
+            .await?;
+            if let Ok(compiled) = md::compile(note.title(), note.source(), &library) {
+                self.cache(&note, &compiled).await?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// # Usage
+    /// Moves what users have done with each of the given notes, by
+    /// rowid and the title they had before being updated, to their
+    /// current title.
+    async fn retitle(&self, rows: &[(i64, String)]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for (rowid, title) in rows {
+            for table in ["ProgressTable", "CardTable"] {
+                sqlx::query(&format!(
+                    "UPDATE {} SET note = (SELECT title FROM NoteTable WHERE rowid = ?) WHERE note = ?",
+                    table
+                ))
+                .bind(rowid)
+                .bind(title)
+                .execute(&mut tx)
+                .await?;
+            }
+        }
+        tx.commit().await?;
+
+        return Ok(());
+    }
+
+    /// # Usage
+    /// Records that a user read the note titled `title`, ignoring
+    /// case, returning their progress on it. Returns `None` if no
+    /// note has the title.
+    pub async fn read(&self, user_id: i64, title: &str, read: &Read) -> Result<Option<Progress>> {
+        let Some((title,)) = sqlx::query_as::<_, (String,)>(
+            "SELECT title FROM NoteTable WHERE title = ? COLLATE NOCASE",
+        )
+        .bind(title)
+        .fetch_optional(&self.pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        sqlx::query(
+            "
+            INSERT INTO ProgressTable
+                (user_id, note, section, scroll, completed, opened_at, updated_at, completed_at)
+            VALUES
+                (?1, ?2, COALESCE(?3, ''), COALESCE(?4, 0), COALESCE(?5, 0), ?6, ?6,
+                 CASE WHEN ?5 THEN ?6 ELSE 0 END)
+            ON CONFLICT (user_id, note) DO UPDATE SET
+                section = COALESCE(?3, section),
+                scroll = MAX(scroll, COALESCE(?4, 0)),
+                completed = COALESCE(?5, completed),
+                updated_at = ?6,
+                completed_at = CASE
+                    WHEN ?5 AND NOT completed THEN ?6
+                    WHEN NOT ?5 THEN 0
+                    ELSE completed_at
+                END
+        ",
+        )
+        .bind(user_id)
+        .bind(&title)
+        .bind(&read.section)
+        .bind(read.scroll)
+        .bind(read.completed)
+        .bind(now()?)
+        .execute(&self.pool)
+        .await?;
+
+        return self.progress(user_id, &title).await;
+    }
+
+    /// # Usage
+    /// Returns a user's progress on the note titled `title`,
+    /// ignoring case, if they have opened it.
+    pub async fn progress(&self, user_id: i64, title: &str) -> Result<Option<Progress>> {
+        let progress = sqlx::query_as::<_, Progress>(
+            "
+            SELECT
+                note, section, scroll, completed, opened_at, updated_at, completed_at
+            FROM
+                ProgressTable
+            WHERE
+                user_id = ? AND note = ?
+        ",
+        )
+        .bind(user_id)
+        .bind(title)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        return Ok(progress);
+    }
+
+    /// # Usage
+    /// Returns a user's progress on every note they have opened,
+    /// most recently read first.
+    pub async fn progress_all(&self, user_id: i64) -> Result<Vec<Progress>> {
+        let progress = sqlx::query_as::<_, Progress>(
+            "
+            SELECT
+                note, section, scroll, completed, opened_at, updated_at, completed_at
+            FROM
+                ProgressTable
+            WHERE
+                user_id = ?
+            ORDER BY
+                updated_at DESC, note
+        ",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        return Ok(progress);
+    }
+
+    /// # Usage
+    /// Returns the number of notes.
+    pub async fn count_notes(&self) -> Result<i64> {
+        let (count,) = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM NoteTable")
+            .fetch_one(&self.pool)
+            .await?;
+
+        return Ok(count);
+    }
+
+    /// # Usage
+    /// Returns the exercise with the given id in the note titled
+    /// `title`, ignoring case, along with the note's exact title.
+    pub async fn exercise(&self, title: &str, id: &str) -> Result<Option<(String, Exercise)>> {
+        let exercise = sqlx::query_as::<_, (String, String, String, f64)>(
+            "SELECT note, section, expected, tolerance FROM ExerciseTable WHERE note = ? AND id = ?",
+        )
+        .bind(title)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        return Ok(exercise.map(|(note, section, expected, tolerance)| {
+            (
+                note,
+                Exercise {
+                    id: id.to_string(),
+                    section,
+                    answer: Answer {
+                        expected,
+                        tolerance,
+                    },
+                },
+            )
+        }));
+    }
+
+    /// # Usage
+    /// Returns the flashcards of the note titled `title`, ignoring
+    /// case, in the order they appear in it. Notes whose cache is
+    /// stale are recompiled first. Returns `None` if no note has
+    /// the title.
+    pub async fn flashcards(&self, title: &str) -> Result<Option<Vec<Flashcard>>> {
+        let Some(cached) = sqlx::query_as::<_, CachedNote>(
+            "
+            SELECT
+                title, author, source, pub_date, html, outline, source_hash
+            FROM
+                NoteTable
+            WHERE
+                title = ? COLLATE NOCASE
+        ",
+        )
+        .bind(title)
+        .fetch_optional(&self.pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+        // Notes which fail to compile keep the cards last cached for them
+        self.rendered(&cached).await?;
+
+        let cards = sqlx::query_as::<_, Flashcard>(
+            "
+            SELECT
+                front, back, note, label
+            FROM
+                FlashcardTable
+            WHERE
+                note = ?
+            ORDER BY
+                position
+        ",
+        )
+        .bind(cached.note.title())
+        .fetch_all(&self.pool)
+        .await?;
+
+        return Ok(Some(cards));
+    }
+
+    /// # Usage
+    /// Gives a user a card for each section of the note titled
+    /// `title`, as found in its cached outline, keeping the
+    /// schedules of existing cards and dropping cards of
+    /// sections which no longer exist.
+    pub async fn seed(&self, user_id: i64, title: &str) -> Result<()> {
+        let Some((title, outline)) = sqlx::query_as::<_, (String, String)>(
+            "SELECT title, outline FROM NoteTable WHERE title = ? COLLATE NOCASE",
+        )
+        .bind(title)
+        .fetch_optional(&self.pool)
+        .await?
+        else {
+            return Ok(());
+        };
+
+        fn flatten(headings: Vec<Heading>, out: &mut Vec<(String, String)>) {
+            for heading in headings {
+                out.push((heading.slug, heading.title));
+                flatten(heading.children, out);
+            }
+        }
+        let mut sections = Vec::new();
+        flatten(serde_json::from_str(&outline)?, &mut sections);
+        if sections.is_empty() {
+            sections.push((String::new(), title.clone()));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        for (section, heading) in &sections {
+            sqlx::query(
+                "
+                INSERT INTO CardTable (user_id, note, section, title) VALUES (?, ?, ?, ?)
+                ON CONFLICT (user_id, note, section) DO UPDATE SET title = excluded.title
+            ",
+            )
+            .bind(user_id)
+            .bind(&title)
+            .bind(section)
+            .bind(heading)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        let query_str = format!(
+            "DELETE FROM CardTable WHERE user_id = ? AND note = ? AND section NOT IN ({})",
+            vec!["?"; sections.len()].join(", ")
+        );
+        let mut query = sqlx::query(&query_str).bind(user_id).bind(&title);
+        for (section, _) in &sections {
+            query = query.bind(section);
+        }
+        query.execute(&mut tx).await?;
+        tx.commit().await?;
+
+        return Ok(());
+    }
+
+    /// # Usage
+    /// Returns up to `limit` of a user's cards which are due, most
+    /// overdue first, along with how many are due in all. Cards are
+    /// first seeded from every note the user has opened.
+    pub async fn queue(&self, user_id: i64, limit: i64) -> Result<(i64, Vec<Card>)> {
+        let opened =
+            sqlx::query_as::<_, (String,)>("SELECT note FROM ProgressTable WHERE user_id = ?")
+                .bind(user_id)
+                .fetch_all(&self.pool)
+                .await?;
+        for (note,) in opened {
+            self.seed(user_id, &note).await?;
+        }
+
+        let now = now()?;
+        let (due,) = sqlx::query_as::<_, (i64,)>(
+            "SELECT COUNT(*) FROM CardTable WHERE user_id = ? AND due <= ?",
+        )
+        .bind(user_id)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+        let cards = sqlx::query_as::<_, Card>(
+            "
+            SELECT
+                note, section, title, ease, interval, repetitions, due, reviewed_at
+            FROM
+                CardTable
+            WHERE
+                user_id = ? AND due <= ?
+            ORDER BY
+                due, note, section
+            LIMIT ?
+        ",
+        )
+        .bind(user_id)
+        .bind(now)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        return Ok((due, cards));
+    }
+
+    /// # Usage
+    /// Schedules a user's card after a review graded `quality` out
+    /// of 5, returning the card. Cards which aren't due are returned
+    /// as they are. Returns `None` if the user has no such card, and
+    /// fails if the grade is greater than 5.
+    pub async fn grade(
+        &self,
+        user_id: i64,
+        note: &str,
+        section: &str,
+        quality: u8,
+    ) -> Result<Option<Card>> {
+        let select = "
+            SELECT
+                note, section, title, ease, interval, repetitions, due, reviewed_at
+            FROM
+                CardTable
+            WHERE
+                user_id = ? AND note = ? AND section = ?
+        ";
+        let Some(card) = sqlx::query_as::<_, Card>(select)
+            .bind(user_id)
+            .bind(note)
+            .bind(section)
+            .fetch_optional(&self.pool)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let now = now()?;
+        let current = Schedule {
+            ease: card.ease,
+            interval: card.interval,
+            repetitions: card.repetitions,
+            due: card.due,
+        };
+        let schedule = current
+            .grade(quality, now)
+            .ok_or(anyhow!("Invalid grade {}, expected 0 to 5", quality))?;
+        if !current.is_due(now) {
+            return Ok(Some(card));
+        }
+        sqlx::query(
+            "
+            UPDATE CardTable SET
+                ease = ?, interval = ?, repetitions = ?, due = ?, reviewed_at = ?
+            WHERE
+                user_id = ? AND note = ? AND section = ?
+        ",
+        )
+        .bind(schedule.ease)
+        .bind(schedule.interval)
+        .bind(schedule.repetitions)
+        .bind(schedule.due)
+        .bind(now)
+        .bind(user_id)
+        .bind(&card.note)
+        .bind(&card.section)
+        .execute(&self.pool)
+        .await?;
+
+        return Ok(Some(Card {
+            ease: schedule.ease,
+            interval: schedule.interval,
+            repetitions: schedule.repetitions,
+            due: schedule.due,
+            reviewed_at: now,
+            ..card
+        }));
+    }
+
+    /// # Usage
+    /// Rebuilds LinkTable from the source of every note.
+    pub async fn relink_notes(&self) -> Result<()> {
+        sqlx::query("DELETE FROM LinkTable").execute(&self.pool).await?;
+        let rows = sqlx::query_as::<_, (i64, String)>("SELECT rowid, title FROM NoteTable")
+            .fetch_all(&self.pool)
+            .await?;
+
+        return self.relink(&rows).await;
+    }
+
+    /// # Usage
+    /// Returns the notes linking to or including the note titled
+    /// `title`, ignoring case, ordered by title.
+    pub async fn backlinks(&self, title: &str) -> Result<Vec<Note>> {
+        let notes = sqlx::query_as::<_, Note>(
+            "
+            SELECT
+                NoteTable.title, NoteTable.author, NoteTable.source, NoteTable.pub_date
+            FROM
+                NoteTable
+                JOIN LinkTable ON LinkTable.source = NoteTable.title
+            WHERE
+                LinkTable.target = ?
+            ORDER BY
+                NoteTable.title
+        ",
+        )
+        .bind(title)
+        .fetch_all(&self.pool)
+        .await?;
+
+        return Ok(notes);
+    }
+
+    /// # Usage
+    /// Returns every note and reference, for compiling
+    /// notes which link to others or cite references.
+    pub async fn library(&self) -> Result<Library> {
+        let notes = sqlx::query_as::<_, (String, String)>("SELECT title, source FROM NoteTable")
+            .fetch_all(&self.pool)
+            .await?;
+        let references = self.references().await?;
+
+        return Ok(Library::new(notes).with_references(references.into_iter().map(Reference::work)));
+    }
+
+    /// # Usage
+    /// Returns every reference, ordered by key.
+    pub async fn references(&self) -> Result<Vec<Reference>> {
+        let references = sqlx::query_as::<_, Reference>(
+            "
+            SELECT
+                key, author, title, year, publisher, url
+            FROM
+                ReferenceTable
+            ORDER BY
+                key
+        ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        return Ok(references);
+    }
+
+    /// # Usage
+    /// Adds a reference, replacing any with the same key.
+    pub async fn put_reference(&self, reference: &Reference) -> Result<()> {
+        sqlx::query(
+            "
+            INSERT OR REPLACE INTO ReferenceTable
+                (key, author, title, year, publisher, url)
+            VALUES
+                (?, ?, ?, ?, ?, ?)
+        ",
+        )
+        .bind(&reference.key)
+        .bind(&reference.author)
+        .bind(&reference.title)
+        .bind(reference.year)
+        .bind(&reference.publisher)
+        .bind(&reference.url)
+        .execute(&self.pool)
+        .await?;
+
+        return self.invalidate::<Reference>().await;
+    }
+
+    /// # Usage
+    /// Marks the cached HTML of notes which refer to rows of `T` as
+    /// stale, since those rows may have changed: notes with wiki links
+    /// or includes for notes, and notes with citations for references.
+    /// Includes are always marked, as the included note may cite.
+    async fn invalidate<T: Table>(&self) -> Result<()> {
+        let condition = match T::name() {
+            x if x == Note::name() => "source LIKE '%[[%' OR source LIKE '%@include(%'",
+            x if x == Reference::name() => "source LIKE '%[@%' OR source LIKE '%@include(%'",
+            _ => return Ok(()),
+        };
+        sqlx::query(&format!("UPDATE NoteTable SET source_hash = '' WHERE {}", condition))
+            .execute(&self.pool)
+            .await?;
+
+        return Ok(());
+    }
+
+    /// # Usage
+    /// Returns the HTML and outline of a note, recompiling it first if
+    /// its cache is stale. Notes which no longer compile are served the
+    /// HTML last cached for them, and those which never compiled are
+    /// [rendered](md::render) despite their errors, so readers can
+    /// still see them.
+    pub async fn rendered(&self, cached: &CachedNote) -> Result<(String, Vec<Heading>)> {
+        let note = &cached.note;
+        if cached.stale() {
+            match md::compile(note.title(), note.source(), &self.library().await?) {
+                Ok(compiled) => {
+                    self.cache(note, &compiled).await?;
+                    return Ok((compiled.html.as_str().to_string(), compiled.outline));
                 }
-            };
+                Err(x) => {
+                    log::warn!("Failed to compile note {}:\n{}", note.title(), x);
+                    self.failed(note).await?;
+                }
+            }
+        }
+        if cached.html.is_empty() {
+            let compiled = md::render(note.title(), note.source(), &self.library().await?);
+            return Ok((compiled.html.as_str().to_string(), compiled.outline));
         }
 
-        query.execute(&mut conn).await?;
+        return Ok((cached.html.clone(), cached.outline()?));
+    }
+
+    /// # Usage
+    /// Recompiles every note, for when the compiler changes. Returns
+    /// the number of notes compiled, and the diagnostics of the notes
+    /// which failed to compile by title. Those keep their old HTML.
+    pub async fn recompile_notes(&self) -> Result<(usize, HashMap<String, Diagnostics>)> {
+        let notes = sqlx::query_as::<_, Note>(
+            "
+            SELECT
+                title, author, source, pub_date
+            FROM
+                NoteTable
+        ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let library = self.library().await?;
+        let mut failed = HashMap::new();
+        for note in &notes {
+            match md::compile(note.title(), note.source(), &library) {
+                Ok(compiled) => self.cache(note, &compiled).await?,
+                Err(diagnostics) => {
+                    self.failed(note).await?;
+                    failed.insert(note.title.clone(), diagnostics);
+                }
+            }
+        }
+
+        return Ok((notes.len() - failed.len(), failed));
+    }
+
+    /// # Usage
+    /// Records that the source of a note failed to compile, keeping
+    /// its last HTML. It isn't compiled again until the source, the
+    /// compiler or the notes and references it uses change.
+    async fn failed(&self, note: &Note) -> Result<()> {
+        sqlx::query("UPDATE NoteTable SET source_hash = ? WHERE title = ?")
+            .bind(source_hash(note.source()))
+            .bind(note.title())
+            .execute(&self.pool)
+            .await?;
 
         return Ok(());
     }
+
+    /// # Usage
+    /// Writes the HTML and outline of a compiled note to NoteTable,
+    /// the answers of its exercises to ExerciseTable and its flashcards
+    /// to FlashcardTable.
+    async fn cache(&self, note: &Note, compiled: &Compiled) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "UPDATE NoteTable SET html = ?, outline = ?, source_hash = ? WHERE title = ?",
+        )
+        .bind(compiled.html.as_str())
+        .bind(serde_json::to_string(&compiled.outline)?)
+        .bind(source_hash(note.source()))
+        .bind(note.title())
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query("DELETE FROM ExerciseTable WHERE note = ?")
+            .bind(note.title())
+            .execute(&mut tx)
+            .await?;
+        for exercise in &compiled.exercises {
+            sqlx::query(
+                "INSERT INTO ExerciseTable (id, note, section, expected, tolerance) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(&exercise.id)
+            .bind(note.title())
+            .bind(&exercise.section)
+            .bind(&exercise.answer.expected)
+            .bind(exercise.answer.tolerance)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        sqlx::query("DELETE FROM FlashcardTable WHERE note = ?")
+            .bind(note.title())
+            .execute(&mut tx)
+            .await?;
+        for (position, card) in compiled.flashcards.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO FlashcardTable (note, position, label, front, back) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(note.title())
+            .bind(position as i64)
+            .bind(&card.label)
+            .bind(&card.front)
+            .bind(&card.back)
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filter(value: Value) -> TableFilter {
+        return serde_json::from_value(value).unwrap();
+    }
+
+    fn invalid(value: Value) -> bool {
+        return serde_json::from_value::<TableFilter>(value).is_err();
+    }
+
+    /// The values bound for a filter, in order, as a JSON array.
+    fn values(filter: &TableFilter) -> Value {
+        return filter
+            .conditions()
+            .iter()
+            .flat_map(|x| x.values())
+            .cloned()
+            .collect();
+    }
+
+    #[test]
+    fn gated() {
+        // `AND` binds tighter than `OR`, wherever it is
+        let gated = filter(json!([
+            [["title", "=", "A"], "OR"],
+            [["author", "=", "B"], "AND"],
+            [["pub_date", ">", 0], "OR"],
+            [["title", "=", "C"], ""]
+        ]));
+        assert_eq!(
+            gated.expr(),
+            "(title = ? OR (author = ? AND pub_date > ?) OR title = ?)"
+        );
+        assert_eq!(values(&gated), json!(["A", "B", 0, "C"]));
+
+        let single = filter(json!([[["title", "=", "A"], ""]]));
+        assert_eq!(single.sql(), "WHERE title = ?");
+        assert_eq!(TableFilter::default().sql(), "");
+        assert!(invalid(json!([[["title", "=", "A"], "AND"]])));
+        assert!(invalid(json!([
+            [["title", "=", "A"], "XOR"],
+            [["title", "=", "B"], ""]
+        ])));
+    }
+
+    #[test]
+    fn grouped() {
+        let not = filter(json!({ "not": ["title", "=", "A"] }));
+        assert_eq!(not.expr(), "NOT (title = ?)");
+        let not = filter(json!({ "not": { "or": [["title", "=", "A"], ["title", "=", "B"]] } }));
+        assert_eq!(not.expr(), "NOT (title = ? OR title = ?)");
+        assert_eq!(filter(json!({ "and": [] })).expr(), "1");
+        assert_eq!(filter(json!({ "or": [] })).expr(), "0");
+
+        // Placeholders are bound depth first, left to right
+        let nested = filter(json!({ "and": [
+            { "or": [["title", "IN", ["A", "B"]], ["author", "LIKE", "C%"]] },
+            { "not": ["pub_date", "BETWEEN", [1, 2]] },
+            ["author", "IS NOT NULL"],
+            ["pub_date", "!=", 3]
+        ] }));
+        assert_eq!(
+            nested.expr(),
+            "((title IN (?, ?) OR author LIKE ? ESCAPE '\\') AND NOT (pub_date BETWEEN ? AND ?) \
+             AND author IS NOT NULL AND pub_date != ?)"
+        );
+        assert_eq!(values(&nested), json!(["A", "B", "C%", 1, 2, 3]));
+        assert_eq!(
+            nested.expr().matches('?').count(),
+            nested.conditions().iter().map(|x| x.values().len()).sum::<usize>()
+        );
+        assert!(nested.valid::<Note>());
+
+        // Written back out in the same form
+        let written = serde_json::to_value(&nested).unwrap();
+        assert_eq!(filter(written).expr(), nested.expr());
+    }
+
+    #[test]
+    fn unknown_keys() {
+        // Read as just the `and`, this would match more than it says
+        let extra = json!({ "and": [["author", "=", "X"]], "not": ["title", "=", "Keep"] });
+        assert!(invalid(extra));
+        assert!(invalid(json!({ "and": [], "limit": 1 })));
+        assert!(invalid(json!({})));
+    }
 }
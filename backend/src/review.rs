@@ -0,0 +1,148 @@
+//! # Usage
+//! Spaced repetition. Each user reviews the sections of the notes
+//! they have opened as cards, which are scheduled by SM-2: after each
+//! review the user grades how well they recalled the section from
+//! 0 (not at all) to 5 (perfectly). Grades of 3 and above push the
+//! card further out, by a factor that grows with easy reviews and
+//! shrinks with hard ones; lower grades start the card over.
+//!
+//! Answering an exercise counts as a review of its section. Only
+//! cards which are due are rescheduled, so answering again doesn't
+//! push a card further out.
+
+/// The lowest ease a card may have, so that cards
+/// which were once hard aren't reviewed forever.
+const MIN_EASE: f64 = 1.3;
+
+/// The ease of a card which has never been reviewed.
+pub const EASE: f64 = 2.5;
+
+/// The longest interval, in days, so that due dates stay in range.
+const MAX_INTERVAL: i64 = 36500;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// # Usage
+/// When a card is next due. Intervals are in days
+/// and times in seconds since the epoch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Schedule {
+    pub ease: f64,
+    pub interval: i64,
+    /// Reviews in a row graded 3 or above.
+    pub repetitions: i64,
+    pub due: i64,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        return Schedule {
+            ease: EASE,
+            interval: 0,
+            repetitions: 0,
+            due: 0,
+        };
+    }
+}
+
+impl Schedule {
+    /// # Usage
+    /// Whether the card should be reviewed at `now`.
+    pub fn is_due(&self, now: i64) -> bool {
+        return self.due <= now;
+    }
+
+    /// # Usage
+    /// Schedules the next review after one graded `quality`, out
+    /// of 5, at `now`. A card which isn't due keeps its schedule.
+    /// Fails if the grade is greater than 5.
+    pub fn grade(&self, quality: u8, now: i64) -> Option<Schedule> {
+        if quality > 5 {
+            return None;
+        }
+        if !self.is_due(now) {
+            return Some(*self);
+        }
+
+        let (repetitions, interval) = match quality {
+            0..=2 => (0, 1),
+            _ => (
+                self.repetitions + 1,
+                match self.repetitions {
+                    0 => 1,
+                    1 => 6,
+                    _ => ((self.interval as f64 * self.ease).round() as i64).min(MAX_INTERVAL),
+                },
+            ),
+        };
+        let miss = (5 - quality) as f64;
+        let ease = (self.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(MIN_EASE);
+
+        return Some(Schedule {
+            ease,
+            interval,
+            repetitions,
+            due: now.saturating_add(interval.saturating_mul(DAY)),
+        });
+    }
+
+    /// # Usage
+    /// The grade an answer to an exercise counts as.
+    pub fn quality(correct: bool) -> u8 {
+        return match correct {
+            true => 4,
+            false => 1,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedule() {
+        let mut schedule = Schedule::default();
+        let mut intervals = Vec::new();
+        for quality in [5, 4, 4, 3] {
+            schedule = schedule.grade(quality, schedule.due).unwrap();
+            intervals.push(schedule.interval);
+        }
+        assert_eq!(intervals, [1, 6, 16, 42]);
+        assert_eq!(schedule.due, (1 + 6 + 16 + 42) * DAY);
+        assert!((schedule.ease - 2.46).abs() < 1e-9);
+
+        // Forgetting starts the card over, and makes it harder
+        let forgotten = schedule.grade(1, schedule.due).unwrap();
+        assert_eq!((forgotten.repetitions, forgotten.interval), (0, 1));
+        assert!(forgotten.ease < schedule.ease);
+
+        let mut hard = Schedule::default();
+        for _ in 0..10 {
+            hard = hard.grade(0, hard.due).unwrap();
+        }
+        assert_eq!(hard.ease, MIN_EASE);
+        assert!(Schedule::default().grade(6, 0).is_none());
+    }
+
+    #[test]
+    fn due() {
+        // Cards which aren't due are left as they are
+        let schedule = Schedule::default().grade(4, 0).unwrap();
+        assert_eq!(schedule.grade(4, DAY - 1), Some(schedule));
+        assert_eq!(schedule.grade(0, 0), Some(schedule));
+        assert_ne!(schedule.grade(4, DAY), Some(schedule));
+
+        // However long a card is remembered, it stays in range
+        let mut schedule = Schedule::default();
+        for _ in 0..100 {
+            schedule = schedule.grade(5, schedule.due).unwrap();
+        }
+        assert_eq!(schedule.interval, MAX_INTERVAL);
+        let far = Schedule {
+            due: i64::MAX - DAY,
+            ..schedule
+        };
+        assert_eq!(far.grade(5, i64::MAX - DAY).unwrap().due, i64::MAX);
+    }
+}
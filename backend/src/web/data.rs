@@ -1,50 +1,575 @@
 use crate::{
-    auth::{RequireAuth, Role},
-    model::{ModelController, Note, SqliteType, Table},
+    auth::{Auth, RequireAuth, Role},
+    model::{ModelController, Note, Reference, SqliteType, Table},
 };
-use axum::{routing, Json, Router};
+use axum::{http::StatusCode, routing, Json, Router};
 use std::{collections::HashMap, sync::Arc};
 
 pub fn routes(mc: Arc<ModelController>) -> Router {
     return Router::new()
         .route("/tables", routing::get(tables))
         .route_layer(RequireAuth::login_with_role(Role::Admin..))
-        .nest("/notes", notes::route(mc.clone()));
+        .nest("/notes", notes::route(mc.clone()))
+        .nest("/references", references::route(mc.clone()))
+        .nest("/math", math::route())
+        .nest("/exercises", exercises::route(mc.clone()))
+        .nest("/progress", progress::route(mc.clone()))
+        .nest("/review", review::route(mc.clone()));
 }
 
 /// # Usage
 /// Returns a list of table fields for
 /// all available tables.
 async fn tables() -> Json<Vec<Arc<HashMap<String, SqliteType>>>> {
-    let mut out = Vec::new();
-    out.push(Note::fields());
-    return Json(out);
+    return Json(vec![Note::fields(), Reference::fields()]);
+}
+
+/// # Usage
+/// The id of the logged in user, for routes
+/// whose layers guarantee there is one.
+fn user_id(auth: &Auth) -> Result<i64, StatusCode> {
+    return auth
+        .current_user
+        .as_ref()
+        .map(|x| x.id)
+        .ok_or(StatusCode::UNAUTHORIZED);
+}
+
+/// Routes for working with expressions
+mod math {
+    use crate::math;
+    use axum::{http::StatusCode, routing, Json, Router};
+    use log::info;
+    use serde::{Deserialize, Serialize};
+
+    /// # Usage
+    /// A student's answer along with the expected one.
+    #[derive(Deserialize)]
+    struct Check {
+        answer: String,
+        expected: String,
+    }
+
+    /// # Usage
+    /// Whether an answer is correct, and if it couldn't be
+    /// read, why not.
+    #[derive(Serialize)]
+    pub(super) struct Checked {
+        pub(super) correct: bool,
+        pub(super) error: Option<String>,
+    }
+
+    /// The longest expression, in bytes, these routes will read.
+    pub(super) const MAX_LENGTH: usize = 1000;
+
+    pub fn route() -> Router {
+        Router::new().route("/check", routing::post(check))
+    }
+
+    /// # Usage
+    /// Parses a submitted answer, or returns why it couldn't be
+    /// read. Answers longer than [MAX_LENGTH] aren't parsed.
+    pub(super) fn answer(text: &str) -> Result<math::Expr, Checked> {
+        if text.len() > MAX_LENGTH {
+            return Err(Checked {
+                correct: false,
+                error: Some(format!("Answers are limited to {} characters", MAX_LENGTH)),
+            });
+        }
+        return math::parse(text).map_err(|(offset, message)| Checked {
+            correct: false,
+            error: Some(format!("{} at column {}", message, offset + 1)),
+        });
+    }
+
+    /// # Usage
+    /// Checks whether an answer is equivalent to the expected
+    /// expression. Fails if the expected expression is invalid
+    /// or longer than [MAX_LENGTH].
+    async fn check(Json(check): Json<Check>) -> Result<Json<Checked>, StatusCode> {
+        info!("{:<12} -> math::check", "ROUTE");
+        if check.expected.len() > MAX_LENGTH {
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        let expected =
+            math::parse(&check.expected).map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+
+        return Ok(Json(match answer(&check.answer) {
+            Ok(answer) => Checked {
+                correct: answer.equivalent(&expected),
+                error: None,
+            },
+            Err(checked) => checked,
+        }));
+    }
+}
+
+/// Routes for the exercises in notes
+mod exercises {
+    use super::math::{answer, Checked};
+    use crate::{auth::Auth, math, model::ModelController, review::Schedule};
+    use axum::{
+        extract::{Path, State},
+        http::StatusCode,
+        routing, Json, Router,
+    };
+    use log::{info, warn};
+    use serde::Deserialize;
+    use std::sync::Arc;
+
+    /// # Usage
+    /// A student's answer to an exercise in the note titled `note`.
+    #[derive(Deserialize)]
+    struct Submission {
+        note: String,
+        answer: String,
+    }
+
+    pub fn route(mc: Arc<ModelController>) -> Router {
+        Router::new()
+            .route("/:id/check", routing::post(check))
+            .with_state(mc)
+    }
+
+    /// # Usage
+    /// Grades an answer to the exercise with the given id in a note
+    /// by comparing it with the hidden answer at sample points.
+    /// For logged in users, this also reviews the card of the
+    /// section the exercise is in. Fails with `404 Not Found`
+    /// if the note has no exercise with the id.
+    async fn check(
+        auth: Auth,
+        State(mc): State<Arc<ModelController>>,
+        Path(id): Path<String>,
+        Json(submission): Json<Submission>,
+    ) -> Result<Json<Checked>, StatusCode> {
+        info!("{:<12} -> exercises::check", "ROUTE");
+        let (note, exercise) = mc
+            .exercise(&submission.note, &id)
+            .await
+            .map_err(|x| {
+                warn!("{}", x);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        let expected = math::parse(&exercise.answer.expected)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        let submitted = match answer(&submission.answer) {
+            Ok(submitted) => submitted,
+            Err(checked) => return Ok(Json(checked)),
+        };
+        let correct = submitted.within(&expected, exercise.answer.tolerance);
+
+        // The answer is still graded if the review can't be recorded
+        if let Some(user) = &auth.current_user {
+            let quality = Schedule::quality(correct);
+            let reviewed = match mc.seed(user.id, &note).await {
+                Ok(()) => mc.grade(user.id, &note, &exercise.section, quality).await,
+                Err(x) => Err(x),
+            };
+            if let Err(x) = reviewed {
+                warn!("Error occurred while reviewing an exercise: {}", x);
+            }
+        }
+
+        return Ok(Json(Checked {
+            correct,
+            error: None,
+        }));
+    }
+}
+
+/// Routes for tracking how far users have read
+mod progress {
+    use super::user_id;
+    use crate::{
+        auth::{Auth, RequireAuth, Role},
+        model::{ModelController, Progress, Read},
+    };
+    use axum::{
+        extract::{Path, State},
+        http::StatusCode,
+        routing, Json, Router,
+    };
+    use log::{info, warn};
+    use serde::Serialize;
+    use std::sync::Arc;
+
+    /// # Usage
+    /// A user's progress over every note.
+    #[derive(Serialize)]
+    struct Overview {
+        /// The number of notes there are to read.
+        notes: i64,
+        opened: usize,
+        completed: usize,
+        progress: Vec<Progress>,
+    }
+
+    pub fn route(mc: Arc<ModelController>) -> Router {
+        Router::new()
+            .route("/", routing::get(overview))
+            .route("/:title", routing::get(get).post(read))
+            .route_layer(RequireAuth::login_with_role(Role::User..))
+            .with_state(mc)
+    }
+
+    async fn overview(
+        auth: Auth,
+        State(mc): State<Arc<ModelController>>,
+    ) -> Result<Json<Overview>, StatusCode> {
+        info!("{:<12} -> progress::overview", "ROUTE");
+        let user_id = user_id(&auth)?;
+        let (notes, progress) = tokio::try_join!(mc.count_notes(), mc.progress_all(user_id))
+            .map_err(|x| {
+                warn!("{}", x);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        return Ok(Json(Overview {
+            notes,
+            opened: progress.len(),
+            completed: progress.iter().filter(|x| x.completed).count(),
+            progress,
+        }));
+    }
+
+    /// # Usage
+    /// Returns the user's progress on a note, failing with
+    /// `404 Not Found` if they have never opened it.
+    async fn get(
+        auth: Auth,
+        State(mc): State<Arc<ModelController>>,
+        Path(title): Path<String>,
+    ) -> Result<Json<Progress>, StatusCode> {
+        info!("{:<12} -> progress::get", "ROUTE");
+        let progress = mc
+            .progress(user_id(&auth)?, &title)
+            .await
+            .map_err(|x| {
+                warn!("{}", x);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        return Ok(Json(progress));
+    }
+
+    /// # Usage
+    /// Records progress on a note, such as opening it, scrolling
+    /// or marking it complete. Fails with `404 Not Found` if there
+    /// is no such note, or `422 Unprocessable Entity` if `scroll`
+    /// isn't between 0 and 1.
+    async fn read(
+        auth: Auth,
+        State(mc): State<Arc<ModelController>>,
+        Path(title): Path<String>,
+        Json(read): Json<Read>,
+    ) -> Result<Json<Progress>, StatusCode> {
+        info!("{:<12} -> progress::read", "ROUTE");
+        if read.scroll.is_some_and(|x| !(0.0..=1.0).contains(&x)) {
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        let progress = mc
+            .read(user_id(&auth)?, &title, &read)
+            .await
+            .map_err(|x| {
+                warn!("Error occurred while recording progress: {}", x);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        return Ok(Json(progress));
+    }
+}
+
+/// Routes for reviewing notes with spaced repetition
+mod review {
+    use super::user_id;
+    use crate::{
+        auth::{Auth, RequireAuth, Role},
+        model::{Card, ModelController},
+    };
+    use axum::{
+        extract::{Query, State},
+        http::StatusCode,
+        routing, Json, Router,
+    };
+    use log::{info, warn};
+    use serde::{Deserialize, Serialize};
+    use std::sync::Arc;
+
+    #[derive(Deserialize)]
+    struct Next {
+        /// The most cards to return, 20 by default.
+        limit: Option<i64>,
+    }
+
+    /// # Usage
+    /// The cards a user should review now.
+    #[derive(Serialize)]
+    struct Queue {
+        /// How many cards are due, including those not returned.
+        due: i64,
+        cards: Vec<Card>,
+    }
+
+    /// # Usage
+    /// How well a user recalled a section, from 0 to 5.
+    #[derive(Deserialize)]
+    struct Grade {
+        note: String,
+        section: String,
+        quality: u8,
+    }
+
+    pub fn route(mc: Arc<ModelController>) -> Router {
+        Router::new()
+            .route("/next", routing::get(next))
+            .route("/grade", routing::post(grade))
+            .route_layer(RequireAuth::login_with_role(Role::User..))
+            .with_state(mc)
+    }
+
+    /// # Usage
+    /// Returns the cards due for review, most overdue first,
+    /// from every note the user has opened.
+    async fn next(
+        auth: Auth,
+        State(mc): State<Arc<ModelController>>,
+        Query(next): Query<Next>,
+    ) -> Result<Json<Queue>, StatusCode> {
+        info!("{:<12} -> review::next", "ROUTE");
+        let (due, cards) = mc
+            .queue(user_id(&auth)?, next.limit.unwrap_or(20).max(0))
+            .await
+            .map_err(|x| {
+                warn!("{}", x);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        return Ok(Json(Queue { due, cards }));
+    }
+
+    /// # Usage
+    /// Schedules a card after a review, returning it. Cards which
+    /// aren't due yet are returned without being rescheduled. Fails with
+    /// `422 Unprocessable Entity` if the quality is greater than 5,
+    /// or `404 Not Found` if the user has no such card.
+    async fn grade(
+        auth: Auth,
+        State(mc): State<Arc<ModelController>>,
+        Json(grade): Json<Grade>,
+    ) -> Result<Json<Card>, StatusCode> {
+        info!("{:<12} -> review::grade", "ROUTE");
+        let user_id = user_id(&auth)?;
+        if grade.quality > 5 {
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        let card = mc
+            .grade(user_id, &grade.note, &grade.section, grade.quality)
+            .await
+            .map_err(|x| {
+                warn!("Error occurred while grading a card: {}", x);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        return Ok(Json(card));
+    }
+}
+
+/// Routes for the references notes cite
+mod references {
+    use crate::{
+        auth::{RequireAuth, Role},
+        model::{ModelController, Reference, Updater},
+    };
+    use axum::{
+        extract::State,
+        http::StatusCode,
+        response::{IntoResponse, Response},
+        routing, Json, Router,
+    };
+    use log::{info, warn};
+    use std::sync::Arc;
+
+    pub fn route(mc: Arc<ModelController>) -> Router {
+        Router::new()
+            .route("/", routing::put(put).patch(patch))
+            .route_layer(RequireAuth::login_with_role(Role::Admin..))
+            .route("/get", routing::get(all))
+            .with_state(mc)
+    }
+
+    async fn all(
+        State(mc): State<Arc<ModelController>>,
+    ) -> Result<Json<Vec<Reference>>, StatusCode> {
+        info!("{:<12} -> references::all", "ROUTE");
+        let references = mc.references().await.map_err(|x| {
+            warn!("{}", x);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        return Ok(Json(references));
+    }
+
+    /// # Usage
+    /// Adds a reference, replacing any with the same key.
+    async fn put(
+        State(mc): State<Arc<ModelController>>,
+        Json(reference): Json<Reference>,
+    ) -> Result<StatusCode, StatusCode> {
+        info!("{:<12} -> references::put", "ROUTE");
+        mc.put_reference(&reference).await.map_err(|x| {
+            warn!("Error occurred while adding a reference: {}", x);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        return Ok(StatusCode::OK);
+    }
+
+    /// # Usage
+    /// Updates references. Fails with `422 Unprocessable Entity`
+    /// and an error for each field which can't be set.
+    async fn patch(
+        State(mc): State<Arc<ModelController>>,
+        Json(updater): Json<Updater>,
+    ) -> Result<StatusCode, Response> {
+        info!("{:<12} -> references::update", "ROUTE");
+        if let Err(errors) = updater.check::<Reference>() {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(errors)).into_response());
+        }
+
+        mc.update::<Reference>(&updater).await.map_err(|x| {
+            warn!("Error occurred while updating a reference: {}", x);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+
+        return Ok(StatusCode::OK);
+    }
 }
 
 /// Routes for notes
 mod notes {
     use crate::{
         auth::{RequireAuth, Role},
-        model::{ModelController, Note, Updater},
+        md::{self, Diagnostics, Heading},
+        model::{
+            CachedNote, Cursor, Flashcard, ModelController, Note, Order, Start, Summary, Table,
+            TableFilter, Updater,
+        },
+    };
+    use axum::{
+        extract::Path,
+        extract::{Query, State},
+        http::{header, StatusCode},
+        response::{IntoResponse, Response},
+        routing, Json, Router,
     };
-    use axum::{extract::Path, extract::State, http::StatusCode, routing, Json, Router};
     use log::{info, warn};
+    use serde::{Deserialize, Serialize};
+    use serde_json::{Map, Value};
     use sqlx::FromRow;
-    use std::sync::Arc;
+    use std::{collections::HashMap, sync::Arc};
+
+    /// # Usage
+    /// A note along with its source compiled to HTML, and the
+    /// outline of its headings. The source itself is left out.
+    #[derive(Serialize)]
+    struct RenderedNote {
+        #[serde(flatten)]
+        note: Summary,
+        html: String,
+        outline: Vec<Heading>,
+    }
+
+    /// # Usage
+    /// The result of recompiling every note.
+    #[derive(Serialize)]
+    struct Recompiled {
+        compiled: usize,
+        /// Diagnostics of the notes which failed, by title.
+        failed: HashMap<String, Diagnostics>,
+    }
+
+    /// # Usage
+    /// The notes to delete. An empty filter matches every
+    /// note, so it is refused unless `all` is set.
+    #[derive(Deserialize)]
+    struct Deletion {
+        at: TableFilter,
+        #[serde(default)]
+        all: bool,
+    }
+
+    /// # Usage
+    /// The query of a page of notes, such as
+    /// `?limit=20&order_by=pub_date&descending=true&fields=title,author`.
+    #[derive(Deserialize)]
+    struct Listing {
+        limit: Option<u32>,
+        offset: Option<u32>,
+        cursor: Option<String>,
+        order_by: Option<String>,
+        #[serde(default)]
+        descending: bool,
+        fields: Option<String>,
+    }
+
+    /// # Usage
+    /// A page of notes, with only the fields asked for.
+    #[derive(Serialize)]
+    struct NotePage {
+        notes: Vec<Map<String, Value>>,
+        /// The number of notes, over every page.
+        total: i64,
+        /// The cursor to get the next page with, if there is one.
+        next: Option<String>,
+    }
+
+    /// # Usage
+    /// Which notes to return, as for [ModelController::select].
+    /// Every note matches an empty or missing filter.
+    #[derive(Deserialize)]
+    struct Selection {
+        #[serde(default)]
+        at: TableFilter,
+        order: Option<Order>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    }
+
+    /// # Usage
+    /// The number of notes deleted.
+    #[derive(Serialize)]
+    struct Deleted {
+        deleted: u64,
+    }
 
     pub fn route(mc: Arc<ModelController>) -> Router {
         Router::new()
+            .route("/", routing::post(create).delete(delete))
             .route("/patch", routing::patch(patch))
+            .route("/recompile", routing::post(recompile))
+            .route("/source/:title", routing::get(source))
             .route_layer(RequireAuth::login_with_role(Role::Admin..))
             .route("/get/:title", routing::get(get))
             .route("/get", routing::get(all))
+            .route("/query", routing::post(query))
+            .route("/backlinks/:title", routing::get(backlinks))
+            .route("/:title/cards", routing::get(cards))
+            .route("/:title/cards.tsv", routing::get(export))
             .with_state(mc)
     }
 
     async fn get(
         State(mc): State<Arc<ModelController>>,
         Path(title): Path<String>,
-    ) -> Result<Json<Note>, StatusCode> {
+    ) -> Result<Json<RenderedNote>, StatusCode> {
         info!("{:<12} -> notes::get", "ROUTE");
         let mut conn = mc
             .pool()
@@ -55,7 +580,7 @@ mod notes {
         let note = sqlx::query(
             "
             SELECT 
-                title, author, source, pub_date 
+                title, author, source, pub_date, html, outline, source_hash 
             FROM 
                 NoteTable
             WHERE 
@@ -67,50 +592,373 @@ mod notes {
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-        return Ok(Json(
-            Note::from_row(&note).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
-        ));
-    }
-
-    async fn all(State(mc): State<Arc<ModelController>>) -> Result<Json<Vec<Note>>, StatusCode> {
-        info!("{:<12} -> notes::all", "ROUTE");
+        let cached = CachedNote::from_row(&note).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let (html, outline) = mc.rendered(&cached).await.map_err(|x| {
+            warn!("{}", x);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
 
-        let mut conn = mc
-            .pool()
-            .acquire()
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        return Ok(Json(RenderedNote {
+            note: cached.note.into(),
+            html,
+            outline,
+        }));
+    }
 
-        let notes = sqlx::query(
+    /// # Usage
+    /// Returns a note along with its source, for editing. Fails
+    /// with `404 Not Found` if there is no such note.
+    async fn source(
+        State(mc): State<Arc<ModelController>>,
+        Path(title): Path<String>,
+    ) -> Result<Json<Note>, StatusCode> {
+        info!("{:<12} -> notes::source", "ROUTE");
+        let note = sqlx::query_as::<_, Note>(
             "
-            SELECT 
-                title, author, source, pub_date 
-            FROM 
+            SELECT
+                title, author, source, pub_date
+            FROM
                 NoteTable
+            WHERE
+                title = ? COLLATE NOCASE
         ",
         )
-        .fetch_all(&mut conn)
+        .bind(title)
+        .fetch_optional(mc.pool())
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|x| {
+            warn!("{}", x);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+        return Ok(Json(note));
+    }
+
+    /// # Usage
+    /// Lists notes a page at a time. Pages start either `offset` notes
+    /// in or at the `cursor` returned with the previous page, and hold
+    /// at most `limit` notes sorted by the field `order_by`. Only the
+    /// comma separated `fields` are returned, if given, and never the
+    /// source. Fails with `422 Unprocessable Entity` if a field isn't
+    /// one of a note's [public](Summary::public) ones, the cursor isn't
+    /// from a page in the same order, or both an offset and a cursor
+    /// are given.
+    async fn all(
+        State(mc): State<Arc<ModelController>>,
+        Query(listing): Query<Listing>,
+    ) -> Result<Json<NotePage>, StatusCode> {
+        info!("{:<12} -> notes::all", "ROUTE");
+        let mut fields = match &listing.fields {
+            Some(fields) => fields
+                .split(',')
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<_>>(),
+            None => Note::fields()
+                .keys()
+                .filter(|x| Summary::public(x))
+                .cloned()
+                .collect(),
+        };
+        fields.sort();
+        fields.dedup();
+        let order = listing
+            .order_by
+            .map(|by| Order::new(by, listing.descending));
+        if !fields.iter().all(|x| Summary::public(x))
+            || !order.as_ref().is_none_or(|x| Summary::public(x.by()))
+        {
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
 
-        return Ok(Json(
-            notes
-                .iter()
-                .map(|r| Note::from_row(r).expect("Failed to map note from row"))
-                .collect::<Vec<Note>>(),
-        ));
+        let start = match (listing.offset, &listing.cursor) {
+            (Some(_), Some(_)) => return Err(StatusCode::UNPROCESSABLE_ENTITY),
+            (_, Some(cursor)) => {
+                Start::After(Cursor::parse(cursor).ok_or(StatusCode::UNPROCESSABLE_ENTITY)?)
+            }
+            (offset, None) => Start::Offset(offset.unwrap_or(0)),
+        };
+
+        let page = mc
+            .page::<Note>(
+                &TableFilter::default(),
+                &fields,
+                order.as_ref(),
+                listing.limit,
+                &start,
+            )
+            .await
+            .map_err(|x| {
+                warn!("{}", x);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+
+        return Ok(Json(NotePage {
+            notes: page.rows,
+            total: page.total,
+            next: page.next,
+        }));
     }
 
+    /// # Usage
+    /// Returns the notes matching a filter, such as those by an
+    /// author published after a date, without their sources. Fails
+    /// with `422 Unprocessable Entity` if the filter or order isn't
+    /// on [public](Summary::public) fields of notes.
+    async fn query(
+        State(mc): State<Arc<ModelController>>,
+        Json(selection): Json<Selection>,
+    ) -> Result<Json<Vec<Summary>>, StatusCode> {
+        info!("{:<12} -> notes::query", "ROUTE");
+        if !selection.at.fields().into_iter().all(Summary::public)
+            || !selection
+                .order
+                .as_ref()
+                .is_none_or(|x| Summary::public(x.by()))
+        {
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        let notes = mc
+            .select::<Note>(
+                &selection.at,
+                selection.order.as_ref(),
+                selection.limit,
+                selection.offset,
+            )
+            .await
+            .map_err(|x| {
+                warn!("{}", x);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+
+        return Ok(Json(notes.into_iter().map(Summary::from).collect()));
+    }
+
+    /// # Usage
+    /// Returns the notes which link to or include the note titled
+    /// `title`, without their sources. The note itself need not exist.
+    async fn backlinks(
+        State(mc): State<Arc<ModelController>>,
+        Path(title): Path<String>,
+    ) -> Result<Json<Vec<Summary>>, StatusCode> {
+        info!("{:<12} -> notes::backlinks", "ROUTE");
+        let notes = mc.backlinks(&title).await.map_err(|x| {
+            warn!("{}", x);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        return Ok(Json(notes.into_iter().map(Summary::from).collect()));
+    }
+
+    /// # Usage
+    /// Returns the flashcards of a note, taken from its definitions
+    /// and theorems. Fails with `404 Not Found` if there is no such note.
+    async fn cards(
+        State(mc): State<Arc<ModelController>>,
+        Path(title): Path<String>,
+    ) -> Result<Json<Vec<Flashcard>>, StatusCode> {
+        info!("{:<12} -> notes::cards", "ROUTE");
+        let cards = mc
+            .flashcards(&title)
+            .await
+            .map_err(|x| {
+                warn!("{}", x);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        return Ok(Json(cards));
+    }
+
+    /// # Usage
+    /// Downloads the flashcards of a note as a file Anki can
+    /// import, see [tsv]. Fails with `404 Not Found` if there
+    /// is no such note.
+    async fn export(
+        State(mc): State<Arc<ModelController>>,
+        Path(title): Path<String>,
+    ) -> Result<Response, StatusCode> {
+        info!("{:<12} -> notes::export", "ROUTE");
+        let cards = mc
+            .flashcards(&title)
+            .await
+            .map_err(|x| {
+                warn!("{}", x);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        let filename = title
+            .chars()
+            .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+                true => c,
+                false => '_',
+            })
+            .collect::<String>();
+        let headers = [
+            (
+                header::CONTENT_TYPE,
+                "text/tab-separated-values; charset=utf-8".to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.tsv\"", filename),
+            ),
+        ];
+        return Ok((headers, tsv(&cards)).into_response());
+    }
+
+    /// # Usage
+    /// Writes flashcards as tab separated values in the format Anki
+    /// imports: the front, the back, then the note as a tag. Fields
+    /// are quoted since the HTML may span lines, and the header tells
+    /// Anki how to read the rest.
+    fn tsv(cards: &[Flashcard]) -> String {
+        let field = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+        let mut out = String::from("#separator:tab\n#html:true\n#tags column:3\n");
+        for card in cards {
+            let tag = card.note.split_whitespace().collect::<Vec<_>>().join("_");
+            out.push_str(&format!(
+                "{}\t{}\t{}\n",
+                field(&card.front),
+                field(&card.back),
+                field(&tag)
+            ));
+        }
+        return out;
+    }
+
+    /// # Usage
+    /// Creates a note, returning it with `201 Created`. The source is
+    /// validated first as in [patch], failing with the diagnostics and
+    /// `422 Unprocessable Entity` if it has errors. Fails with
+    /// `409 Conflict` if a note has the title, ignoring case.
+    async fn create(
+        State(mc): State<Arc<ModelController>>,
+        Json(note): Json<Note>,
+    ) -> Result<(StatusCode, Json<Note>), Response> {
+        info!("{:<12} -> notes::create", "ROUTE");
+        if note.title().trim().is_empty() {
+            return Err(StatusCode::UNPROCESSABLE_ENTITY.into_response());
+        }
+
+        let library = mc.library().await.map_err(|x| {
+            warn!("{}", x);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+        let diagnostics = md::validate(note.title(), note.source(), &library);
+        if diagnostics.has_errors() {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(diagnostics)).into_response());
+        }
+
+        let row = serde_json::to_value(&note)
+            .and_then(serde_json::from_value::<HashMap<String, Value>>)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+        let inserted = mc.insert::<Note>(&row).await.map_err(|x| {
+            warn!("Error occurred while creating a note: {}", x);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+        if !inserted {
+            return Err(StatusCode::CONFLICT.into_response());
+        }
+
+        return Ok((StatusCode::CREATED, Json(note)));
+    }
+
+    /// # Usage
+    /// Deletes the notes matching a filter, along with their links,
+    /// exercises, flashcards and the progress of users on them. Fails
+    /// with `422 Unprocessable Entity` if the filter is empty and
+    /// `all` isn't set.
+    async fn delete(
+        State(mc): State<Arc<ModelController>>,
+        Json(deletion): Json<Deletion>,
+    ) -> Result<Json<Deleted>, StatusCode> {
+        info!("{:<12} -> notes::delete", "ROUTE");
+        if deletion.at.is_empty() && !deletion.all {
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        let deleted = mc.delete::<Note>(&deletion.at).await.map_err(|x| {
+            warn!("Error occurred while deleting notes: {}", x);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        return Ok(Json(Deleted { deleted }));
+    }
+
+    /// # Usage
+    /// Updates notes. Fields which can't be set, such as those given
+    /// values of the wrong type, fail with `422 Unprocessable Entity`
+    /// and an error for each. A new `source` is then validated as each
+    /// of the notes it is for: if it has errors nothing is saved and
+    /// the diagnostics are returned with `422 Unprocessable Entity`,
+    /// otherwise any warnings are returned alongside the update.
     async fn patch(
         State(mc): State<Arc<ModelController>>,
         Json(updater): Json<Updater>,
-    ) -> Result<(), StatusCode> {
+    ) -> Result<Json<Diagnostics>, Response> {
         info!("{:<12} -> notes::update", "ROUTE");
+        if let Err(errors) = updater.check::<Note>() {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(errors)).into_response());
+        }
+
+        let diagnostics = match updater.set.get("source") {
+            Some(source) => {
+                let source = source
+                    .as_str()
+                    .ok_or(StatusCode::UNPROCESSABLE_ENTITY.into_response())?;
+                let library = mc.library().await.map_err(|x| {
+                    warn!("{}", x);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                })?;
+                let mut titles = mc.titles(&updater).await.map_err(|x| {
+                    warn!("{}", x);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                })?;
+                if titles.is_empty() {
+                    titles.push(String::new());
+                }
+
+                let mut diagnostics = Diagnostics(Vec::new());
+                for title in &titles {
+                    diagnostics = md::validate(title, source, &library);
+                    if diagnostics.has_errors() {
+                        break;
+                    }
+                }
+                diagnostics
+            }
+            None => Diagnostics(Vec::new()),
+        };
+        if diagnostics.has_errors() {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(diagnostics)).into_response());
+        }
+
         mc.update::<Note>(&updater).await.map_err(|x| {
             warn!("Error occurred while updating a note: {}", x);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+
+        return Ok(Json(diagnostics));
+    }
+
+    /// # Usage
+    /// Recompiles the cached HTML of every note, for
+    /// when the compiler changes.
+    async fn recompile(
+        State(mc): State<Arc<ModelController>>,
+    ) -> Result<Json<Recompiled>, StatusCode> {
+        info!("{:<12} -> notes::recompile", "ROUTE");
+        let (compiled, failed) = mc.recompile_notes().await.map_err(|x| {
+            warn!("Error occurred while recompiling notes: {}", x);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
-        return Ok(());
+        return Ok(Json(Recompiled { compiled, failed }));
     }
 }
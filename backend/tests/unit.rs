@@ -1,14 +1,15 @@
 use anyhow::{anyhow, Result};
 use axum::http::{HeaderName, HeaderValue};
 use once_cell::sync::Lazy;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use reqwest::{
     cookie::{Cookie, CookieStore, Jar},
     Response,
 };
-use serde_json::json;
+use serde_json::{json, Value};
 use std::env;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 async fn fmt_response(response: Response) -> String {
     let mut output: String = String::new();
@@ -31,7 +32,7 @@ async fn fmt_response(response: Response) -> String {
             .collect::<String>()
     ));
     let response_cookies = response.cookies().collect::<Vec<Cookie>>();
-    if response_cookies.len() != 0 {
+    if !response_cookies.is_empty() {
         output.push_str(&format!(
             "RESPONSE COOKIES:\n{}",
             response_cookies
@@ -41,13 +42,12 @@ async fn fmt_response(response: Response) -> String {
         ));
     }
 
-    let cookies = COOKIE_JAR.cookies(&response.url());
+    let cookies = COOKIE_JAR.cookies(response.url());
 
-    if cookies.is_some() {
+    if let Some(cookies) = cookies {
         output.push_str(&format!(
             "CLIENT COOKIES:\n    {}\n",
             cookies
-                .unwrap()
                 .to_str()
                 .unwrap_or("Client cookies in non-ASCII format!")
         ));
@@ -196,7 +196,7 @@ async fn patch() -> Result<()> {
             {
                 "set":
                 {
-                    "source": "<h1>Usage</h1> <p>This is almost entirely for testing purposes</p>",
+                    "source": "# Usage\n\nThis is almost entirely for testing purposes",
                     "pub_date": 0
                 },
                 "at":
@@ -228,3 +228,607 @@ async fn patch() -> Result<()> {
 
     return Ok(());
 }
+
+/// Checks that a source with errors is refused along with its diagnostics.
+#[tokio::test]
+async fn patch_invalid() -> Result<()> {
+    let client = Client::builder()
+        .cookie_store(true)
+        .cookie_provider(COOKIE_JAR.clone())
+        .build()?;
+
+    client
+        .post(format!("{}/auth/login", BACKEND_URL.as_str()))
+        .json(&json!([TEST_ADMIN.0, TEST_ADMIN.1]))
+        .send()
+        .await?;
+
+    let response = client
+        .patch(format!("{}/data/notes/patch", BACKEND_URL.as_str()))
+        .json(&json!(
+            {
+                "set":
+                {
+                    "source": "<div>\n\n$\\frac{1$ and @missing()",
+                },
+                "at":
+                [
+                    [["title", "=", "Test"], ""]
+                ]
+            }
+        ))
+        .send()
+        .await?;
+
+    if response.status() != StatusCode::UNPROCESSABLE_ENTITY {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+
+    let diagnostics = response.json::<Value>().await?;
+    if diagnostics.as_array().is_none_or(|x| x.is_empty()) {
+        return Err(anyhow!("Unexpected diagnostics: {}", diagnostics));
+    }
+
+    // A note can't include itself, though its saved source doesn't yet
+    let response = client
+        .patch(format!("{}/data/notes/patch", BACKEND_URL.as_str()))
+        .json(&json!({ "set": { "source": "# Test\n\n@include(\"Test\")" }, "at": [[["title", "=", "Test"], ""]] }))
+        .send()
+        .await?;
+
+    if response.status() != StatusCode::UNPROCESSABLE_ENTITY {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+
+    // Each field of the wrong type is reported, in order
+    let response = client
+        .patch(format!("{}/data/notes/patch", BACKEND_URL.as_str()))
+        .json(&json!({ "set": { "title": 5, "pub_date": "today" }, "at": [[["title", "=", "Test"], ""]] }))
+        .send()
+        .await?;
+
+    if response.status() != StatusCode::UNPROCESSABLE_ENTITY {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+
+    let errors = response.json::<Value>().await?;
+    if errors[0]["field"] != "pub_date" || errors[1]["field"] != "title" {
+        return Err(anyhow!("Unexpected errors: {}", errors));
+    }
+
+    client
+        .get(format!("{}/auth/logout", BACKEND_URL.as_str()))
+        .send()
+        .await?;
+
+    return Ok(());
+}
+
+/// Checks that equivalent answers are accepted and others refused,
+/// including answers nested too deeply to parse.
+#[tokio::test]
+async fn math_check() -> Result<()> {
+    let client = Client::new();
+
+    let nested = format!("{}x{}", "(".repeat(10000), ")".repeat(10000));
+    let deep = format!("{}x{}", "(".repeat(400), ")".repeat(400));
+    for (answer, correct) in [
+        ("2x cos(x^2)", true),
+        ("cos(x^2)", false),
+        ("2x cos(", false),
+        (nested.as_str(), false),
+        (deep.as_str(), false),
+    ] {
+        let response = client
+            .post(format!("{}/data/math/check", BACKEND_URL.as_str()))
+            .json(&json!({ "answer": answer, "expected": "2*x*cos(x^2)" }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!(fmt_response(response).await));
+        }
+
+        let checked = response.json::<Value>().await?;
+        if checked["correct"] != correct {
+            return Err(anyhow!("Unexpected result for {}: {}", answer, checked));
+        }
+    }
+
+    return Ok(());
+}
+
+/// Checks that a saved link to a note shows up in its backlinks.
+#[tokio::test]
+async fn backlinks() -> Result<()> {
+    let client = Client::builder()
+        .cookie_store(true)
+        .cookie_provider(COOKIE_JAR.clone())
+        .build()?;
+
+    client
+        .post(format!("{}/auth/login", BACKEND_URL.as_str()))
+        .json(&json!([TEST_ADMIN.0, TEST_ADMIN.1]))
+        .send()
+        .await?;
+
+    for (source, linked) in [
+        ("See [the backlinked note](/notes/Backlinked%20Note).", true),
+        ("# Usage\n\nThis is almost entirely for testing purposes", false),
+    ] {
+        let response = client
+            .patch(format!("{}/data/notes/patch", BACKEND_URL.as_str()))
+            .json(&json!({ "set": { "source": source }, "at": [[["title", "=", "Test"], ""]] }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!(fmt_response(response).await));
+        }
+
+        let response = client
+            .get(format!("{}/data/notes/backlinks/backlinked note", BACKEND_URL.as_str()))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!(fmt_response(response).await));
+        }
+
+        let notes = response.json::<Value>().await?;
+        let found = notes
+            .as_array()
+            .is_some_and(|x| x.iter().any(|x| x["title"] == "Test"));
+        if found != linked {
+            return Err(anyhow!("Unexpected backlinks: {}", notes));
+        }
+    }
+
+    client
+        .get(format!("{}/auth/logout", BACKEND_URL.as_str()))
+        .send()
+        .await?;
+
+    return Ok(());
+}
+
+/// Checks that answers to a saved exercise are graded,
+/// and that the answer is not in the note's HTML.
+#[tokio::test]
+async fn exercise_check() -> Result<()> {
+    let client = Client::builder()
+        .cookie_store(true)
+        .cookie_provider(COOKIE_JAR.clone())
+        .build()?;
+
+    client
+        .post(format!("{}/auth/login", BACKEND_URL.as_str()))
+        .json(&json!([TEST_ADMIN.0, TEST_ADMIN.1]))
+        .send()
+        .await?;
+
+    let source = "@exercise(id=\"test-pi\", prompt=\"Estimate $\\pi$.\", answer=\"pi\", tolerance=0.01)";
+    let response = client
+        .patch(format!("{}/data/notes/patch", BACKEND_URL.as_str()))
+        .json(&json!({ "set": { "source": source }, "at": [[["title", "=", "Test"], ""]] }))
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+
+    let note = client
+        .get(format!("{}/data/notes/get/Test", BACKEND_URL.as_str()))
+        .send()
+        .await?
+        .json::<Value>()
+        .await?;
+    if note["html"].as_str().is_some_and(|x| x.contains("tolerance")) {
+        return Err(anyhow!("The answer was rendered: {}", note["html"]));
+    }
+    if !note["source"].is_null() {
+        return Err(anyhow!("The source was sent to readers: {}", note));
+    }
+
+    // Admins can still read the source to edit it
+    let note = client
+        .get(format!("{}/data/notes/source/Test", BACKEND_URL.as_str()))
+        .send()
+        .await?
+        .json::<Value>()
+        .await?;
+    if note["source"] != source {
+        return Err(anyhow!("Unexpected source: {}", note));
+    }
+
+    let nested = format!("{}pi{}", "(".repeat(10000), ")".repeat(10000));
+    for (answer, correct) in [
+        ("3.14", true),
+        ("22/7", true),
+        ("3", false),
+        (nested.as_str(), false),
+    ] {
+        let response = client
+            .post(format!("{}/data/exercises/test-pi/check", BACKEND_URL.as_str()))
+            .json(&json!({ "note": "Test", "answer": answer }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!(fmt_response(response).await));
+        }
+
+        let checked = response.json::<Value>().await?;
+        if checked["correct"] != correct {
+            return Err(anyhow!("Unexpected result for {}: {}", answer, checked));
+        }
+    }
+
+    // Ids are only unique within a note
+    for (note, id) in [("Test", "missing"), ("Limits", "test-pi")] {
+        let response = client
+            .post(format!("{}/data/exercises/{}/check", BACKEND_URL.as_str(), id))
+            .json(&json!({ "note": note, "answer": "1" }))
+            .send()
+            .await?;
+        if response.status() != StatusCode::NOT_FOUND {
+            return Err(anyhow!(fmt_response(response).await));
+        }
+    }
+
+    client
+        .get(format!("{}/auth/logout", BACKEND_URL.as_str()))
+        .send()
+        .await?;
+
+    return Ok(());
+}
+
+/// Checks that progress on a note is recorded for the logged in user.
+#[tokio::test]
+async fn progress() -> Result<()> {
+    let response = Client::new()
+        .get(format!("{}/data/progress", BACKEND_URL.as_str()))
+        .send()
+        .await?;
+    if response.status() != StatusCode::UNAUTHORIZED {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+
+    let client = Client::builder()
+        .cookie_store(true)
+        .cookie_provider(COOKIE_JAR.clone())
+        .build()?;
+
+    client
+        .post(format!("{}/auth/login", BACKEND_URL.as_str()))
+        .json(&json!([TEST_ADMIN.0, TEST_ADMIN.1]))
+        .send()
+        .await?;
+
+    for completed in [true, false] {
+        let response = client
+            .post(format!("{}/data/progress/test", BACKEND_URL.as_str()))
+            .json(&json!({ "scroll": 0.5, "completed": completed }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!(fmt_response(response).await));
+        }
+
+        let overview = client
+            .get(format!("{}/data/progress", BACKEND_URL.as_str()))
+            .send()
+            .await?
+            .json::<Value>()
+            .await?;
+        let found = overview["progress"]
+            .as_array()
+            .and_then(|x| x.iter().find(|x| x["note"] == "Test"))
+            .ok_or(anyhow!("No progress on Test: {}", overview))?;
+        if found["completed"] != completed || found["scroll"] != 0.5 {
+            return Err(anyhow!("Unexpected progress: {}", found));
+        }
+    }
+
+    let response = client
+        .post(format!("{}/data/progress/Not%20A%20Note", BACKEND_URL.as_str()))
+        .json(&json!({}))
+        .send()
+        .await?;
+    if response.status() != StatusCode::NOT_FOUND {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+
+    client
+        .get(format!("{}/auth/logout", BACKEND_URL.as_str()))
+        .send()
+        .await?;
+
+    return Ok(());
+}
+
+/// Checks that the definitions and theorems of a note
+/// are served as flashcards, and exported for Anki.
+#[tokio::test]
+async fn flashcards() -> Result<()> {
+    let client = Client::builder()
+        .cookie_store(true)
+        .cookie_provider(COOKIE_JAR.clone())
+        .build()?;
+
+    client
+        .post(format!("{}/auth/login", BACKEND_URL.as_str()))
+        .json(&json!([TEST_ADMIN.0, TEST_ADMIN.1]))
+        .send()
+        .await?;
+
+    let source = ":::definition Limit {#limit}\nThe value approached.\n:::";
+    let response = client
+        .patch(format!("{}/data/notes/patch", BACKEND_URL.as_str()))
+        .json(&json!({ "set": { "source": source }, "at": [[["title", "=", "Test"], ""]] }))
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+
+    let cards = client
+        .get(format!("{}/data/notes/test/cards", BACKEND_URL.as_str()))
+        .send()
+        .await?
+        .json::<Value>()
+        .await?;
+    if cards[0]["front"] != "Definition 1 (Limit)" || cards[0]["label"] != "limit" {
+        return Err(anyhow!("Unexpected cards: {}", cards));
+    }
+
+    let response = client
+        .get(format!("{}/data/notes/test/cards.tsv", BACKEND_URL.as_str()))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+    let tsv = response.text().await?;
+    if !tsv.starts_with("#separator:tab\n") || !tsv.contains("\"Definition 1 (Limit)\"\t") {
+        return Err(anyhow!("Unexpected export: {}", tsv));
+    }
+
+    let response = client
+        .get(format!("{}/data/notes/Not%20A%20Note/cards", BACKEND_URL.as_str()))
+        .send()
+        .await?;
+    if response.status() != StatusCode::NOT_FOUND {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+
+    client
+        .get(format!("{}/auth/logout", BACKEND_URL.as_str()))
+        .send()
+        .await?;
+
+    return Ok(());
+}
+
+/// Checks that notes can be created, but not twice, and deleted.
+#[tokio::test]
+async fn create() -> Result<()> {
+    let client = Client::builder()
+        .cookie_store(true)
+        .cookie_provider(COOKIE_JAR.clone())
+        .build()?;
+
+    client
+        .post(format!("{}/auth/login", BACKEND_URL.as_str()))
+        .json(&json!([TEST_ADMIN.0, TEST_ADMIN.1]))
+        .send()
+        .await?;
+
+    // A new title for every run
+    let title = format!(
+        "Created {}",
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos()
+    );
+    let note = json!({ "title": title, "author": "Test", "source": "# Created", "pub_date": 0 });
+    let response = client
+        .post(format!("{}/data/notes", BACKEND_URL.as_str()))
+        .json(&note)
+        .send()
+        .await?;
+    if response.status() != StatusCode::CREATED {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+    if response.json::<Value>().await? != note {
+        return Err(anyhow!("The created note differs from the one sent"));
+    }
+
+    let response = client
+        .post(format!("{}/data/notes", BACKEND_URL.as_str()))
+        .json(&json!({ "title": title.to_uppercase(), "author": "Test", "source": "", "pub_date": 0 }))
+        .send()
+        .await?;
+    if response.status() != StatusCode::CONFLICT {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+
+    // Deleting every note must be asked for explicitly
+    let response = client
+        .delete(format!("{}/data/notes", BACKEND_URL.as_str()))
+        .json(&json!({ "at": [] }))
+        .send()
+        .await?;
+    if response.status() != StatusCode::UNPROCESSABLE_ENTITY {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+
+    let response = client
+        .delete(format!("{}/data/notes", BACKEND_URL.as_str()))
+        .json(&json!({ "at": [[["title", "=", title], ""]] }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+    let deleted = response.json::<Value>().await?;
+    if deleted["deleted"] != 1 {
+        return Err(anyhow!("Unexpected deletion: {}", deleted));
+    }
+
+    client
+        .get(format!("{}/auth/logout", BACKEND_URL.as_str()))
+        .send()
+        .await?;
+
+    return Ok(());
+}
+
+/// Checks that notes can be filtered, sorted and paged.
+#[tokio::test]
+async fn query() -> Result<()> {
+    let client = Client::new();
+
+    let response = client
+        .post(format!("{}/data/notes/query", BACKEND_URL.as_str()))
+        .json(&json!({
+            "at": [[["title", "=", "Test"], "OR"], [["pub_date", ">=", 0], ""]],
+            "order": { "by": "title" },
+            "limit": 1
+        }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+    let notes = response.json::<Value>().await?;
+    if notes.as_array().map_or(0, |x| x.len()) != 1 {
+        return Err(anyhow!("Expected a single note: {}", notes));
+    }
+
+    // Grouped conditions, as a tree
+    let response = client
+        .post(format!("{}/data/notes/query", BACKEND_URL.as_str()))
+        .json(&json!({
+            "at": { "and": [
+                { "or": [["title", "=", "Test"], ["title", "=", "Not A Note"]] },
+                { "not": ["pub_date", "<", -1000000] }
+            ] }
+        }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+    let notes = response.json::<Value>().await?;
+    if notes[0]["title"] != "Test" {
+        return Err(anyhow!("Expected the note Test: {}", notes));
+    }
+
+    let response = client
+        .post(format!("{}/data/notes/query", BACKEND_URL.as_str()))
+        .json(&json!({
+            "at": { "and": [
+                ["title", "LIKE", "te%"],
+                ["title", "IN", ["Test", "Other"]],
+                ["pub_date", "BETWEEN", [-1000000000000i64, 1000000000000i64]],
+                ["author", "IS NOT NULL"]
+            ] }
+        }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+    let notes = response.json::<Value>().await?;
+    if notes[0]["title"] != "Test" {
+        return Err(anyhow!("Expected the note Test: {}", notes));
+    }
+
+    // Constants must have the type of their field
+    let response = client
+        .post(format!("{}/data/notes/query", BACKEND_URL.as_str()))
+        .json(&json!({ "at": ["pub_date", "IN", ["today"]] }))
+        .send()
+        .await?;
+    if response.status() != StatusCode::UNPROCESSABLE_ENTITY {
+        return Err(anyhow!(fmt_response(response).await));
+    }
+
+    // Sources hold the answers to exercises, so can't be read or filtered by,
+    // and expressions with keys besides `and`, `or` or `not` are refused
+    for selection in [
+        json!({ "order": { "by": "html" } }),
+        json!({ "order": { "by": "source" } }),
+        json!({ "at": ["source", "LIKE", "%answer=%"] }),
+        json!({ "at": { "and": [["author", "=", "X"]], "not": ["title", "=", "Test"] } }),
+    ] {
+        let response = client
+            .post(format!("{}/data/notes/query", BACKEND_URL.as_str()))
+            .json(&selection)
+            .send()
+            .await?;
+        if response.status() != StatusCode::UNPROCESSABLE_ENTITY {
+            return Err(anyhow!(fmt_response(response).await));
+        }
+    }
+
+    return Ok(());
+}
+
+/// Checks that notes are listed a page at a time.
+#[tokio::test]
+async fn listing() -> Result<()> {
+    let client = Client::new();
+
+    let page = client
+        .get(format!(
+            "{}/data/notes/get?limit=1&fields=title&order_by=pub_date&descending=true",
+            BACKEND_URL.as_str()
+        ))
+        .send()
+        .await?
+        .json::<Value>()
+        .await?;
+    let fields = page["notes"][0].as_object().map(|x| x.keys().collect::<Vec<_>>());
+    if fields != Some(vec![&"title".to_string()]) || page["total"].as_i64().unwrap_or(0) < 1 {
+        return Err(anyhow!("Unexpected page: {}", page));
+    }
+
+    // The next page starts after the last note of this one
+    if let Some(cursor) = page["next"].as_str() {
+        let next = client
+            .get(format!(
+                "{}/data/notes/get?limit=1&fields=title&order_by=pub_date&descending=true&cursor={}",
+                BACKEND_URL.as_str(),
+                cursor
+            ))
+            .send()
+            .await?
+            .json::<Value>()
+            .await?;
+        if next["notes"][0]["title"] == page["notes"][0]["title"] {
+            return Err(anyhow!("The page was repeated: {}", next));
+        }
+    }
+
+    for query in [
+        "order_by=html",
+        "order_by=source",
+        "fields=title,html",
+        "fields=title,source",
+        "offset=1&cursor=00",
+    ] {
+        let response = client
+            .get(format!("{}/data/notes/get?{}", BACKEND_URL.as_str(), query))
+            .send()
+            .await?;
+        if response.status() != StatusCode::UNPROCESSABLE_ENTITY {
+            return Err(anyhow!(fmt_response(response).await));
+        }
+    }
+
+    return Ok(());
+}
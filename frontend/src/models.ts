@@ -7,4 +7,32 @@ class Note {
     author: string;
     source: string;
     pub_date: number;
+    html: string;
+    outline: Heading[];
+}
+
+class Heading {
+    level: number;
+    title: string;
+    slug: string;
+    children: Heading[];
+}
+
+/// A section of a note scheduled for review
+class Card {
+    note: string;
+    section: string;
+    title: string;
+    ease: number;
+    interval: number;
+    repetitions: number;
+    due: number;
+    reviewed_at: number;
+}
+
+class Diagnostic {
+    message: string;
+    line: number;
+    column: number;
+    severity: "error" | "warning";
 }
\ No newline at end of file
@@ -12,7 +12,7 @@ export const load = (async ({ fetch }) : Promise<Values<Note>> => {
             }
         ),
         await fetch (
-            PUBLIC_BACKEND_URL + "/data/notes/get", 
+            PUBLIC_BACKEND_URL + "/data/notes/get?fields=title,author,pub_date&order_by=title", 
             {
                 method: "GET",
                 mode: "cors",
@@ -21,7 +21,7 @@ export const load = (async ({ fetch }) : Promise<Values<Note>> => {
         )
     ])
 
-    let [role, notes] = await Promise.all([
+    let [role, page] = await Promise.all([
         res.json(),
         data.json(),
     ]);
@@ -30,6 +30,6 @@ export const load = (async ({ fetch }) : Promise<Values<Note>> => {
         throw redirect(307, "/admin/account/login")
     }
 
-    return { "values": notes };
+    return { "values": page.notes };
 });
 
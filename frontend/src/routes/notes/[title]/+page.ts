@@ -1,14 +1,15 @@
 import type { PageLoad } from './$types';
 import { PUBLIC_BACKEND_URL} from '$env/static/public'
 
-export const load = (async ({ fetch, params }) : Promise<Note> => {
-    const response = await fetch(
-        PUBLIC_BACKEND_URL + "/data/notes/get/" + params.title,
-        {
-            method: "GET",
-            mode: "cors",
-            credentials: "include",
-        }
-    );
-    return await response.json();
+export const load = (async ({ fetch, params }) : Promise<Note & { backlinks: Note[] }> => {
+    const options: RequestInit = {
+        method: "GET",
+        mode: "cors",
+        credentials: "include",
+    };
+    const [note, backlinks] = await Promise.all([
+        fetch(PUBLIC_BACKEND_URL + "/data/notes/get/" + params.title, options),
+        fetch(PUBLIC_BACKEND_URL + "/data/notes/backlinks/" + params.title, options),
+    ]);
+    return { ...await note.json(), backlinks: await backlinks.json() };
 }) satisfies PageLoad;
@@ -0,0 +1,47 @@
+import { PUBLIC_BACKEND_URL } from '$env/static/public'
+
+/// Adds an answer box to every exercise in the rendered note
+/// titled `note`, which the backend grades against the hidden answer.
+/// Exercises included from other notes are graded as those notes'.
+export function exercises(node: HTMLElement, note: string) {
+    for (const section of node.querySelectorAll<HTMLElement>("section.exercise")) {
+        const id = section.id.replace(/^exercise-/, "");
+        const from = section.dataset.note ?? note;
+        const form = document.createElement("form");
+        form.className = "answer";
+        const input = document.createElement("input");
+        input.placeholder = "Your answer";
+        const button = document.createElement("button");
+        button.textContent = "Check";
+        const result = document.createElement("span");
+        form.append(input, button, result);
+
+        form.addEventListener("submit", async (event) => {
+            event.preventDefault();
+            const res = await fetch(
+                PUBLIC_BACKEND_URL + "/data/exercises/" + encodeURIComponent(id) + "/check",
+                {
+                    method: "POST",
+                    mode: "cors",
+                    credentials: "include",
+                    headers: {
+                        "Content-Type": "application/json",
+                    },
+                    body: JSON.stringify({ note: from, answer: input.value }),
+                }
+            );
+            if (!res.ok) {
+                result.className = "wrong";
+                result.textContent = "Could not check the answer";
+                return;
+            }
+
+            const checked: { correct: boolean, error: string | null } = await res.json();
+            result.className = checked.correct ? "correct" : "wrong";
+            result.textContent = checked.error ?? (checked.correct ? "Correct" : "Not quite");
+        });
+
+        // After the prompt, before any hints
+        section.insertBefore(form, section.querySelector("details"));
+    }
+}
@@ -0,0 +1,59 @@
+import { PUBLIC_BACKEND_URL } from '$env/static/public'
+
+export type Progress = {
+    note: string,
+    section: string,
+    scroll: number,
+    completed: boolean,
+    opened_at: number,
+    updated_at: number,
+    completed_at: number,
+};
+
+/// Records progress on a note for the logged in user. Resolves
+/// to `undefined` when nobody is logged in.
+export async function read(
+    title: string,
+    progress: { section?: string, scroll?: number, completed?: boolean } = {},
+): Promise<Progress | undefined> {
+    const res = await fetch(
+        PUBLIC_BACKEND_URL + "/data/progress/" + encodeURIComponent(title),
+        {
+            method: "POST",
+            mode: "cors",
+            credentials: "include",
+            headers: {
+                "Content-Type": "application/json",
+            },
+            body: JSON.stringify(progress),
+        }
+    );
+    return res.ok ? await res.json() : undefined;
+}
+
+/// Reports how far the page has been scrolled, and the last heading
+/// scrolled past, at most once every few seconds.
+export function tracking(node: HTMLElement, title: string) {
+    let timer: ReturnType<typeof setTimeout> | undefined;
+
+    function report() {
+        timer = undefined;
+        const bottom = window.scrollY + window.innerHeight;
+        const scroll = Math.min(1, Math.max(0, (bottom - node.offsetTop) / node.offsetHeight));
+        const passed = [...node.querySelectorAll<HTMLElement>("h1[id], h2[id], h3[id], h4[id]")]
+            .filter((x) => x.getBoundingClientRect().top < window.innerHeight / 2);
+        read(title, { scroll, section: passed.at(-1)?.id });
+    }
+
+    function scrolled() {
+        timer ??= setTimeout(report, 3000);
+    }
+
+    window.addEventListener("scroll", scrolled, { passive: true });
+    return {
+        destroy() {
+            clearTimeout(timer);
+            window.removeEventListener("scroll", scrolled);
+        },
+    };
+}